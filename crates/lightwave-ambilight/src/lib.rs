@@ -1,7 +1,7 @@
 //! Screen-capture client for the ambilight preset: grabs frames via the
 //! desktop portal, reduces each one to per-box average colors, and
 //! streams the boxes over UDP in the preset's packet format (packed
-//! little-endian f32 RGB triplets, 0..=1).
+//! little-endian f32 RGB triplets, 0..=1, optionally framed).
 
 #[cfg(not(target_os = "linux"))]
 compile_error!(
@@ -12,11 +12,16 @@ compile_error!(
 mod capture;
 mod sampler;
 
-use std::net::UdpSocket;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, bail};
+use lightwave_core::config::Config as FileConfig;
+use lightwave_core::net::{Destination, SendOptions, Sender, StreamKind};
+use lightwave_core::stream::{
+    self, Control, Event, Inbox, Meter, Monitor, Reachability, Signal, Signals, Stats, StopReason,
+};
+use lightwave_core::tuning::{Param, Tuning};
 use tokio::time::MissedTickBehavior;
 
 use capture::{Capture, CaptureOptions};
@@ -41,12 +46,25 @@ pub struct Config {
     pub min_saturation: f32,
     /// Send boxes in reverse order (strip runs against screen direction).
     pub reverse: bool,
+    /// Calibration profile from the config file applied to every
+    /// outgoing box; None = the file's default. Re-read on SIGHUP.
+    pub profile: Option<String>,
     /// UDP packets per second; also caps the negotiated capture rate.
     pub fps: u32,
     /// Ignore the saved portal permission and show the picker again.
     pub reselect: bool,
     /// UDP target, e.g. "192.168.1.20:5556".
     pub target: String,
+    /// Output protocol, packet format and payload encoding.
+    pub output: SendOptions,
+    /// How often to report [`Event::Stats`]; None = never.
+    pub stats_interval: Option<Duration>,
+    /// Refusals in a row before reporting [`Event::Unreachable`].
+    pub unreachable_after: u64,
+    /// End the stream with [`Unreachable`] instead of just reporting it.
+    ///
+    /// [`Unreachable`]: lightwave_core::stream::Unreachable
+    pub fail_on_unreachable: bool,
 }
 
 impl Config {
@@ -81,6 +99,14 @@ impl Config {
             bail!("fps must be at least 1");
         }
 
+        if self.stats_interval == Some(Duration::ZERO) {
+            bail!("stats interval must be positive");
+        }
+
+        if self.unreachable_after == 0 {
+            bail!("unreachable-after must be at least 1");
+        }
+
         Ok(())
     }
 }
@@ -93,10 +119,30 @@ impl Config {
 /// colors are simply repeated until something changes.
 pub struct Streamer {
     capture: Capture,
-    colors: Arc<Mutex<Option<Vec<[f32; 3]>>>>,
-    socket: UdpSocket,
-    packet: Vec<u8>,
+    sampler: Arc<Mutex<Sampler>>,
+    profile: Option<String>,
+    latest: Arc<Mutex<Latest>>,
+    sender: Sender,
+    /// Flattened RGB channels of the latest boxes.
+    values: Vec<f32>,
     period: Duration,
+    meter: Meter,
+    stats_interval: Option<Duration>,
+    reachability: Reachability,
+    control: Control,
+    inbox: Inbox,
+    tuning: Tuning,
+    monitor: Monitor,
+    /// Tuning version last applied to the sampler.
+    tuned: u64,
+}
+
+/// Shared between the capture callback and the send loop.
+#[derive(Default)]
+struct Latest {
+    colors: Option<Vec<[f32; 3]>>,
+    captured_at: Option<Instant>,
+    frames: u64,
 }
 
 impl Streamer {
@@ -111,31 +157,57 @@ impl Streamer {
             config.gamma,
             config.min_saturation,
             config.reverse,
-        );
+        )
+        .calibrated(FileConfig::load()?.profile(config.profile.as_deref())?);
+        let sampler = Arc::new(Mutex::new(sampler));
 
-        let colors = Arc::new(Mutex::new(None));
-        let slot = Arc::clone(&colors);
+        let latest = Arc::new(Mutex::new(Latest::default()));
+        let slot = Arc::clone(&latest);
+        let shared = Arc::clone(&sampler);
         let capture = Capture::open(
             &CaptureOptions {
                 max_fps: config.fps,
                 reselect: config.reselect,
             },
             move |frame| {
-                let boxes = sampler.sample(&frame);
+                let boxes = shared.lock().unwrap().sample(&frame);
+                let mut latest = slot.lock().unwrap();
+                latest.frames += 1;
                 if !boxes.is_empty() {
-                    *slot.lock().unwrap() = Some(boxes);
+                    latest.colors = Some(boxes);
+                    latest.captured_at = Some(Instant::now());
                 }
             },
         )?;
 
-        let socket = lightwave_core::net::connect_udp(&config.target)?;
+        let sender = Sender::connect(&config.target, StreamKind::Ambilight, &config.output)?;
+        let period = Duration::from_secs(1) / config.fps;
+        let (control, inbox) = stream::channel();
 
         Ok(Self {
             capture,
-            colors,
-            socket,
-            packet: Vec::with_capacity(config.boxes * 12),
-            period: Duration::from_secs(1) / config.fps,
+            sampler,
+            profile: config.profile.clone(),
+            latest,
+            sender,
+            values: Vec::with_capacity(config.boxes * 3),
+            period,
+            meter: Meter::new(period),
+            stats_interval: config.stats_interval,
+            reachability: Reachability::new(
+                &config.target,
+                config.unreachable_after,
+                config.fail_on_unreachable,
+            ),
+            control,
+            inbox,
+            tuning: Tuning::new(vec![
+                Param::new("vividness", config.vividness, 0.0..=10.0, 0.1),
+                Param::new("gamma", config.gamma, 0.1..=5.0, 0.1),
+                Param::new("min_saturation", config.min_saturation, 0.0..=1.0, 0.05),
+            ]),
+            monitor: Monitor::default(),
+            tuned: 0,
         })
     }
 
@@ -144,8 +216,39 @@ impl Streamer {
         self.capture.size()
     }
 
-    /// Stream packets until Ctrl+C.
-    pub fn run(mut self) -> Result<()> {
+    /// Whether packets go to a unicast, broadcast or multicast address.
+    pub fn destination(&self) -> Destination {
+        self.sender.destination()
+    }
+
+    /// A handle for pausing, resuming or stopping the stream from
+    /// another thread, and for reporting events through it.
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// A handle for adjusting `vividness`, `gamma` and `min_saturation`
+    /// while streaming.
+    pub fn tuning(&self) -> Tuning {
+        self.tuning.clone()
+    }
+
+    /// A handle on the values sent last, for live displays.
+    pub fn monitor(&self) -> Monitor {
+        self.monitor.clone()
+    }
+
+    /// Health counters so far; see [`Stats`].
+    pub fn stats(&mut self) -> Stats {
+        let frames = self.latest.lock().unwrap().frames;
+        self.meter
+            .report(Instant::now(), frames, self.sender.stats())
+    }
+
+    /// Stream packets until Ctrl+C, SIGTERM or a [`Control::stop`],
+    /// passing events to `on_event`; an error from it ends the stream.
+    /// SIGHUP re-reads the config file.
+    pub fn run(mut self, mut on_event: impl FnMut(Event) -> Result<()>) -> Result<StopReason> {
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
@@ -155,37 +258,87 @@ impl Streamer {
             let mut ticker = tokio::time::interval(self.period);
             ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-            let ctrl_c = tokio::signal::ctrl_c();
-            tokio::pin!(ctrl_c);
+            let mut stats_ticker = self
+                .stats_interval
+                .map(|every| tokio::time::interval_at(tokio::time::Instant::now() + every, every));
+
+            let mut signals = Signals::new()?;
 
             loop {
                 tokio::select! {
-                    result = &mut ctrl_c => {
-                        return result.context("waiting for Ctrl+C");
+                    signal = signals.recv() => match signal {
+                        Signal::Stop(reason) => return Ok(reason),
+                        Signal::Reload => on_event(match self.reload() {
+                            Ok(()) => Event::Reloaded,
+                            Err(err) => Event::ReloadFailed {
+                                error: format!("{err:#}"),
+                            },
+                        })?,
+                    },
+                    at = ticker.tick() => {
+                        self.meter.tick(at.into_std());
+
+                        if let Some(reason) = self.inbox.drain(&mut on_event)? {
+                            return Ok(reason);
+                        }
+
+                        if self.inbox.paused() {
+                            continue;
+                        }
+
+                        self.send_frame();
+
+                        if let Some(event) = self.reachability.check(self.sender.stats()) {
+                            on_event(event)?;
+                            self.reachability.enforce(self.sender.stats())?;
+                        }
+                    }
+                    _ = async { stats_ticker.as_mut().unwrap().tick().await },
+                        if stats_ticker.is_some() =>
+                    {
+                        on_event(Event::Stats(self.stats()))?;
                     }
-                    _ = ticker.tick() => self.send_frame()?,
                 }
             }
         })
     }
 
-    fn send_frame(&mut self) -> Result<()> {
-        self.packet.clear();
+    fn reload(&mut self) -> Result<()> {
+        let profile = FileConfig::load()?.profile(self.profile.as_deref())?;
+        self.sampler.lock().unwrap().set_calibration(profile);
+        Ok(())
+    }
 
-        {
-            let colors = self.colors.lock().unwrap();
+    fn send_frame(&mut self) {
+        let version = self.tuning.version();
+        if version != self.tuned {
+            let value = |name| self.tuning.get(name).unwrap_or_default();
+            self.sampler.lock().unwrap().tune(
+                value("vividness"),
+                value("gamma"),
+                value("min_saturation"),
+            );
+            self.tuned = version;
+        }
+
+        self.values.clear();
+
+        let captured_at = {
+            let latest = self.latest.lock().unwrap();
             // Nothing to show until the first frame arrives.
-            let Some(colors) = colors.as_ref() else {
-                return Ok(());
+            let Some(colors) = latest.colors.as_ref() else {
+                return;
             };
 
             for color in colors {
-                for channel in color {
-                    self.packet.extend_from_slice(&channel.to_le_bytes());
-                }
+                self.values.extend_from_slice(color);
             }
-        }
 
-        lightwave_core::net::send_packet(&self.socket, &self.packet)
+            latest.captured_at
+        };
+
+        self.sender.send(&self.values);
+        self.monitor.record(&self.values);
+        self.meter.sent(Instant::now(), captured_at);
     }
 }
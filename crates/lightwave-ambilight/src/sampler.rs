@@ -1,5 +1,8 @@
 use std::str::FromStr;
 
+use lightwave_core::calibration::Profile;
+use lightwave_core::color::{linear_to_srgb, srgb_to_linear};
+
 use crate::capture::Frame;
 
 /// Pixels are sampled every this many rows/columns; box colors are heavy
@@ -60,6 +63,7 @@ pub struct Sampler {
     gamma: f32,
     min_saturation: f32,
     reverse: bool,
+    calibration: Profile,
     /// sRGB byte -> linear-light value.
     to_linear: [f32; 256],
 }
@@ -87,10 +91,29 @@ impl Sampler {
             gamma,
             min_saturation,
             reverse,
+            calibration: Profile::default(),
             to_linear,
         }
     }
 
+    /// Correct every box for the strip's calibration profile.
+    pub fn calibrated(mut self, profile: Profile) -> Self {
+        self.calibration = profile;
+        self
+    }
+
+    /// Swap the calibration profile, e.g. after the config file changed.
+    pub fn set_calibration(&mut self, profile: Profile) {
+        self.calibration = profile;
+    }
+
+    /// Change the color tuning between frames.
+    pub fn tune(&mut self, vividness: f32, gamma: f32, min_saturation: f32) {
+        self.vividness = vividness;
+        self.gamma = gamma;
+        self.min_saturation = min_saturation;
+    }
+
     /// Average the frame's edge band into per-box colors (sRGB, 0..=1).
     /// Returns an empty vec if the frame doesn't match its own geometry.
     pub fn sample(&self, frame: &Frame<'_>) -> Vec<[f32; 3]> {
@@ -160,7 +183,8 @@ impl Sampler {
     }
 
     /// Final per-box adjustments, in sRGB space: lift saturation to the
-    /// configured floor, then gamma-correct brightness for the strip.
+    /// configured floor, gamma-correct brightness for the strip, then
+    /// apply the strip's calibration profile (in linear light).
     fn finish(&self, [r, g, b]: [f32; 3]) -> [f32; 3] {
         let mut color = [r, g, b];
         let v = r.max(g).max(b);
@@ -189,24 +213,7 @@ impl Sampler {
             }
         }
 
-        color
-    }
-}
-
-fn srgb_to_linear(c: f32) -> f32 {
-    if c <= 0.04045 {
-        c / 12.92
-    } else {
-        ((c + 0.055) / 1.055).powf(2.4)
-    }
-}
-
-fn linear_to_srgb(c: f32) -> f32 {
-    let c = c.clamp(0.0, 1.0);
-    if c <= 0.003_130_8 {
-        12.92 * c
-    } else {
-        1.055 * c.powf(1.0 / 2.4) - 0.055
+        self.calibration.apply_srgb(color)
     }
 }
 
@@ -404,6 +411,22 @@ mod tests {
         assert_close(red_box[1], 0.0);
     }
 
+    #[test]
+    fn calibration_applies_to_every_box() {
+        let data = rgbx(16, 16, |_, _| [255, 255, 255]);
+        let sampler =
+            Sampler::new(2, Edge::Bottom, 1.0, 0.0, 2.2, 0.0, false).calibrated(Profile {
+                white_point: [1.0, 0.8, 0.9],
+                ..Profile::default()
+            });
+
+        for color in sampler.sample(&frame(16, 16, &data)) {
+            assert_close(color[0], 1.0);
+            assert_close(color[1], linear_to_srgb(0.8));
+            assert_close(color[2], linear_to_srgb(0.9));
+        }
+    }
+
     #[test]
     fn malformed_frame_yields_nothing() {
         let data = rgbx(8, 4, |_, _| [255; 3]);
@@ -4,6 +4,7 @@ use anyhow::{Context, Result, anyhow, bail};
 use reqwest::{
     StatusCode, Url,
     blocking::{Client as HttpClient, Response},
+    header::{ETAG, IF_NONE_MATCH},
 };
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::Value;
@@ -32,6 +33,9 @@ pub struct ArgSchema {
     pub arg_type: String,
     pub default: Value,
     pub description: String,
+    /// Allowed values, when the server lists them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<Value>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -48,6 +52,27 @@ pub struct StartRequest<'a> {
     pub args: &'a Value,
 }
 
+/// The answer to a GET sent with the ETag of a copy already on hand.
+#[derive(Debug)]
+pub enum Revalidated<T> {
+    /// 304: the copy is current.
+    Unchanged,
+    Changed {
+        value: T,
+        etag: Option<String>,
+    },
+}
+
+/// Whether `err` came from not reaching the server at all (refused,
+/// unresolvable, timed out), as opposed to an error response.
+pub fn is_unreachable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|err| err.is_connect() || err.is_timeout())
+    })
+}
+
 #[derive(Clone)]
 pub struct Client {
     base: Url,
@@ -56,6 +81,11 @@ pub struct Client {
 
 impl Client {
     pub fn new(base: impl AsRef<str>) -> Result<Self> {
+        Self::with_timeout(base, Duration::from_secs(10))
+    }
+
+    /// Like [`Client::new`], giving up on each request after `timeout`.
+    pub fn with_timeout(base: impl AsRef<str>, timeout: Duration) -> Result<Self> {
         let input = base.as_ref().trim();
 
         if input.is_empty() {
@@ -74,7 +104,7 @@ impl Client {
         base.set_fragment(None);
 
         let http = HttpClient::builder()
-            .timeout(Duration::from_secs(10))
+            .timeout(timeout)
             .build()
             .context("building HTTP client")?;
 
@@ -131,6 +161,40 @@ impl Client {
             .with_context(|| format!("decoding response from {endpoint}"))
     }
 
+    fn get_revalidated<T>(
+        &self,
+        endpoint: &str,
+        url: Url,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let mut request = self.http.get(url.clone());
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = request.send().with_context(|| format!("GET {url}"))?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Revalidated::Unchanged);
+        }
+
+        let response = Self::ensure_success(response, endpoint)?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let value = response
+            .json()
+            .with_context(|| format!("decoding response from {endpoint}"))?;
+
+        Ok(Revalidated::Changed { value, etag })
+    }
+
     fn post_json<T>(&self, endpoint: &str, url: Url, body: &T) -> Result<()>
     where
         T: Serialize + ?Sized,
@@ -157,6 +221,11 @@ impl Client {
         Ok(())
     }
 
+    /// The server URL as given, without query or fragment.
+    pub fn base(&self) -> &str {
+        self.base.as_str()
+    }
+
     /// Host portion of the server URL, for protocols that bypass HTTP (e.g. UDP).
     pub fn host(&self) -> &str {
         self.base.host_str().unwrap_or("localhost")
@@ -171,6 +240,24 @@ impl Client {
         self.get_json(&endpoint, self.url(&["presets", name])?)
     }
 
+    /// [`Client::list_presets`], skipping the body if `etag` still matches.
+    pub fn list_presets_if_changed(
+        &self,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<PresetsListResponse>> {
+        self.get_revalidated("/presets", self.url(&["presets"])?, etag)
+    }
+
+    /// [`Client::preset_info`], skipping the body if `etag` still matches.
+    pub fn preset_info_if_changed(
+        &self,
+        name: &str,
+        etag: Option<&str>,
+    ) -> Result<Revalidated<PresetInfo>> {
+        let endpoint = format!("/presets/{name}");
+        self.get_revalidated(&endpoint, self.url(&["presets", name])?, etag)
+    }
+
     pub fn running(&self) -> Result<Option<RunningPreset>> {
         let url = self.url(&["presets", "running"])?;
 
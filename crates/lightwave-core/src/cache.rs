@@ -0,0 +1,359 @@
+//! Preset lists and schemas kept on disk (`$XDG_CACHE_HOME/lightwave`, or
+//! `LIGHTWAVE_CACHE`), one file per server. Entries younger than the max
+//! age are used without asking the server; older ones are revalidated
+//! with their ETag, and used as they are while the server is down.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, process};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{Client, PresetInfo, PresetsListResponse, Revalidated, is_unreachable};
+
+/// How long an entry is used before asking the server again.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// Reads through to the server. Failing to read or write the cache file
+/// is never an error; it just means asking the server again.
+pub struct Cache {
+    client: Client,
+    path: Option<PathBuf>,
+    max_age: Duration,
+}
+
+/// A response, and whether it's a copy the server couldn't confirm.
+#[derive(Debug)]
+pub struct Cached<T> {
+    pub value: T,
+    /// Set when the server was unreachable: when this copy was fetched.
+    pub offline: Option<SystemTime>,
+}
+
+impl<T> Cached<T> {
+    fn live(value: T) -> Self {
+        Self {
+            value,
+            offline: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct Entries {
+    presets: Option<Entry<PresetsListResponse>>,
+    info: BTreeMap<String, Entry<PresetInfo>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry<T> {
+    /// Seconds since the Unix epoch.
+    fetched: u64,
+    #[serde(default)]
+    etag: Option<String>,
+    value: T,
+}
+
+impl<T> Entry<T> {
+    fn new(value: T, etag: Option<String>) -> Self {
+        Self {
+            fetched: now(),
+            etag,
+            value,
+        }
+    }
+
+    fn fresh(&self, max_age: Duration) -> bool {
+        now().saturating_sub(self.fetched) < max_age.as_secs()
+    }
+
+    fn fetched_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.fetched)
+    }
+}
+
+impl Cache {
+    /// Where cache files live; `None` if neither `LIGHTWAVE_CACHE`,
+    /// `XDG_CACHE_HOME` nor `HOME` is set.
+    pub fn dir() -> Option<PathBuf> {
+        env::var_os("LIGHTWAVE_CACHE")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("XDG_CACHE_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+                    .map(|base| base.join("lightwave"))
+            })
+    }
+
+    pub fn new(client: Client, max_age: Duration) -> Self {
+        let path = Self::dir().map(|dir| dir.join(file_name(client.base())));
+
+        Self {
+            client,
+            path,
+            max_age,
+        }
+    }
+
+    /// Ask the server every time and leave the cache file alone.
+    pub fn disabled(client: Client) -> Self {
+        Self {
+            client,
+            path: None,
+            max_age: Duration::ZERO,
+        }
+    }
+
+    /// Delete every server's cached responses; returns how many servers
+    /// had some. Only files the cache writes go: the directory may be
+    /// shared with anything.
+    pub fn clear() -> Result<usize> {
+        let Some(dir) = Self::dir() else {
+            return Ok(0);
+        };
+
+        let listing = match fs::read_dir(&dir) {
+            Ok(listing) => listing,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err).with_context(|| format!("reading {}", dir.display())),
+        };
+
+        let mut servers = 0;
+        for entry in listing {
+            let path = entry
+                .with_context(|| format!("reading {}", dir.display()))?
+                .path();
+            let entry = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(written_entry);
+            let Some(entry) = entry.filter(|_| path.is_file()) else {
+                continue;
+            };
+
+            fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+            if entry {
+                servers += 1;
+            }
+        }
+
+        Ok(servers)
+    }
+
+    pub fn presets(&self) -> Result<Cached<PresetsListResponse>> {
+        let mut entries = self.load();
+
+        let (presets, changed) = self.resolve(&mut entries.presets, |etag| {
+            self.client.list_presets_if_changed(etag)
+        })?;
+
+        if changed {
+            self.store(&entries);
+        }
+
+        Ok(presets)
+    }
+
+    pub fn preset_info(&self, name: &str) -> Result<Cached<PresetInfo>> {
+        let mut entries = self.load();
+        let mut entry = entries.info.remove(name);
+
+        let (info, changed) = self.resolve(&mut entry, |etag| {
+            self.client.preset_info_if_changed(name, etag)
+        })?;
+
+        if changed {
+            entries
+                .info
+                .extend(entry.map(|entry| (name.to_string(), entry)));
+            self.store(&entries);
+        }
+
+        Ok(info)
+    }
+
+    /// Use `entry` while it's fresh, otherwise `fetch` (passing its ETag)
+    /// and update it. Also says whether `entry` changed.
+    fn resolve<T: Clone>(
+        &self,
+        entry: &mut Option<Entry<T>>,
+        fetch: impl FnOnce(Option<&str>) -> Result<Revalidated<T>>,
+    ) -> Result<(Cached<T>, bool)> {
+        if let Some(entry) = entry
+            && entry.fresh(self.max_age)
+        {
+            return Ok((Cached::live(entry.value.clone()), false));
+        }
+
+        match fetch(entry.as_ref().and_then(|entry| entry.etag.as_deref())) {
+            Ok(Revalidated::Changed { value, etag }) => {
+                *entry = Some(Entry::new(value.clone(), etag));
+                Ok((Cached::live(value), true))
+            }
+            Ok(Revalidated::Unchanged) => {
+                let Some(entry) = entry else {
+                    bail!("server answered 304 Not Modified without an ETag to match");
+                };
+                entry.fetched = now();
+                Ok((Cached::live(entry.value.clone()), true))
+            }
+            Err(err) => match entry {
+                Some(entry) if is_unreachable(&err) => Ok((
+                    Cached {
+                        value: entry.value.clone(),
+                        offline: Some(entry.fetched_at()),
+                    },
+                    false,
+                )),
+                _ => Err(err),
+            },
+        }
+    }
+
+    fn load(&self) -> Entries {
+        self.path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn store(&self, entries: &Entries) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let Ok(text) = serde_json::to_string(entries) else {
+            return;
+        };
+
+        // Two commands can race; write aside and rename so neither reads
+        // half a file.
+        let partial = path.with_extension(format!("json.{}", process::id()));
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&partial, text))
+            .and_then(|()| fs::rename(&partial, path));
+
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+    }
+}
+
+/// One file per server URL, e.g. `http___localhost_8080.json`.
+fn file_name(base: &str) -> String {
+    let key = base
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    format!("{key}.json")
+}
+
+/// Whether the cache wrote a file named `name`: Some(true) for an entry
+/// (`*.json`), Some(false) for a write left partial (`*.json.<pid>`), None
+/// for anything else.
+fn written_entry(name: &str) -> Option<bool> {
+    if name.ends_with(".json") {
+        return Some(true);
+    }
+
+    let (stem, pid) = name.rsplit_once('.')?;
+    let partial =
+        stem.ends_with(".json") && !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit());
+    partial.then_some(false)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_files_after_the_server() {
+        assert_eq!(
+            file_name("http://localhost:8080/"),
+            "http___localhost_8080.json"
+        );
+        assert_eq!(
+            file_name("https://lights.lan/api/v1"),
+            "https___lights.lan_api_v1.json"
+        );
+    }
+
+    #[test]
+    fn clears_only_its_own_files() {
+        assert_eq!(written_entry("http___localhost_8080.json"), Some(true));
+        assert_eq!(
+            written_entry("http___localhost_8080.json.4242"),
+            Some(false)
+        );
+        assert_eq!(written_entry("notes.txt"), None);
+        assert_eq!(written_entry("backup.json.bak"), None);
+        assert_eq!(written_entry("json"), None);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut entry = Entry::new((), None);
+        assert!(entry.fresh(Duration::from_secs(60)));
+        assert!(!entry.fresh(Duration::ZERO));
+
+        entry.fetched -= 120;
+        assert!(!entry.fresh(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn revalidates_stale_entries() {
+        let cache = Cache {
+            client: Client::new("http://localhost:8080").unwrap(),
+            path: None,
+            max_age: Duration::from_secs(60),
+        };
+
+        let mut entry = Some(Entry::new(1, Some("\"v1\"".to_string())));
+        let (cached, changed) = cache
+            .resolve(&mut entry, |_| panic!("fresh entries skip the server"))
+            .unwrap();
+        assert_eq!((cached.value, changed), (1, false));
+
+        entry.as_mut().unwrap().fetched -= 120;
+        let (cached, changed) = cache
+            .resolve(&mut entry, |etag| {
+                assert_eq!(etag, Some("\"v1\""));
+                Ok(Revalidated::Unchanged)
+            })
+            .unwrap();
+        assert_eq!((cached.value, changed), (1, true));
+        assert!(entry.as_ref().unwrap().fresh(cache.max_age));
+
+        entry.as_mut().unwrap().fetched -= 120;
+        let (cached, _) = cache
+            .resolve(&mut entry, |_| {
+                Ok(Revalidated::Changed {
+                    value: 2,
+                    etag: Some("\"v2\"".to_string()),
+                })
+            })
+            .unwrap();
+        assert_eq!(cached.value, 2);
+        assert_eq!(entry.unwrap().etag.as_deref(), Some("\"v2\""));
+    }
+}
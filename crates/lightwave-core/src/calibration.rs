@@ -0,0 +1,185 @@
+//! Per-strip color correction: maps the color we want to see onto the
+//! drive values a particular strip needs to show it.
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::color::{linear_to_srgb, srgb_to_linear};
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// A strip's calibration. Stages run on linear-light 0..=1 RGB, in field
+/// order: matrix, white point, per-channel gamma, then gain. Colors that
+/// are sRGB-encoded, like hex colors, go through
+/// [`apply_srgb`](Self::apply_srgb). The default profile passes colors
+/// through unchanged.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Maps requested RGB onto the strip's LED primaries; row `i` gives
+    /// output channel `i` as a mix of the input channels.
+    pub matrix: [[f32; 3]; 3],
+    /// Drive values at which the strip shows neutral white, each in (0, 1].
+    pub white_point: [f32; 3],
+    /// Per-channel response exponent; above 1 dims the channel's low end
+    /// so greys stay neutral as they darken.
+    pub gamma: [f32; 3],
+    /// Per-channel output trim applied last, e.g. to boost weak blue LEDs.
+    pub gain: [f32; 3],
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            matrix: IDENTITY,
+            white_point: [1.0; 3],
+            gamma: [1.0; 3],
+            gain: [1.0; 3],
+        }
+    }
+}
+
+impl Profile {
+    pub fn validate(&self) -> Result<()> {
+        if self.matrix.iter().flatten().any(|v| !v.is_finite()) {
+            bail!("calibration matrix must contain only finite numbers");
+        }
+
+        if self.white_point.iter().any(|&v| !(v > 0.0 && v <= 1.0)) {
+            bail!(
+                "calibration white point channels must be in (0, 1], got {:?}",
+                self.white_point
+            );
+        }
+
+        if self.gamma.iter().any(|&v| !v.is_finite() || v <= 0.0) {
+            bail!(
+                "calibration gamma must be finite positive numbers, got {:?}",
+                self.gamma
+            );
+        }
+
+        if self.gain.iter().any(|&v| !v.is_finite() || v < 0.0) {
+            bail!(
+                "calibration gain must be finite numbers >= 0, got {:?}",
+                self.gain
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether [`apply`](Self::apply) leaves every color unchanged.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Correct one sRGB-encoded color for the strip: linearized, through
+    /// [`apply`](Self::apply), and encoded again.
+    pub fn apply_srgb(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.apply(rgb.map(srgb_to_linear)).map(linear_to_srgb)
+    }
+
+    /// Correct one linear-light color for the strip, clamped to 0..=1.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let mut out = [0.0; 3];
+
+        for (i, channel) in out.iter_mut().enumerate() {
+            let mixed: f32 = self.matrix[i].iter().zip(&rgb).map(|(m, c)| m * c).sum();
+            let balanced = (mixed * self.white_point[i]).clamp(0.0, 1.0);
+
+            *channel = (balanced.powf(self.gamma[i]) * self.gain[i]).clamp(0.0, 1.0);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_profile_is_identity() {
+        let profile = Profile::default();
+
+        assert_eq!(profile.apply([0.2, 0.5, 0.9]), [0.2, 0.5, 0.9]);
+        assert_eq!(profile.apply([2.0, -1.0, 0.0]), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn white_point_scales_neutral_white() {
+        let profile = Profile {
+            white_point: [1.0, 0.8, 0.9],
+            ..Profile::default()
+        };
+
+        assert_eq!(profile.apply([1.0; 3]), [1.0, 0.8, 0.9]);
+    }
+
+    #[test]
+    fn matrix_mixes_channels() {
+        let profile = Profile {
+            // Green LEDs bleed into red: pull some red out of green.
+            matrix: [[1.0, 0.0, 0.0], [-0.1, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            ..Profile::default()
+        };
+
+        let [r, g, b] = profile.apply([1.0, 0.5, 0.0]);
+        assert_eq!(r, 1.0);
+        assert!((g - 0.4).abs() < 1e-6);
+        assert_eq!(b, 0.0);
+    }
+
+    #[test]
+    fn gamma_dims_the_low_end_and_gain_trims() {
+        let profile = Profile {
+            gamma: [1.0, 2.0, 1.0],
+            gain: [1.0, 1.0, 0.5],
+            ..Profile::default()
+        };
+
+        let [r, g, b] = profile.apply([0.5, 0.5, 0.5]);
+        assert_eq!(r, 0.5);
+        assert!((g - 0.25).abs() < 1e-6);
+        assert!((b - 0.25).abs() < 1e-6);
+        // Full drive is unaffected by gamma.
+        assert_eq!(profile.apply([1.0; 3])[1], 1.0);
+    }
+
+    #[test]
+    fn srgb_colors_are_corrected_in_linear_light() {
+        let identity = Profile::default().apply_srgb([0.2, 0.5, 0.9]);
+        for (out, expected) in identity.into_iter().zip([0.2, 0.5, 0.9]) {
+            assert!((out - expected).abs() < 1e-5, "{identity:?}");
+        }
+
+        let profile = Profile {
+            white_point: [1.0, 0.5, 1.0],
+            ..Profile::default()
+        };
+
+        // Half the light, not half the sRGB value.
+        let [r, g, b] = profile.apply_srgb([1.0; 3]);
+        assert!(r > 0.9999 && b > 0.9999, "got {r} {b}");
+        assert!((g - linear_to_srgb(0.5)).abs() < 1e-5, "got {g}");
+        assert!(g > 0.7);
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        let profile = Profile {
+            white_point: [1.0, 0.0, 1.0],
+            ..Profile::default()
+        };
+
+        assert!(profile.validate().is_err());
+        assert!(Profile::default().validate().is_ok());
+
+        let profile = Profile {
+            gamma: [1.0, 0.0, 1.0],
+            ..Profile::default()
+        };
+        assert!(profile.validate().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Daily color-temperature schedules for `lightwave white --circadian`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::color::parse_kelvin;
+
+const MINUTES_PER_DAY: f32 = 24.0 * 60.0;
+
+/// Warm mornings and evenings around a neutral working day.
+pub const DEFAULT_SCHEDULE: &str = "06:00=2700,09:00=5000,17:00=4500,20:00=2700,23:00=2200";
+
+/// Color temperatures pinned to times of day, e.g.
+/// `07:00=2700,12:00=5500,21:00=2200`. Between points the temperature
+/// moves linearly in mireds (perceptually even steps), wrapping around
+/// midnight.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Schedule {
+    /// (minute of day, kelvin), sorted by minute.
+    points: Vec<(u32, f32)>,
+}
+
+impl Schedule {
+    /// Color temperature at `minute` past midnight (fractions allowed).
+    pub fn kelvin_at(&self, minute: f32) -> f32 {
+        let minute = minute.rem_euclid(MINUTES_PER_DAY);
+
+        // The segment containing `minute` starts at the last point at or
+        // before it; before the first point, that's yesterday's last one.
+        let next = self
+            .points
+            .iter()
+            .position(|&(m, _)| m as f32 > minute)
+            .unwrap_or(0);
+        let prev = (next + self.points.len() - 1) % self.points.len();
+
+        let (m0, k0) = self.points[prev];
+        let (m1, k1) = self.points[next];
+
+        let span = (m1 as f32 - m0 as f32).rem_euclid(MINUTES_PER_DAY);
+        if span == 0.0 {
+            return k0;
+        }
+
+        let t = (minute - m0 as f32).rem_euclid(MINUTES_PER_DAY) / span;
+        let mired = 1e6 / k0 + (1e6 / k1 - 1e6 / k0) * t;
+
+        1e6 / mired
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        DEFAULT_SCHEDULE
+            .parse()
+            .expect("the default schedule is valid")
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut points = Vec::new();
+
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (time, kelvin) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("schedule entry {entry:?} must look like HH:MM=KELVIN"))?;
+
+            points.push((parse_time(time)?, parse_kelvin(kelvin)?));
+        }
+
+        if points.is_empty() {
+            bail!("schedule needs at least one HH:MM=KELVIN entry");
+        }
+
+        points.sort_by_key(|&(minute, _)| minute);
+
+        if points.windows(2).any(|w| w[0].0 == w[1].0) {
+            bail!("schedule {s:?} lists the same time twice");
+        }
+
+        Ok(Self { points })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Schedule> for String {
+    fn from(schedule: Schedule) -> Self {
+        schedule.to_string()
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(minute, kelvin)) in self.points.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{:02}:{:02}={kelvin}", minute / 60, minute % 60)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_time(time: &str) -> Result<u32> {
+    let parsed = time.trim().split_once(':').and_then(|(h, m)| {
+        let h: u32 = h.parse().ok()?;
+        let m: u32 = m.parse().ok()?;
+        (h < 24 && m < 60).then_some(h * 60 + m)
+    });
+
+    parsed
+        .ok_or_else(|| anyhow!("expected a time like 07:30"))
+        .with_context(|| format!("invalid schedule time {time:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1.0,
+            "expected ~{expected}K, got {actual}K"
+        );
+    }
+
+    #[test]
+    fn parses_and_sorts_entries() {
+        let schedule: Schedule = "20:00=2700, 07:30=5000".parse().unwrap();
+
+        assert_eq!(schedule.points, vec![(450, 5000.0), (1200, 2700.0)]);
+        assert_eq!(schedule.to_string(), "07:30=5000,20:00=2700");
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        assert!("".parse::<Schedule>().is_err());
+        assert!("07:00".parse::<Schedule>().is_err());
+        assert!("25:00=3000".parse::<Schedule>().is_err());
+        assert!("07:00=hot".parse::<Schedule>().is_err());
+        assert!("07:00=3000,07:00=4000".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn hits_points_exactly_and_interpolates_in_mireds() {
+        let schedule: Schedule = "06:00=2500,12:00=5000".parse().unwrap();
+
+        assert_close(schedule.kelvin_at(360.0), 2500.0);
+        assert_close(schedule.kelvin_at(720.0), 5000.0);
+        // Halfway in mireds between 400 and 200 is 300 mired ≈ 3333K.
+        assert_close(schedule.kelvin_at(540.0), 1e6 / 300.0);
+    }
+
+    #[test]
+    fn wraps_around_midnight() {
+        let schedule: Schedule = "06:00=2500,18:00=5000".parse().unwrap();
+
+        // Midnight is halfway through the 18:00 -> 06:00 segment.
+        assert_close(schedule.kelvin_at(0.0), 1e6 / 300.0);
+        assert_close(schedule.kelvin_at(MINUTES_PER_DAY), 1e6 / 300.0);
+    }
+
+    #[test]
+    fn single_point_is_constant() {
+        let schedule: Schedule = "12:00=4000".parse().unwrap();
+
+        assert_close(schedule.kelvin_at(0.0), 4000.0);
+        assert_close(schedule.kelvin_at(900.0), 4000.0);
+    }
+
+    #[test]
+    fn default_schedule_parses() {
+        let _ = Schedule::default();
+    }
+}
@@ -1,4 +1,8 @@
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+
+/// Color temperatures the blackbody approximation in [`kelvin_to_rgb`]
+/// is valid for.
+pub const KELVIN_RANGE: std::ops::RangeInclusive<f32> = 1667.0..=25000.0;
 
 pub fn normalize(input: &str) -> Result<String> {
     let s = input.trim();
@@ -46,6 +50,91 @@ pub fn parse_hex_rgb(normalized: &str) -> Option<[u8; 3]> {
     ])
 }
 
+/// Format 0..=1 RGB channels as `#RRGGBB`, clamping out-of-range values.
+pub fn to_hex(rgb: [f32; 3]) -> String {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{r:02X}{g:02X}{b:02X}")
+}
+
+/// The sRGB transfer function: a linear-light channel encoded for
+/// `#RRGGBB` colors, which are sRGB; clamped to 0..=1.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of [`linear_to_srgb`]: an sRGB channel (0..=1) in linear
+/// light.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Parse a color temperature such as `3200K` or `3200`.
+pub fn parse_kelvin(input: &str) -> Result<f32> {
+    let s = input.trim();
+    let digits = s.strip_suffix(['K', 'k']).unwrap_or(s);
+
+    let kelvin: f32 = digits
+        .parse()
+        .with_context(|| format!("could not parse color temperature {input:?}"))?;
+
+    if !KELVIN_RANGE.contains(&kelvin) {
+        bail!(
+            "color temperature must be between {}K and {}K, got {input:?}",
+            KELVIN_RANGE.start(),
+            KELVIN_RANGE.end()
+        );
+    }
+
+    Ok(kelvin)
+}
+
+/// Linear-light RGB of a blackbody radiator at `kelvin`, scaled so the
+/// brightest channel is 1.0; [`linear_to_srgb`] encodes it for hex.
+///
+/// The Planckian locus is approximated in CIE xy (Kim et al. 2002), then
+/// converted through XYZ to linear sRGB, so 6500K lands close to the
+/// sRGB white point (D65 sits slightly off the locus). Values outside [`KELVIN_RANGE`] are clamped to it.
+pub fn kelvin_to_rgb(kelvin: f32) -> [f32; 3] {
+    let t = f64::from(kelvin.clamp(*KELVIN_RANGE.start(), *KELVIN_RANGE.end()));
+    let (t2, t3) = (t * t, t * t * t);
+
+    let x = if t <= 4000.0 {
+        -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179_910
+    } else {
+        -3.025_846_9e9 / t3 + 2.107_037_9e6 / t2 + 0.222_634_7e3 / t + 0.240_390
+    };
+
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x3 - 1.348_110_20 * x2 + 2.185_558_32 * x - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x3 - 1.374_185_93 * x2 + 2.091_370_15 * x - 0.167_488_67
+    } else {
+        3.081_758_0 * x3 - 5.873_386_70 * x2 + 3.751_129_97 * x - 0.370_014_83
+    };
+
+    // XYZ at unit luminance, then the standard XYZ -> linear sRGB matrix.
+    let (cx, cy, cz) = (x / y, 1.0, (1.0 - x - y) / y);
+    let rgb = [
+        3.240_454_2 * cx - 1.537_138_5 * cy - 0.498_531_4 * cz,
+        -0.969_266_0 * cx + 1.876_010_8 * cy + 0.041_556_0 * cz,
+        0.055_643_4 * cx - 0.204_025_9 * cy + 1.057_225_2 * cz,
+    ]
+    .map(|c| c.max(0.0));
+
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    rgb.map(|c| (c / max) as f32)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,4 +188,66 @@ mod tests {
         assert_eq!(parse_hex_rgb("#123"), None);
         assert_eq!(parse_hex_rgb("FF00AA"), None);
     }
+
+    #[test]
+    fn formats_hex_from_channels() {
+        assert_eq!(to_hex([1.0, 0.0, 170.0 / 255.0]), "#FF00AA");
+        assert_eq!(to_hex([-0.5, 2.0, 0.5]), "#00FF80");
+    }
+
+    #[test]
+    fn parses_kelvin_with_and_without_suffix() {
+        assert_eq!(parse_kelvin("3200K").unwrap(), 3200.0);
+        assert_eq!(parse_kelvin("2700k").unwrap(), 2700.0);
+        assert_eq!(parse_kelvin(" 5000 ").unwrap(), 5000.0);
+        assert!(parse_kelvin("warm").is_err());
+        assert!(parse_kelvin("800K").is_err());
+        assert!(parse_kelvin("40000K").is_err());
+    }
+
+    #[test]
+    fn d65_temperature_is_near_white() {
+        for channel in kelvin_to_rgb(6504.0) {
+            assert!(channel > 0.9, "expected near-white, got {channel}");
+        }
+    }
+
+    #[test]
+    fn warm_temperatures_lean_red_and_cool_ones_blue() {
+        let [r, g, b] = kelvin_to_rgb(2700.0);
+        assert_eq!(r, 1.0);
+        assert!(g < r && b < g, "2700K should be orange, got {r} {g} {b}");
+
+        let [r, _, b] = kelvin_to_rgb(12000.0);
+        assert_eq!(b, 1.0);
+        assert!(r < b);
+
+        // Blue rises monotonically with temperature below the white point.
+        let blues: Vec<f32> = [2000.0, 3000.0, 4000.0, 5000.0, 6000.0]
+            .into_iter()
+            .map(|k| kelvin_to_rgb(k)[2])
+            .collect();
+        assert!(blues.windows(2).all(|w| w[0] < w[1]), "{blues:?}");
+    }
+
+    #[test]
+    fn temperatures_encode_to_srgb_hex() {
+        let hex = |kelvin| {
+            let hex = to_hex(kelvin_to_rgb(kelvin).map(linear_to_srgb));
+            parse_hex_rgb(&hex).unwrap()
+        };
+
+        for channel in hex(6500.0) {
+            assert!(channel >= 0xF8, "expected about #FFFFFF, got {channel:02X}");
+        }
+
+        // Mid values come out gamma-encoded, well above linear light.
+        let [_, g, b] = kelvin_to_rgb(3200.0);
+        let [_, hex_g, hex_b] = hex(3200.0);
+        assert_eq!(hex_g, (linear_to_srgb(g) * 255.0).round() as u8);
+        assert_eq!(hex_b, (linear_to_srgb(b) * 255.0).round() as u8);
+        assert!(f32::from(hex_g) > g * 255.0 + 20.0, "green {hex_g} vs {g}");
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,252 @@
+//! The user's config file (`$XDG_CONFIG_HOME/lightwave/config.toml`, or
+//! `LIGHTWAVE_CONFIG`): calibration profiles, the circadian schedule and
+//! the music spectrum's equalization.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::Profile;
+use crate::circadian::Schedule;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Calibration profile used when none is named on the command line.
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    pub circadian: Circadian,
+    pub music: Music,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Circadian {
+    /// Replaces the built-in schedule when set.
+    pub schedule: Option<Schedule>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Music {
+    /// Spectrum equalization measured by `lightwave music
+    /// --calibrate-pink`: `[frequency in Hz, correction in dB]` points in
+    /// rising frequency order.
+    pub eq: Vec<[f32; 2]>,
+}
+
+impl Music {
+    pub fn validate(&self) -> Result<()> {
+        for &[freq, db] in &self.eq {
+            if !freq.is_finite() || freq <= 0.0 {
+                bail!("eq frequencies must be finite positive numbers, got {freq}");
+            }
+            if !db.is_finite() {
+                bail!("eq corrections must be finite numbers of dB, got {db} at {freq} Hz");
+            }
+        }
+
+        if let Some(pair) = self.eq.windows(2).find(|pair| pair[0][0] >= pair[1][0]) {
+            bail!(
+                "eq frequencies must rise from point to point, got {} Hz then {} Hz",
+                pair[0][0],
+                pair[1][0]
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The equalization at `freq`, interpolated between points on a log
+    /// frequency axis and held flat past the ends; 0 dB without a curve.
+    pub fn eq_db(&self, freq: f32) -> f32 {
+        let (Some(&[first_freq, first_db]), Some(&[last_freq, last_db])) =
+            (self.eq.first(), self.eq.last())
+        else {
+            return 0.0;
+        };
+
+        if freq <= first_freq {
+            return first_db;
+        }
+        if freq >= last_freq {
+            return last_db;
+        }
+
+        let upper = self.eq.partition_point(|&[point, _]| point < freq);
+        let [lo_freq, lo_db] = self.eq[upper - 1];
+        let [hi_freq, hi_db] = self.eq[upper];
+        let t = (freq / lo_freq).ln() / (hi_freq / lo_freq).ln();
+
+        lo_db + (hi_db - lo_db) * t
+    }
+}
+
+impl Config {
+    /// Where the config file lives; `None` if neither `LIGHTWAVE_CONFIG`,
+    /// `XDG_CONFIG_HOME` nor `HOME` is set.
+    pub fn path() -> Option<PathBuf> {
+        env::var_os("LIGHTWAVE_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+                    .map(|base| base.join("lightwave/config.toml"))
+            })
+    }
+
+    /// Load the config file; a missing file is an empty config.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).with_context(|| format!("reading {}", path.display())),
+        };
+
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+
+        for (name, profile) in &config.profiles {
+            profile
+                .validate()
+                .with_context(|| format!("calibration profile {name:?} in {}", path.display()))?;
+        }
+
+        config
+            .music
+            .validate()
+            .with_context(|| format!("music settings in {}", path.display()))?;
+
+        Ok(config)
+    }
+
+    /// Write the config file back, creating its directory if needed.
+    /// Comments and formatting in a hand-edited file are not preserved.
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = Self::path().ok_or_else(|| {
+            anyhow!("cannot locate the config file: set LIGHTWAVE_CONFIG or HOME")
+        })?;
+
+        let text = toml::to_string_pretty(self).context("serializing config")?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+
+        fs::write(&path, text).with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// The named calibration profile, or the configured default, or the
+    /// identity profile when no default is configured.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let Some(name) = name.or(self.profile.as_deref()) else {
+            return Ok(Profile::default());
+        };
+
+        self.profiles.get(name).cloned().ok_or_else(|| {
+            let location = Self::path()
+                .map(|path| format!(" in {}", path.display()))
+                .unwrap_or_default();
+            anyhow!("no calibration profile named {name:?}{location}")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles_and_schedule() {
+        let config: Config = toml::from_str(
+            r#"
+            profile = "desk"
+
+            [profiles.desk]
+            white_point = [1.0, 0.8, 0.9]
+
+            [circadian]
+            schedule = "07:00=5000,21:00=2700"
+            "#,
+        )
+        .unwrap();
+
+        let desk = config.profile(None).unwrap();
+        assert_eq!(desk.white_point, [1.0, 0.8, 0.9]);
+        assert_eq!(desk.matrix, Profile::default().matrix);
+        assert!(config.profile(Some("shelf")).is_err());
+        assert_eq!(
+            config.circadian.schedule.unwrap().to_string(),
+            "07:00=5000,21:00=2700"
+        );
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let config = Config {
+            profile: Some("desk".to_string()),
+            profiles: BTreeMap::from([(
+                "desk".to_string(),
+                Profile {
+                    gamma: [1.0, 1.2, 1.1],
+                    ..Profile::default()
+                },
+            )]),
+            ..Config::default()
+        };
+
+        let text = toml::to_string_pretty(&config).unwrap();
+        let parsed: Config = toml::from_str(&text).unwrap();
+
+        assert_eq!(parsed.profile(None).unwrap(), config.profiles["desk"]);
+    }
+
+    #[test]
+    fn interpolates_the_eq_curve() {
+        let config: Config = toml::from_str(
+            r#"
+            [music]
+            eq = [[100.0, 6.0], [400.0, 0.0], [1600.0, -2.0]]
+            "#,
+        )
+        .unwrap();
+        let music = config.music;
+
+        assert_eq!(music.eq_db(50.0), 6.0);
+        assert_eq!(music.eq_db(100.0), 6.0);
+        assert!((music.eq_db(200.0) - 3.0).abs() < 1e-4);
+        assert!((music.eq_db(800.0) + 1.0).abs() < 1e-4);
+        assert_eq!(music.eq_db(20_000.0), -2.0);
+        assert_eq!(Music::default().eq_db(440.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_broken_eq_curves() {
+        let eq = |eq: &[[f32; 2]]| Music { eq: eq.to_vec() }.validate();
+
+        assert!(eq(&[[100.0, 6.0], [400.0, 0.0]]).is_ok());
+        assert!(eq(&[]).is_ok());
+        assert!(eq(&[[100.0, 6.0], [100.0, 0.0]]).is_err(), "duplicate");
+        assert!(eq(&[[400.0, 6.0], [100.0, 0.0]]).is_err(), "falling");
+        assert!(eq(&[[0.0, 6.0]]).is_err());
+        assert!(eq(&[[f32::NAN, 6.0]]).is_err());
+        assert!(eq(&[[100.0, f32::INFINITY]]).is_err());
+    }
+
+    #[test]
+    fn empty_config_uses_identity_profile() {
+        let config: Config = toml::from_str("").unwrap();
+
+        assert_eq!(config.profile(None).unwrap(), Profile::default());
+    }
+}
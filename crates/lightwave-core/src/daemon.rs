@@ -0,0 +1,174 @@
+//! The `lightwaved` control protocol: newline-delimited JSON over a Unix
+//! domain socket, one response object per request. Responses carry
+//! `"ok": true` plus request-specific fields, or `"ok": false` and an
+//! `"error"` message.
+
+use std::env;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Where the daemon listens: `LIGHTWAVE_SOCKET`, else
+/// `$XDG_RUNTIME_DIR/lightwave.sock`, else a per-user file in the temp
+/// directory.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = env::var_os("LIGHTWAVE_SOCKET") {
+        return PathBuf::from(path);
+    }
+
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("lightwave.sock");
+    }
+
+    let user = env::var("USER").unwrap_or_else(|_| "default".to_string());
+    env::temp_dir().join(format!("lightwave-{user}.sock"))
+}
+
+/// The streams the daemon can run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamName {
+    Music,
+    Ambilight,
+}
+
+impl FromStr for StreamName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "music" => Ok(Self::Music),
+            "ambilight" => Ok(Self::Ambilight),
+            _ => Err(format!("unknown stream {s:?}; expected music or ambilight")),
+        }
+    }
+}
+
+impl fmt::Display for StreamName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Music => "music",
+            Self::Ambilight => "ambilight",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// What's streaming, with its tuning and latest stats.
+    Status,
+    /// Start a stream, or resume a stopped one with the same options.
+    /// `options` are the stream's settings by name (`gamma`, `bins`, ...).
+    Start {
+        stream: StreamName,
+        #[serde(default)]
+        options: Map<String, Value>,
+    },
+    /// Stop streaming. Capture stays open for a quick restart unless
+    /// `release` is set.
+    Stop {
+        #[serde(default)]
+        release: bool,
+    },
+    /// Change a tuning parameter of the current stream.
+    Set { param: String, value: f32 },
+    /// Stop streaming and exit.
+    Shutdown,
+}
+
+/// Send one request to the daemon at `path` and return its response,
+/// turning `"ok": false` into an error.
+pub fn request(path: &Path, request: &Request) -> Result<Value> {
+    let mut stream = UnixStream::connect(path).with_context(|| {
+        format!(
+            "connecting to lightwaved at {} (is it running?)",
+            path.display()
+        )
+    })?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .context("sending request to lightwaved")?;
+
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .context("reading response from lightwaved")?;
+
+    let response: Value =
+        serde_json::from_str(&response).context("decoding response from lightwaved")?;
+
+    if response["ok"] != Value::Bool(true) {
+        let error = response["error"].as_str().unwrap_or("malformed response");
+        return Err(anyhow!("lightwaved: {error}"));
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn requests_use_a_cmd_tag() {
+        let request = Request::Set {
+            param: "gamma".to_string(),
+            value: 2.0,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({ "cmd": "set", "param": "gamma", "value": 2.0 })
+        );
+        assert_eq!(
+            serde_json::from_value::<Request>(json!({ "cmd": "stop" })).unwrap(),
+            Request::Stop { release: false }
+        );
+    }
+
+    #[test]
+    fn round_trips_over_a_socket() {
+        let dir = env::temp_dir().join(format!("lightwave-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("daemon.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let replies = [
+                json!({ "ok": true, "state": "idle" }),
+                json!({ "ok": false, "error": "nope" }),
+            ];
+
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                serde_json::from_str::<Request>(&line).unwrap();
+                writeln!(stream, "{reply}").unwrap();
+            }
+        });
+
+        let status = request(&path, &Request::Status).unwrap();
+        assert_eq!(status["state"], "idle");
+
+        let err = request(&path, &Request::Shutdown).unwrap_err();
+        assert!(err.to_string().contains("nope"), "{err}");
+
+        server.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,13 @@
 pub mod api;
+pub mod cache;
+pub mod calibration;
+pub mod circadian;
 pub mod color;
+pub mod config;
+#[cfg(unix)]
+pub mod daemon;
 pub mod net;
+pub mod stream;
+pub mod tuning;
 
 pub use api::{ArgSchema, Client, PresetInfo, PresetSummary, PresetsListResponse, RunningPreset};
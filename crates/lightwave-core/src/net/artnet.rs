@@ -0,0 +1,96 @@
+//! Art-Net ArtDmx packets: one DMX universe each, addressed by a 15-bit
+//! port address. Like E1.31, frames spill over into consecutive
+//! universes 170 RGB pixels (510 channels) at a time.
+
+pub const PORT: u16 = 6454;
+
+pub const HEADER_LEN: usize = 18;
+
+/// Channels filled per universe: 170 whole RGB pixels.
+pub const CHANNELS_PER_UNIVERSE: usize = 510;
+
+/// Highest 15-bit port address.
+pub const MAX_UNIVERSE: u16 = 0x7fff;
+
+const ID: [u8; 8] = *b"Art-Net\0";
+const OP_DMX: u16 = 0x5000;
+const PROTOCOL_VERSION: u16 = 14;
+
+pub struct ArtNet {
+    first_universe: u16,
+    /// 1..=255; 0 would disable the receiver's reordering check.
+    sequence: u8,
+}
+
+impl ArtNet {
+    pub fn new(first_universe: u16) -> Self {
+        Self {
+            first_universe,
+            sequence: 0,
+        }
+    }
+
+    /// Split one frame of channel bytes into per-universe packets.
+    pub fn encode(&mut self, data: &[u8], packets: &mut Vec<Vec<u8>>) {
+        self.sequence = self.sequence % 255 + 1;
+
+        let universes = data.len().div_ceil(CHANNELS_PER_UNIVERSE).max(1);
+
+        for i in 0..universes {
+            let start = (i * CHANNELS_PER_UNIVERSE).min(data.len());
+            let end = (start + CHANNELS_PER_UNIVERSE).min(data.len());
+            let universe = (self.first_universe + i as u16) & MAX_UNIVERSE;
+
+            // The DMX length must be even and at least 2.
+            let channels = &data[start..end];
+            let length = (channels.len() + channels.len() % 2).max(2);
+
+            let mut packet = Vec::with_capacity(HEADER_LEN + length);
+            packet.extend_from_slice(&ID);
+            packet.extend_from_slice(&OP_DMX.to_le_bytes());
+            packet.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+            packet.push(self.sequence);
+            packet.push(0); // physical input port
+            packet.extend_from_slice(&universe.to_le_bytes()); // SubUni, Net
+            packet.extend_from_slice(&(length as u16).to_be_bytes());
+            packet.extend_from_slice(channels);
+            packet.resize(HEADER_LEN + length, 0);
+
+            packets.push(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_layout_matches_the_spec() {
+        let mut artnet = ArtNet::new(0x0123);
+        let mut packets = Vec::new();
+        artnet.encode(&[9, 8, 7], &mut packets);
+
+        let p = &packets[0];
+        assert_eq!(p[..8], *b"Art-Net\0");
+        assert_eq!(p[8..10], [0x00, 0x50]);
+        assert_eq!(p[10..12], [0, 14]);
+        assert_eq!(p[12], 1); // sequence
+        assert_eq!(p[14], 0x23); // SubUni
+        assert_eq!(p[15], 0x01); // Net
+        assert_eq!(p[16..18], [0, 4]); // padded to even
+        assert_eq!(p[18..], [9, 8, 7, 0]);
+    }
+
+    #[test]
+    fn splits_universes_on_whole_pixels() {
+        let mut artnet = ArtNet::new(0);
+        let mut packets = Vec::new();
+        artnet.encode(&[1; 1200], &mut packets);
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].len(), HEADER_LEN + 510);
+        assert_eq!(packets[2].len(), HEADER_LEN + 180);
+        assert_eq!(packets[2][14], 2);
+    }
+}
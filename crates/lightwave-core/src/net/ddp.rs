@@ -0,0 +1,104 @@
+//! DDP (Distributed Display Protocol), as spoken by WLED and xLights:
+//! a 10-byte header and up to 1440 bytes of RGB data per packet, with
+//! the push flag on a frame's last packet telling the device to display.
+
+pub const PORT: u16 = 4048;
+
+/// Data bytes per packet (480 RGB pixels), the usual DDP maximum.
+pub const MAX_DATA: usize = 1440;
+
+pub const HEADER_LEN: usize = 10;
+
+const FLAG_VERSION_1: u8 = 0x40;
+const FLAG_PUSH: u8 = 0x01;
+/// Data type: RGB, 8 bits per channel.
+const TYPE_RGB8: u8 = 0x0B;
+/// Destination: the device's default output.
+const ID_DISPLAY: u8 = 1;
+
+#[derive(Default)]
+pub struct Ddp {
+    /// 1..=15; 0 would mean "not used".
+    sequence: u8,
+}
+
+impl Ddp {
+    /// Split one frame of RGB channel bytes into packets.
+    pub fn encode(&mut self, data: &[u8], packets: &mut Vec<Vec<u8>>) {
+        self.sequence = self.sequence % 15 + 1;
+
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![&[]]
+        } else {
+            data.chunks(MAX_DATA).collect()
+        };
+        let last = chunks.len() - 1;
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let offset = (i * MAX_DATA) as u32;
+            let mut packet = Vec::with_capacity(HEADER_LEN + chunk.len());
+
+            packet.push(FLAG_VERSION_1 | if i == last { FLAG_PUSH } else { 0 });
+            packet.push(self.sequence);
+            packet.push(TYPE_RGB8);
+            packet.push(ID_DISPLAY);
+            packet.extend_from_slice(&offset.to_be_bytes());
+            packet.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            packet.extend_from_slice(chunk);
+
+            packets.push(packet);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_frame_is_one_pushed_packet() {
+        let mut packets = Vec::new();
+        Ddp::default().encode(&[1, 2, 3, 4, 5, 6], &mut packets);
+
+        assert_eq!(packets.len(), 1);
+        let p = &packets[0];
+        assert_eq!(p[0], FLAG_VERSION_1 | FLAG_PUSH);
+        assert_eq!(p[1], 1);
+        assert_eq!(p[2], TYPE_RGB8);
+        assert_eq!(p[4..8], [0, 0, 0, 0]);
+        assert_eq!(p[8..10], [0, 6]);
+        assert_eq!(p[HEADER_LEN..], [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn large_frame_splits_with_offsets_and_pushes_last() {
+        let data = vec![7u8; MAX_DATA + 300];
+        let mut packets = Vec::new();
+        Ddp::default().encode(&data, &mut packets);
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][0], FLAG_VERSION_1);
+        assert_eq!(packets[0].len(), HEADER_LEN + MAX_DATA);
+        assert_eq!(packets[1][0], FLAG_VERSION_1 | FLAG_PUSH);
+        assert_eq!(packets[1][4..8], (MAX_DATA as u32).to_be_bytes());
+        assert_eq!(packets[1][8..10], 300u16.to_be_bytes());
+    }
+
+    #[test]
+    fn sequence_cycles_through_one_to_fifteen() {
+        let mut ddp = Ddp::default();
+        let mut packets = Vec::new();
+
+        let sequences: Vec<u8> = (0..16)
+            .map(|_| {
+                packets.clear();
+                ddp.encode(&[0; 3], &mut packets);
+                packets[0][1]
+            })
+            .collect();
+
+        assert_eq!(sequences[0], 1);
+        assert_eq!(sequences[14], 15);
+        assert_eq!(sequences[15], 1);
+    }
+}
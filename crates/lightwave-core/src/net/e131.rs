@@ -0,0 +1,213 @@
+//! E1.31 (sACN, streaming DMX over ACN) data packets: 512-slot DMX
+//! universes behind a 126-byte root/framing/DMP header. Frames larger
+//! than one universe spill into consecutive universes, 170 RGB pixels
+//! (510 slots) each so no pixel straddles two universes.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use anyhow::{Result, bail};
+
+pub const PORT: u16 = 5568;
+
+pub const HEADER_LEN: usize = 126;
+
+/// Slots filled per universe: 170 whole RGB pixels.
+pub const SLOTS_PER_UNIVERSE: usize = 510;
+
+pub const MIN_UNIVERSE: u16 = 1;
+pub const MAX_UNIVERSE: u16 = 63999;
+
+pub const DEFAULT_PRIORITY: u8 = 100;
+pub const MAX_PRIORITY: u8 = 200;
+
+const ACN_PACKET_IDENTIFIER: [u8; 12] = *b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const SOURCE_NAME: &str = "lightwave";
+
+pub struct E131 {
+    cid: [u8; 16],
+    first_universe: u16,
+    priority: u8,
+    /// Per-universe sequence numbers, as receivers track them.
+    sequences: Vec<u8>,
+}
+
+impl E131 {
+    pub fn new(first_universe: u16, priority: u8) -> Self {
+        Self {
+            cid: random_cid(),
+            first_universe,
+            priority,
+            sequences: Vec::new(),
+        }
+    }
+
+    /// Split one frame of channel bytes into per-universe packets. Fails,
+    /// sending nothing, if the frame would run past [`MAX_UNIVERSE`].
+    pub fn encode(&mut self, data: &[u8], packets: &mut Vec<Vec<u8>>) -> Result<()> {
+        let universes = data.len().div_ceil(SLOTS_PER_UNIVERSE).max(1);
+        let last = self.first_universe as usize + universes - 1;
+        if last > MAX_UNIVERSE as usize {
+            bail!(
+                "{} e131 slots starting at universe {} run past universe {MAX_UNIVERSE}",
+                data.len(),
+                self.first_universe
+            );
+        }
+
+        self.sequences.resize(universes, 0);
+
+        for i in 0..universes {
+            let start = (i * SLOTS_PER_UNIVERSE).min(data.len());
+            let end = (start + SLOTS_PER_UNIVERSE).min(data.len());
+            let universe = self.first_universe + i as u16;
+
+            let sequence = self.sequences[i];
+            self.sequences[i] = sequence.wrapping_add(1);
+
+            packets.push(self.packet(universe, sequence, &data[start..end]));
+        }
+
+        Ok(())
+    }
+
+    fn packet(&self, universe: u16, sequence: u8, slots: &[u8]) -> Vec<u8> {
+        let total = HEADER_LEN + slots.len();
+        // PDU lengths cover everything from their own flags field onward.
+        let flags_and_length = |offset: usize| (0x7000 | (total - offset) as u16).to_be_bytes();
+
+        let mut packet = Vec::with_capacity(total);
+
+        // Root layer.
+        packet.extend_from_slice(&0x0010u16.to_be_bytes());
+        packet.extend_from_slice(&0u16.to_be_bytes());
+        packet.extend_from_slice(&ACN_PACKET_IDENTIFIER);
+        packet.extend_from_slice(&flags_and_length(16));
+        packet.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+        packet.extend_from_slice(&self.cid);
+
+        // Framing layer.
+        packet.extend_from_slice(&flags_and_length(38));
+        packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        let mut name = [0u8; 64];
+        name[..SOURCE_NAME.len()].copy_from_slice(SOURCE_NAME.as_bytes());
+        packet.extend_from_slice(&name);
+        packet.push(self.priority);
+        packet.extend_from_slice(&0u16.to_be_bytes()); // sync address
+        packet.push(sequence);
+        packet.push(0); // options
+        packet.extend_from_slice(&universe.to_be_bytes());
+
+        // DMP layer.
+        packet.extend_from_slice(&flags_and_length(115));
+        packet.push(VECTOR_DMP_SET_PROPERTY);
+        packet.push(0xa1); // address and data type
+        packet.extend_from_slice(&0u16.to_be_bytes()); // first property address
+        packet.extend_from_slice(&1u16.to_be_bytes()); // address increment
+        packet.extend_from_slice(&(slots.len() as u16 + 1).to_be_bytes());
+        packet.push(0); // DMX start code
+        packet.extend_from_slice(slots);
+
+        packet
+    }
+}
+
+/// A random (version 4) UUID identifying this sender for the process
+/// lifetime; `RandomState` is seeded from the OS.
+fn random_cid() -> [u8; 16] {
+    let mut cid = [0u8; 16];
+
+    for (i, half) in cid.chunks_exact_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        half.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+
+    cid[6] = (cid[6] & 0x0f) | 0x40;
+    cid[8] = (cid[8] & 0x3f) | 0x80;
+    cid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_layout_matches_the_spec() {
+        let mut e131 = E131::new(7, 150);
+        let mut packets = Vec::new();
+        e131.encode(&[10, 20, 30], &mut packets).unwrap();
+
+        let p = &packets[0];
+        assert_eq!(p.len(), HEADER_LEN + 3);
+        assert_eq!(p[4..16], ACN_PACKET_IDENTIFIER);
+        assert_eq!(
+            u16::from_be_bytes([p[16], p[17]]),
+            0x7000 | (p.len() - 16) as u16
+        );
+        assert_eq!(
+            u16::from_be_bytes([p[38], p[39]]),
+            0x7000 | (p.len() - 38) as u16
+        );
+        assert_eq!(
+            u16::from_be_bytes([p[115], p[116]]),
+            0x7000 | (p.len() - 115) as u16
+        );
+        assert_eq!(&p[44..53], b"lightwave");
+        assert_eq!(p[108], 150); // priority
+        assert_eq!(p[111], 0); // sequence
+        assert_eq!(u16::from_be_bytes([p[113], p[114]]), 7); // universe
+        assert_eq!(u16::from_be_bytes([p[123], p[124]]), 4); // start code + 3 slots
+        assert_eq!(p[125], 0);
+        assert_eq!(p[126..], [10, 20, 30]);
+    }
+
+    #[test]
+    fn splits_universes_on_whole_pixels() {
+        let mut e131 = E131::new(1, DEFAULT_PRIORITY);
+        let mut packets = Vec::new();
+        // 200 RGB pixels: 170 in universe 1, 30 in universe 2.
+        e131.encode(&[1; 600], &mut packets).unwrap();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].len(), HEADER_LEN + 510);
+        assert_eq!(packets[1].len(), HEADER_LEN + 90);
+        assert_eq!(u16::from_be_bytes([packets[1][113], packets[1][114]]), 2);
+    }
+
+    #[test]
+    fn rejects_frames_past_the_last_universe() {
+        let mut e131 = E131::new(MAX_UNIVERSE, DEFAULT_PRIORITY);
+        let mut packets = Vec::new();
+
+        e131.encode(&[0; 510], &mut packets).unwrap();
+        assert_eq!(packets.len(), 1);
+
+        packets.clear();
+        assert!(e131.encode(&[0; 511], &mut packets).is_err());
+        assert!(packets.is_empty());
+    }
+
+    #[test]
+    fn sequence_advances_per_universe() {
+        let mut e131 = E131::new(1, DEFAULT_PRIORITY);
+        let mut packets = Vec::new();
+
+        e131.encode(&[0; 3], &mut packets).unwrap();
+        packets.clear();
+        e131.encode(&[0; 3], &mut packets).unwrap();
+
+        assert_eq!(packets[0][111], 1);
+    }
+
+    #[test]
+    fn cid_is_a_version_4_uuid() {
+        let cid = random_cid();
+
+        assert_eq!(cid[6] >> 4, 4);
+        assert_eq!(cid[8] >> 6, 0b10);
+    }
+}
@@ -0,0 +1,179 @@
+//! Payload encodings for framed packets: full f32, quantized u16/u8, and
+//! u8 delta frames that only carry the elements that changed.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Delta streams send a full frame at least this often, so a receiver
+/// that joined late or lost a packet resynchronizes within a second or two.
+pub const KEYFRAME_INTERVAL: u32 = 60;
+
+/// How a framed packet stores its values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Little-endian f32, 4 bytes per value.
+    #[default]
+    F32,
+    /// 0..=1 quantized to little-endian u16, 2 bytes per value.
+    U16,
+    /// 0..=1 quantized to u8, 1 byte per value.
+    U8,
+    /// u8 values for changed elements only, after a bitmask of which
+    /// elements changed since the previous frame.
+    Delta,
+}
+
+impl Encoding {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Self::F32 => 0,
+            Self::U16 => 1,
+            Self::U8 => 2,
+            Self::Delta => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::F32),
+            1 => Some(Self::U16),
+            2 => Some(Self::U8),
+            3 => Some(Self::Delta),
+            _ => None,
+        }
+    }
+
+    /// Largest quantized value, for the quantized encodings.
+    pub(crate) fn max(self) -> Option<u16> {
+        match self {
+            Self::F32 => None,
+            Self::U16 => Some(u16::MAX),
+            Self::U8 | Self::Delta => Some(u8::MAX as u16),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Self::F32),
+            "u16" => Ok(Self::U16),
+            "u8" => Ok(Self::U8),
+            "delta" => Ok(Self::Delta),
+            _ => Err(format!(
+                "unknown encoding {s:?}; expected f32, u16, u8 or delta"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::F32 => "f32",
+            Self::U16 => "u16",
+            Self::U8 => "u8",
+            Self::Delta => "delta",
+        })
+    }
+}
+
+/// Rounds 0..=1 values to integer steps with temporal error diffusion:
+/// each value's rounding error is carried into its next frame, so a slow
+/// fade alternates between neighboring steps in the right proportion
+/// instead of jumping a whole step at a time (banding).
+///
+/// A value that hasn't changed since the last frame keeps its previous
+/// output, so static content stays static (and delta frames stay small).
+pub(crate) struct Quantizer {
+    max: f32,
+    input: Vec<f32>,
+    residual: Vec<f32>,
+    output: Vec<u16>,
+}
+
+impl Quantizer {
+    pub(crate) fn new(max: u16) -> Self {
+        Self {
+            max: max as f32,
+            input: Vec::new(),
+            residual: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub(crate) fn quantize(&mut self, values: &[f32]) -> &[u16] {
+        if self.input.len() != values.len() {
+            self.input = vec![f32::NAN; values.len()];
+            self.residual = vec![0.0; values.len()];
+            self.output = vec![0; values.len()];
+        }
+
+        for (i, &value) in values.iter().enumerate() {
+            let value = value.clamp(0.0, 1.0);
+            if value == self.input[i] {
+                continue;
+            }
+
+            let target = value * self.max + self.residual[i];
+            let step = target.round().clamp(0.0, self.max);
+
+            self.residual[i] = target - step;
+            self.input[i] = value;
+            self.output[i] = step as u16;
+        }
+
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dithering_preserves_the_average_level() {
+        let mut quantizer = Quantizer::new(255);
+        let level = 127.3 / 255.0;
+
+        // Nudge the value every frame so it's re-quantized each time.
+        let sum: u32 = (0..1000)
+            .map(|i| {
+                let value = if i % 2 == 0 { level } else { level + 1e-6 };
+                quantizer.quantize(&[value])[0] as u32
+            })
+            .sum();
+
+        let mean = sum as f32 / 1000.0;
+        assert!((mean - 127.3).abs() < 0.05, "mean level {mean}");
+    }
+
+    #[test]
+    fn static_values_hold_their_output() {
+        let mut quantizer = Quantizer::new(255);
+        let first = quantizer.quantize(&[0.4, 0.7]).to_vec();
+
+        for _ in 0..10 {
+            assert_eq!(quantizer.quantize(&[0.4, 0.7]), first);
+        }
+    }
+
+    #[test]
+    fn clamps_out_of_range_values() {
+        let mut quantizer = Quantizer::new(255);
+
+        assert_eq!(quantizer.quantize(&[-1.0, 2.0]), [0, 255]);
+    }
+
+    #[test]
+    fn parses_encoding_names() {
+        for encoding in [Encoding::F32, Encoding::U16, Encoding::U8, Encoding::Delta] {
+            assert_eq!(encoding.to_string().parse(), Ok(encoding));
+            assert_eq!(Encoding::from_id(encoding.id()), Some(encoding));
+        }
+
+        assert!("u4".parse::<Encoding>().is_err());
+    }
+}
@@ -0,0 +1,312 @@
+//! UDP client plumbing shared by the streaming presets (music, ambilight).
+
+mod artnet;
+mod ddp;
+mod e131;
+mod encoding;
+mod packet;
+mod protocol;
+mod udp;
+
+use std::io;
+use std::net::UdpSocket;
+
+use anyhow::{Result, bail};
+
+pub use encoding::{Encoding, KEYFRAME_INTERVAL};
+pub use packet::{
+    Decoder, Encoder, HEADER_LEN, MAGIC, Packet, PacketFormat, StreamKind, VERSION, decode,
+};
+pub use protocol::Protocol;
+pub use udp::{Destination, UdpOptions, connect_udp, send_packet};
+
+use protocol::PixelEncoder;
+
+/// How a stream's frames go out on the wire.
+#[derive(Clone, Debug)]
+pub struct SendOptions {
+    pub protocol: Protocol,
+    /// LightWave packet layout.
+    pub format: PacketFormat,
+    /// LightWave payload encoding; anything but f32 needs the framed format.
+    pub encoding: Encoding,
+    /// First E1.31 / Art-Net universe; None = the protocol's default.
+    pub universe: Option<u16>,
+    /// E1.31 source priority, 0..=200.
+    pub priority: u8,
+    /// Multicast socket settings.
+    pub udp: UdpOptions,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            protocol: Protocol::default(),
+            format: PacketFormat::default(),
+            encoding: Encoding::default(),
+            universe: None,
+            priority: e131::DEFAULT_PRIORITY,
+            udp: UdpOptions::default(),
+        }
+    }
+}
+
+/// Running totals of a [`Sender`]'s sends.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SendStats {
+    pub packets: u64,
+    /// Failed sends, not counting refusals.
+    pub errors: u64,
+    /// Sends refused because nothing listens on the target port (only
+    /// reported for unicast targets, and only by some platforms).
+    pub refused: u64,
+    /// Refusals since sends last got through twice in a row. A port
+    /// nobody listens on refuses every other send on Linux (the ICMP
+    /// error for one send fails the next), so a single clean send
+    /// proves nothing.
+    pub refused_streak: u64,
+    pub last_error: Option<String>,
+}
+
+enum Wire {
+    LightWave(Encoder),
+    Pixels(PixelEncoder),
+}
+
+/// A connected UDP stream that encodes each frame's values in the chosen
+/// protocol and packet format.
+pub struct Sender {
+    socket: UdpSocket,
+    destination: Destination,
+    wire: Wire,
+    packets: Vec<Vec<u8>>,
+    stats: SendStats,
+    clean_sends: u32,
+}
+
+impl Sender {
+    pub fn connect(target: &str, kind: StreamKind, options: &SendOptions) -> Result<Self> {
+        let wire = match options.protocol {
+            Protocol::LightWave => {
+                if options.universe.is_some() {
+                    bail!("universe only applies to the e131 and artnet protocols");
+                }
+
+                Wire::LightWave(Encoder::new(options.format, options.encoding, kind)?)
+            }
+            protocol => {
+                if options.format != PacketFormat::Legacy || options.encoding != Encoding::F32 {
+                    bail!("packet format and encoding only apply to the lightwave protocol");
+                }
+
+                Wire::Pixels(PixelEncoder::new(
+                    protocol,
+                    kind,
+                    options.universe,
+                    options.priority,
+                )?)
+            }
+        };
+
+        let (socket, destination) = connect_udp(target, &options.udp)?;
+
+        Ok(Self {
+            socket,
+            destination,
+            wire,
+            packets: vec![Vec::new()],
+            stats: SendStats::default(),
+            clean_sends: 0,
+        })
+    }
+
+    /// Whether the target is a unicast, broadcast or multicast address.
+    pub fn destination(&self) -> Destination {
+        self.destination
+    }
+
+    pub fn stats(&self) -> &SendStats {
+        &self.stats
+    }
+
+    /// Encode and send one frame, which may take several packets (DDP
+    /// chunks, DMX universes). Failed sends are counted in [`stats`]
+    /// rather than returned: a refused or dropped packet shouldn't end
+    /// the stream, the next frame may well get through. So are frames
+    /// that don't fit the protocol, such as ones running past the last
+    /// E1.31 universe.
+    ///
+    /// [`stats`]: Self::stats
+    pub fn send(&mut self, values: &[f32]) {
+        match &mut self.wire {
+            Wire::LightWave(encoder) => {
+                self.packets.truncate(1);
+                encoder.encode(values, &mut self.packets[0]);
+            }
+            Wire::Pixels(encoder) => {
+                if let Err(err) = encoder.encode(values, &mut self.packets) {
+                    self.stats.errors += 1;
+                    self.stats.last_error = Some(err.to_string());
+                    return;
+                }
+            }
+        }
+
+        for packet in &self.packets {
+            match self.socket.send(packet) {
+                Ok(_) => {
+                    self.stats.packets += 1;
+                    self.clean_sends += 1;
+                    if self.clean_sends >= 2 {
+                        self.stats.refused_streak = 0;
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    self.stats.refused += 1;
+                    self.stats.refused_streak += 1;
+                    self.clean_sends = 0;
+                }
+                Err(err) => {
+                    self.stats.errors += 1;
+                    self.stats.last_error = Some(err.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A receiver on loopback and a sender connected to it.
+    fn loopback(kind: StreamKind, options: &SendOptions) -> (UdpSocket, Sender) {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+
+        (receiver, Sender::connect(&target, kind, options).unwrap())
+    }
+
+    fn receive(receiver: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0u8; 2048];
+        let len = receiver.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn lightwave_frames_arrive_over_loopback() {
+        let options = SendOptions {
+            format: PacketFormat::Framed,
+            ..SendOptions::default()
+        };
+        let (receiver, mut sender) = loopback(StreamKind::Ambilight, &options);
+
+        sender.send(&[0.25, 0.5, 1.0]);
+        let packet = decode(&receive(&receiver)).unwrap();
+
+        assert_eq!(packet.values, [0.25, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn ddp_frames_arrive_over_loopback() {
+        let options = SendOptions {
+            protocol: Protocol::Ddp,
+            ..SendOptions::default()
+        };
+        let (receiver, mut sender) = loopback(StreamKind::Ambilight, &options);
+
+        sender.send(&[1.0, 0.0, 0.5]);
+        let packet = receive(&receiver);
+
+        assert_eq!(packet[0] & 0x01, 0x01); // push
+        assert_eq!(packet[ddp::HEADER_LEN..], [255, 0, 128]);
+    }
+
+    #[test]
+    fn e131_frames_split_across_universes_over_loopback() {
+        let options = SendOptions {
+            protocol: Protocol::E131,
+            universe: Some(5),
+            ..SendOptions::default()
+        };
+        let (receiver, mut sender) = loopback(StreamKind::Ambilight, &options);
+
+        // 200 boxes: 170 pixels in universe 5, 30 in universe 6.
+        sender.send(&[1.0; 600]);
+        let universes: Vec<(u16, usize)> = (0..2)
+            .map(|_| {
+                let packet = receive(&receiver);
+                (
+                    u16::from_be_bytes([packet[113], packet[114]]),
+                    packet.len() - e131::HEADER_LEN,
+                )
+            })
+            .collect();
+
+        assert_eq!(universes, [(5, 510), (6, 90)]);
+    }
+
+    #[test]
+    fn artnet_frames_arrive_over_loopback() {
+        let options = SendOptions {
+            protocol: Protocol::ArtNet,
+            ..SendOptions::default()
+        };
+        let (receiver, mut sender) = loopback(StreamKind::Music, &options);
+
+        sender.send(&[1.0]);
+        let packet = receive(&receiver);
+
+        assert_eq!(packet[..8], *b"Art-Net\0");
+        assert_eq!(packet[artnet::HEADER_LEN..], [255, 255, 255, 0]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn counts_refused_sends() {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let target = format!("127.0.0.1:{port}");
+        let mut sender =
+            Sender::connect(&target, StreamKind::Music, &SendOptions::default()).unwrap();
+
+        // The ICMP port-unreachable for one send surfaces on the next.
+        for _ in 0..3 {
+            sender.send(&[0.5]);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        assert!(sender.stats().refused >= 1, "{:?}", sender.stats());
+        assert_eq!(sender.stats().refused_streak, sender.stats().refused);
+        assert_eq!(sender.stats().errors, 0);
+
+        // Once something listens, the streak clears.
+        let receiver = UdpSocket::bind(&target).unwrap();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        for _ in 0..3 {
+            sender.send(&[0.5]);
+        }
+        receive(&receiver);
+
+        assert_eq!(sender.stats().refused_streak, 0);
+    }
+
+    #[test]
+    fn pixel_protocols_reject_lightwave_options() {
+        let options = SendOptions {
+            protocol: Protocol::Ddp,
+            encoding: Encoding::U8,
+            ..SendOptions::default()
+        };
+
+        assert!(Sender::connect("127.0.0.1:4048", StreamKind::Music, &options).is_err());
+    }
+}
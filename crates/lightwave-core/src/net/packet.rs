@@ -0,0 +1,525 @@
+//! Streaming packet layouts.
+//!
+//! `legacy` is the original headerless format: the stream's values as
+//! packed little-endian f32, nothing else. `framed` prefixes the payload
+//! with a 20-byte little-endian header:
+//!
+//! | offset | size | field                                            |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 2    | magic `"LW"`                                     |
+//! | 2      | 1    | protocol version (1)                             |
+//! | 3      | 1    | stream kind (1 = music, 2 = ambilight, 3 = beat) |
+//! | 4      | 2    | element count (bins, boxes or beats)             |
+//! | 6      | 1    | encoding (0 = f32, 1 = u16, 2 = u8, 3 = delta)   |
+//! | 7      | 1    | flags; bit 0 marks a delta keyframe              |
+//! | 8      | 4    | sequence number, wrapping, +1 per packet         |
+//! | 12     | 8    | send time, microseconds since the Unix epoch     |
+//!
+//! and may store the values compactly (see [`Encoding`]). Quantized
+//! values map 0..=max onto 0..=1. A delta payload is a bitmask with one
+//! bit per element (LSB first, rounded up to whole bytes) followed by the
+//! u8 channels of each set element, in order; keyframes set every bit.
+//!
+//! Music packets analyzing two channels (stereo or mid-side) carry both
+//! spectra back to back: all of the first channel's bins (left, or mid),
+//! low to high, then the second's, so the element count is twice the
+//! bins.
+//!
+//! Beat packets (framed, f32 only) carry one element per beat: its
+//! strength (0..=1), the tempo in BPM (0 until one is established) and
+//! the tempo's confidence (0..=1).
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, anyhow, bail};
+
+use super::encoding::{Encoding, KEYFRAME_INTERVAL, Quantizer};
+
+pub const MAGIC: [u8; 2] = *b"LW";
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 20;
+
+const FLAG_KEYFRAME: u8 = 1;
+
+/// Wire layout of a streaming packet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PacketFormat {
+    /// Bare little-endian f32 values, as the presets have always read them.
+    #[default]
+    Legacy,
+    /// Versioned header followed by the legacy payload.
+    Framed,
+}
+
+impl FromStr for PacketFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(Self::Legacy),
+            "framed" => Ok(Self::Framed),
+            _ => Err(format!(
+                "unknown packet format {s:?}; expected legacy or framed"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PacketFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Legacy => "legacy",
+            Self::Framed => "framed",
+        })
+    }
+}
+
+/// What a stream carries, which fixes how many values make one element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamKind {
+    /// Spectrum bins, one value each.
+    Music,
+    /// RGB boxes, three values each.
+    Ambilight,
+    /// Beats detected in the music: strength, BPM and confidence.
+    Beat,
+}
+
+impl StreamKind {
+    /// Values per element.
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Music => 1,
+            Self::Ambilight | Self::Beat => 3,
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Self::Music => 1,
+            Self::Ambilight => 2,
+            Self::Beat => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::Music),
+            2 => Some(Self::Ambilight),
+            3 => Some(Self::Beat),
+            _ => None,
+        }
+    }
+}
+
+/// Turns a stream's values into packets, numbering framed ones.
+pub struct Encoder {
+    format: PacketFormat,
+    encoding: Encoding,
+    kind: StreamKind,
+    sequence: u32,
+    quantizer: Option<Quantizer>,
+    /// Delta encoding: the last values sent, and packets sent since (and
+    /// including) the last keyframe.
+    previous: Vec<u16>,
+    since_keyframe: u32,
+}
+
+impl Encoder {
+    pub fn new(format: PacketFormat, encoding: Encoding, kind: StreamKind) -> Result<Self> {
+        if format == PacketFormat::Legacy && encoding != Encoding::F32 {
+            bail!("{encoding} encoding needs the framed packet format");
+        }
+
+        Ok(Self {
+            format,
+            encoding,
+            kind,
+            sequence: 0,
+            quantizer: encoding.max().map(Quantizer::new),
+            previous: Vec::new(),
+            since_keyframe: 0,
+        })
+    }
+
+    /// Replace `out` with the packet for `values` (a whole number of
+    /// elements).
+    pub fn encode(&mut self, values: &[f32], out: &mut Vec<u8>) {
+        out.clear();
+
+        if self.format == PacketFormat::Legacy {
+            for value in values {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            return;
+        }
+
+        let channels = self.kind.channels();
+        let count = (values.len() / channels) as u16;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_micros() as u64);
+
+        out.extend_from_slice(&MAGIC);
+        out.push(VERSION);
+        out.push(self.kind.id());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.push(self.encoding.id());
+        out.push(0);
+        out.extend_from_slice(&self.sequence.to_le_bytes());
+        out.extend_from_slice(&timestamp.to_le_bytes());
+
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let Some(quantizer) = &mut self.quantizer else {
+            for value in values {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            return;
+        };
+        let steps = quantizer.quantize(values);
+
+        match self.encoding {
+            Encoding::U16 => {
+                for &step in steps {
+                    out.extend_from_slice(&step.to_le_bytes());
+                }
+            }
+            Encoding::U8 => out.extend(steps.iter().map(|&step| step as u8)),
+            Encoding::Delta => {
+                let keyframe =
+                    self.previous.len() != steps.len() || self.since_keyframe >= KEYFRAME_INTERVAL;
+
+                if keyframe {
+                    out[7] |= FLAG_KEYFRAME;
+                    self.since_keyframe = 1;
+                } else {
+                    self.since_keyframe += 1;
+                }
+
+                let mask_start = out.len();
+                out.resize(mask_start + (count as usize).div_ceil(8), 0);
+
+                for (i, element) in steps.chunks_exact(channels).enumerate() {
+                    let changed =
+                        keyframe || element != &self.previous[i * channels..(i + 1) * channels];
+
+                    if changed {
+                        out[mask_start + i / 8] |= 1 << (i % 8);
+                        out.extend(element.iter().map(|&step| step as u8));
+                    }
+                }
+
+                self.previous.clear();
+                self.previous.extend_from_slice(steps);
+            }
+            Encoding::F32 => unreachable!("f32 has no quantizer"),
+        }
+    }
+}
+
+/// A decoded framed packet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub kind: StreamKind,
+    pub encoding: Encoding,
+    pub sequence: u32,
+    /// Microseconds since the Unix epoch at send time.
+    pub timestamp_us: u64,
+    /// `count * kind.channels()` values; for delta frames, the full
+    /// frame with unchanged elements carried over.
+    pub values: Vec<f32>,
+}
+
+impl Packet {
+    /// Number of elements (bins or boxes) in the packet.
+    pub fn count(&self) -> usize {
+        self.values.len() / self.kind.channels()
+    }
+}
+
+/// Parses framed packets, keeping the state delta frames build on.
+#[derive(Default)]
+pub struct Decoder {
+    previous: Vec<f32>,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse and validate a framed packet.
+    pub fn decode(&mut self, packet: &[u8]) -> Result<Packet> {
+        let header = packet
+            .get(..HEADER_LEN)
+            .ok_or_else(|| anyhow!("packet is {} bytes, shorter than the header", packet.len()))?;
+
+        if header[0..2] != MAGIC {
+            bail!(
+                "bad magic {:02x?}; not a framed LightWave packet",
+                &header[0..2]
+            );
+        }
+
+        if header[2] != VERSION {
+            bail!(
+                "unsupported protocol version {} (expected {VERSION})",
+                header[2]
+            );
+        }
+
+        let kind = StreamKind::from_id(header[3])
+            .ok_or_else(|| anyhow!("unknown stream kind {}", header[3]))?;
+        let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+        let encoding = Encoding::from_id(header[6])
+            .ok_or_else(|| anyhow!("unknown encoding {}", header[6]))?;
+        let keyframe = header[7] & FLAG_KEYFRAME != 0;
+        let sequence = u32::from_le_bytes(header[8..12].try_into()?);
+        let timestamp_us = u64::from_le_bytes(header[12..20].try_into()?);
+
+        let channels = kind.channels();
+        let len = count * channels;
+        let payload = &packet[HEADER_LEN..];
+
+        let check_len = |expected: usize| {
+            if payload.len() != expected {
+                bail!(
+                    "{encoding} payload is {} bytes, but {count} elements need {expected}",
+                    payload.len()
+                );
+            }
+            Ok(())
+        };
+
+        let values: Vec<f32> = match encoding {
+            Encoding::F32 => {
+                check_len(len * 4)?;
+                payload
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect()
+            }
+            Encoding::U16 => {
+                check_len(len * 2)?;
+                payload
+                    .chunks_exact(2)
+                    .map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+                    .collect()
+            }
+            Encoding::U8 => {
+                check_len(len)?;
+                payload.iter().map(|&b| b as f32 / 255.0).collect()
+            }
+            Encoding::Delta => {
+                let mask_len = count.div_ceil(8);
+                let mask = payload
+                    .get(..mask_len)
+                    .ok_or_else(|| anyhow!("delta payload is shorter than its bitmask"))?;
+                let changed = (0..count)
+                    .filter(|i| mask[i / 8] & (1 << (i % 8)) != 0)
+                    .count();
+                check_len(mask_len + changed * channels)?;
+
+                if !keyframe && self.previous.len() != len {
+                    bail!("delta frame {sequence} arrived before a keyframe");
+                }
+
+                let mut values = if keyframe {
+                    vec![0.0; len]
+                } else {
+                    self.previous.clone()
+                };
+                let mut data = payload[mask_len..].chunks_exact(channels);
+
+                for i in (0..count).filter(|i| mask[i / 8] & (1 << (i % 8)) != 0) {
+                    let element = data.next().expect("length checked above");
+                    for (value, &b) in values[i * channels..].iter_mut().zip(element) {
+                        *value = b as f32 / 255.0;
+                    }
+                }
+
+                values
+            }
+        };
+
+        self.previous.clone_from(&values);
+
+        Ok(Packet {
+            kind,
+            encoding,
+            sequence,
+            timestamp_us,
+            values,
+        })
+    }
+}
+
+/// Parse and validate one framed packet on its own; delta frames other
+/// than keyframes need a [`Decoder`] that has seen the stream.
+pub fn decode(packet: &[u8]) -> Result<Packet> {
+    Decoder::new().decode(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_is_bare_little_endian_floats() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Legacy, Encoding::F32, StreamKind::Music).unwrap();
+        let mut out = Vec::new();
+
+        encoder.encode(&[0.5, 1.0], &mut out);
+
+        assert_eq!(out.len(), 8);
+        assert_eq!(out[..4], 0.5f32.to_le_bytes());
+        assert_eq!(out[4..], 1.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn framed_round_trips() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Framed, Encoding::F32, StreamKind::Ambilight).unwrap();
+        let mut out = Vec::new();
+        let values = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+
+        encoder.encode(&values, &mut out);
+        let packet = decode(&out).unwrap();
+
+        assert_eq!(out.len(), HEADER_LEN + values.len() * 4);
+        assert_eq!(packet.kind, StreamKind::Ambilight);
+        assert_eq!(packet.count(), 2);
+        assert_eq!(packet.sequence, 0);
+        assert!(packet.timestamp_us > 0);
+        assert_eq!(packet.values, values);
+    }
+
+    #[test]
+    fn sequence_advances_per_packet() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Framed, Encoding::F32, StreamKind::Music).unwrap();
+        let mut out = Vec::new();
+
+        let sequences: Vec<u32> = (0..3)
+            .map(|_| {
+                encoder.encode(&[0.0], &mut out);
+                decode(&out).unwrap().sequence
+            })
+            .collect();
+
+        assert_eq!(sequences, [0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Framed, Encoding::F32, StreamKind::Music).unwrap();
+        let mut good = Vec::new();
+        encoder.encode(&[0.25, 0.75], &mut good);
+
+        assert!(decode(&good[..HEADER_LEN - 1]).is_err());
+        assert!(decode(&good[..good.len() - 4]).is_err());
+
+        let mut bad_magic = good.clone();
+        bad_magic[0] = b'X';
+        assert!(decode(&bad_magic).is_err());
+
+        let mut bad_version = good.clone();
+        bad_version[2] = VERSION + 1;
+        assert!(decode(&bad_version).is_err());
+
+        let mut bad_kind = good.clone();
+        bad_kind[3] = 9;
+        assert!(decode(&bad_kind).is_err());
+
+        let mut bad_encoding = good;
+        bad_encoding[6] = 9;
+        assert!(decode(&bad_encoding).is_err());
+    }
+
+    #[test]
+    fn compact_encodings_need_framing() {
+        assert!(Encoder::new(PacketFormat::Legacy, Encoding::U8, StreamKind::Music).is_err());
+    }
+
+    #[test]
+    fn quantized_encodings_round_trip_within_a_step() {
+        let values = [0.0, 0.1234, 0.5, 0.9876, 1.0, 0.333];
+
+        for (encoding, bytes, step) in [
+            (Encoding::U16, 2, 1.0 / 65535.0),
+            (Encoding::U8, 1, 1.0 / 255.0),
+        ] {
+            let mut encoder =
+                Encoder::new(PacketFormat::Framed, encoding, StreamKind::Ambilight).unwrap();
+            let mut out = Vec::new();
+            encoder.encode(&values, &mut out);
+
+            assert_eq!(out.len(), HEADER_LEN + values.len() * bytes);
+
+            let packet = decode(&out).unwrap();
+            assert_eq!(packet.encoding, encoding);
+            assert_eq!(packet.count(), 2);
+            for (decoded, original) in packet.values.iter().zip(values) {
+                assert!(
+                    (decoded - original).abs() <= step,
+                    "{encoding}: {decoded} vs {original}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delta_frames_skip_unchanged_elements() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Framed, Encoding::Delta, StreamKind::Ambilight).unwrap();
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+
+        let mut frame = vec![0.5; 30];
+        encoder.encode(&frame, &mut out);
+        // Keyframe: 10 boxes -> 2 mask bytes + 30 channel bytes.
+        assert_eq!(out.len(), HEADER_LEN + 2 + 30);
+        decoder.decode(&out).unwrap();
+
+        frame[27] = 1.0;
+        encoder.encode(&frame, &mut out);
+        // Only box 9 changed.
+        assert_eq!(out.len(), HEADER_LEN + 2 + 3);
+        assert_eq!(out[HEADER_LEN..HEADER_LEN + 2], [0b0000_0000, 0b0000_0010]);
+
+        let packet = decoder.decode(&out).unwrap();
+        assert_eq!(packet.values[27], 1.0);
+        assert!((packet.values[0] - 0.5).abs() <= 1.0 / 255.0);
+
+        // Without the keyframe, a delta frame can't be reconstructed.
+        assert!(decode(&out).is_err());
+    }
+
+    #[test]
+    fn delta_streams_resend_keyframes() {
+        let mut encoder =
+            Encoder::new(PacketFormat::Framed, Encoding::Delta, StreamKind::Music).unwrap();
+        let mut out = Vec::new();
+
+        let keyframes = (0..=KEYFRAME_INTERVAL * 2)
+            .filter(|_| {
+                encoder.encode(&[0.25; 4], &mut out);
+                out[7] & FLAG_KEYFRAME != 0
+            })
+            .count();
+
+        assert_eq!(keyframes, 3);
+    }
+
+    #[test]
+    fn parses_format_names() {
+        assert_eq!("framed".parse(), Ok(PacketFormat::Framed));
+        assert_eq!("legacy".parse(), Ok(PacketFormat::Legacy));
+        assert!("json".parse::<PacketFormat>().is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Output protocols: LightWave's own packets, or one of the common LED
+//! controller protocols (DDP, E1.31, Art-Net) for driving WLED, xLights
+//! or DMX gear directly.
+//!
+//! The pixel protocols carry 8-bit RGB: each ambilight box is one pixel,
+//! each music bin one grey pixel (the same level on all three channels).
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, bail};
+
+use super::artnet::{self, ArtNet};
+use super::ddp::Ddp;
+use super::e131::{self, E131};
+use super::encoding::Quantizer;
+use super::packet::StreamKind;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// LightWave packets, as read by the server's presets.
+    #[default]
+    LightWave,
+    Ddp,
+    E131,
+    ArtNet,
+}
+
+impl Protocol {
+    /// The protocol's well-known UDP port; LightWave presets listen on
+    /// whatever port they're started with.
+    pub fn default_port(self) -> Option<u16> {
+        match self {
+            Self::LightWave => None,
+            Self::Ddp => Some(super::ddp::PORT),
+            Self::E131 => Some(e131::PORT),
+            Self::ArtNet => Some(artnet::PORT),
+        }
+    }
+
+    /// Default first universe, for the protocols that have universes.
+    /// E1.31 universes start at 1; Art-Net port addresses at 0.
+    pub fn default_universe(self) -> Option<u16> {
+        match self {
+            Self::E131 => Some(1),
+            Self::ArtNet => Some(0),
+            Self::LightWave | Self::Ddp => None,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lightwave" => Ok(Self::LightWave),
+            "ddp" => Ok(Self::Ddp),
+            "e131" | "sacn" => Ok(Self::E131),
+            "artnet" => Ok(Self::ArtNet),
+            _ => Err(format!(
+                "unknown protocol {s:?}; expected lightwave, ddp, e131 or artnet"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LightWave => "lightwave",
+            Self::Ddp => "ddp",
+            Self::E131 => "e131",
+            Self::ArtNet => "artnet",
+        })
+    }
+}
+
+enum Packetizer {
+    Ddp(Ddp),
+    E131(E131),
+    ArtNet(ArtNet),
+}
+
+/// Turns frames of 0..=1 values into RGB pixel packets for one of the
+/// pixel protocols.
+pub(crate) struct PixelEncoder {
+    packetizer: Packetizer,
+    /// Channels per element: 1 for music (expanded to grey), 3 for RGB.
+    channels: usize,
+    quantizer: Quantizer,
+    data: Vec<u8>,
+}
+
+impl PixelEncoder {
+    pub(crate) fn new(
+        protocol: Protocol,
+        kind: StreamKind,
+        universe: Option<u16>,
+        priority: u8,
+    ) -> Result<Self> {
+        if universe.is_some() && protocol.default_universe().is_none() {
+            bail!("universe only applies to the e131 and artnet protocols");
+        }
+
+        let universe = universe.or(protocol.default_universe()).unwrap_or(0);
+
+        let packetizer = match protocol {
+            Protocol::LightWave => bail!("lightwave packets don't go through the pixel encoder"),
+            Protocol::Ddp => Packetizer::Ddp(Ddp::default()),
+            Protocol::E131 => {
+                if !(e131::MIN_UNIVERSE..=e131::MAX_UNIVERSE).contains(&universe) {
+                    bail!(
+                        "e131 universe must be in {}..={}, got {universe}",
+                        e131::MIN_UNIVERSE,
+                        e131::MAX_UNIVERSE
+                    );
+                }
+
+                if priority > e131::MAX_PRIORITY {
+                    bail!(
+                        "e131 priority must be in 0..={}, got {priority}",
+                        e131::MAX_PRIORITY
+                    );
+                }
+
+                Packetizer::E131(E131::new(universe, priority))
+            }
+            Protocol::ArtNet => {
+                if universe > artnet::MAX_UNIVERSE {
+                    bail!(
+                        "artnet universe must be in 0..={}, got {universe}",
+                        artnet::MAX_UNIVERSE
+                    );
+                }
+
+                Packetizer::ArtNet(ArtNet::new(universe))
+            }
+        };
+
+        Ok(Self {
+            packetizer,
+            channels: kind.channels(),
+            quantizer: Quantizer::new(u8::MAX as u16),
+            data: Vec::new(),
+        })
+    }
+
+    /// Encode one frame into `packets` (cleared first); fails if the frame
+    /// doesn't fit the protocol's address space.
+    pub(crate) fn encode(&mut self, values: &[f32], packets: &mut Vec<Vec<u8>>) -> Result<()> {
+        packets.clear();
+        self.data.clear();
+
+        for &level in self.quantizer.quantize(values) {
+            let level = level as u8;
+            if self.channels == 1 {
+                self.data.extend_from_slice(&[level; 3]);
+            } else {
+                self.data.push(level);
+            }
+        }
+
+        match &mut self.packetizer {
+            Packetizer::Ddp(ddp) => ddp.encode(&self.data, packets),
+            Packetizer::E131(e131) => return e131.encode(&self.data, packets),
+            Packetizer::ArtNet(artnet) => artnet.encode(&self.data, packets),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_protocol_names() {
+        for protocol in [
+            Protocol::LightWave,
+            Protocol::Ddp,
+            Protocol::E131,
+            Protocol::ArtNet,
+        ] {
+            assert_eq!(protocol.to_string().parse(), Ok(protocol));
+        }
+
+        assert_eq!("sacn".parse(), Ok(Protocol::E131));
+        assert!("dmx".parse::<Protocol>().is_err());
+    }
+
+    #[test]
+    fn music_bins_become_grey_pixels() {
+        let mut encoder = PixelEncoder::new(Protocol::Ddp, StreamKind::Music, None, 100).unwrap();
+        let mut packets = Vec::new();
+        encoder.encode(&[0.0, 1.0], &mut packets).unwrap();
+
+        assert_eq!(
+            packets[0][super::super::ddp::HEADER_LEN..],
+            [0, 0, 0, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn validates_universe_and_priority() {
+        let encoder = |protocol, universe, priority| {
+            PixelEncoder::new(protocol, StreamKind::Ambilight, universe, priority)
+        };
+
+        assert!(encoder(Protocol::E131, Some(0), 100).is_err());
+        assert!(encoder(Protocol::E131, Some(1), 201).is_err());
+        assert!(encoder(Protocol::ArtNet, Some(0x8000), 100).is_err());
+        assert!(encoder(Protocol::Ddp, Some(1), 100).is_err());
+        assert!(encoder(Protocol::ArtNet, Some(0), 100).is_ok());
+    }
+}
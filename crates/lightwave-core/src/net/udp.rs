@@ -0,0 +1,311 @@
+//! Connected UDP sockets for unicast, subnet broadcast and multicast
+//! targets. The kind of target is read off the address: multicast groups
+//! (224.0.0.0/4, ff00::/8) get the multicast options, the limited
+//! broadcast address or a local interface's broadcast address gets
+//! `SO_BROADCAST`, anything else is plain unicast.
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+use anyhow::{Context, Result, anyhow, bail};
+use socket2::SockRef;
+
+/// Socket settings that only matter for multicast targets.
+#[derive(Clone, Debug)]
+pub struct UdpOptions {
+    /// Multicast TTL (IPv4) or hop limit (IPv6); 1 stays on the local subnet.
+    pub multicast_ttl: u32,
+    /// Interface multicast leaves on: a name (`eth0`), an IPv4 address
+    /// for IPv4 groups or an interface index for IPv6 groups; None lets
+    /// the routing table decide.
+    pub multicast_interface: Option<String>,
+    /// Also deliver multicast to listeners on this host.
+    pub multicast_loop: bool,
+}
+
+impl Default for UdpOptions {
+    fn default() -> Self {
+        Self {
+            multicast_ttl: 1,
+            multicast_interface: None,
+            multicast_loop: true,
+        }
+    }
+}
+
+/// What kind of address a stream is sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Destination {
+    Unicast,
+    Broadcast,
+    Multicast,
+}
+
+impl Destination {
+    /// Classify `addr`, checking IPv4 addresses against the broadcast
+    /// addresses of the local interfaces.
+    pub fn of(addr: IpAddr) -> Self {
+        let broadcasts: Vec<Ipv4Addr> = match addr {
+            IpAddr::V4(_) => if_addrs::get_if_addrs()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|interface| match interface.addr {
+                    if_addrs::IfAddr::V4(v4) => v4.broadcast,
+                    if_addrs::IfAddr::V6(_) => None,
+                })
+                .collect(),
+            IpAddr::V6(_) => Vec::new(),
+        };
+
+        classify(addr, &broadcasts)
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unicast => "unicast",
+            Self::Broadcast => "broadcast",
+            Self::Multicast => "multicast",
+        })
+    }
+}
+
+fn classify(addr: IpAddr, broadcasts: &[Ipv4Addr]) -> Destination {
+    match addr {
+        addr if addr.is_multicast() => Destination::Multicast,
+        IpAddr::V4(v4) if v4.is_broadcast() || broadcasts.contains(&v4) => Destination::Broadcast,
+        _ => Destination::Unicast,
+    }
+}
+
+/// Resolve `target` (preferring IPv4 addresses) and return a UDP socket
+/// bound to the matching address family, set up for the kind of
+/// destination, and connected to it.
+pub fn connect_udp(target: &str, options: &UdpOptions) -> Result<(UdpSocket, Destination)> {
+    let target = resolve_target(target)?;
+    let bind_addr: SocketAddr = if target.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+
+    let socket = UdpSocket::bind(bind_addr).context("binding UDP socket")?;
+    let destination = Destination::of(target.ip());
+
+    match destination {
+        Destination::Unicast => {}
+        Destination::Broadcast => socket
+            .set_broadcast(true)
+            .context("enabling UDP broadcast")?,
+        Destination::Multicast => setup_multicast(&socket, target.ip(), options)
+            .with_context(|| format!("setting up multicast to {}", target.ip()))?,
+    }
+
+    socket
+        .connect(target)
+        .with_context(|| format!("connecting UDP socket to {target}"))?;
+
+    Ok((socket, destination))
+}
+
+fn setup_multicast(socket: &UdpSocket, group: IpAddr, options: &UdpOptions) -> Result<()> {
+    let sock = SockRef::from(socket);
+
+    if group.is_ipv4() {
+        sock.set_multicast_ttl_v4(options.multicast_ttl)?;
+        sock.set_multicast_loop_v4(options.multicast_loop)?;
+
+        if let Some(interface) = &options.multicast_interface {
+            sock.set_multicast_if_v4(&interface_v4(interface)?)?;
+        }
+    } else {
+        sock.set_multicast_hops_v6(options.multicast_ttl)?;
+        sock.set_multicast_loop_v6(options.multicast_loop)?;
+
+        if let Some(interface) = &options.multicast_interface {
+            sock.set_multicast_if_v6(interface_index(interface)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// An IPv4 multicast interface, given as an address or an interface name.
+fn interface_v4(interface: &str) -> Result<Ipv4Addr> {
+    if let Ok(addr) = interface.parse() {
+        return Ok(addr);
+    }
+
+    if_addrs::get_if_addrs()
+        .context("listing network interfaces")?
+        .into_iter()
+        .filter(|candidate| candidate.name == interface)
+        .find_map(|candidate| match candidate.addr {
+            if_addrs::IfAddr::V4(v4) => Some(v4.ip),
+            if_addrs::IfAddr::V6(_) => None,
+        })
+        .ok_or_else(|| anyhow!("no network interface {interface:?} with an IPv4 address"))
+}
+
+/// An IPv6 multicast interface, given as an index or an interface name.
+fn interface_index(interface: &str) -> Result<u32> {
+    if let Ok(index) = interface.parse() {
+        return Ok(index);
+    }
+
+    if interface.parse::<IpAddr>().is_ok() {
+        bail!("IPv6 multicast needs an interface name or index, not an address");
+    }
+
+    if_addrs::get_if_addrs()
+        .context("listing network interfaces")?
+        .into_iter()
+        .find(|candidate| candidate.name == interface)
+        .and_then(|candidate| candidate.index)
+        .ok_or_else(|| anyhow!("no network interface {interface:?}"))
+}
+
+/// Send one packet on a connected socket. ConnectionRefused is ignored:
+/// the server may simply not be listening yet, and streaming should
+/// continue until it is.
+pub fn send_packet(socket: &UdpSocket, packet: &[u8]) -> Result<()> {
+    if let Err(err) = socket.send(packet)
+        && err.kind() != io::ErrorKind::ConnectionRefused
+    {
+        return Err(err).context("sending UDP packet");
+    }
+
+    Ok(())
+}
+
+fn resolve_target(target: &str) -> Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = target
+        .to_socket_addrs()
+        .with_context(|| format!("resolving UDP target {target:?}"))?
+        .collect();
+
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()
+        .ok_or_else(|| anyhow!("UDP target {target:?} resolved to no addresses"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn receive(receiver: &UdpSocket) -> Vec<u8> {
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn classifies_destinations() {
+        let subnet = [Ipv4Addr::new(192, 168, 1, 255)];
+        let classify = |addr: &str| classify(addr.parse().unwrap(), &subnet);
+
+        assert_eq!(classify("192.168.1.20"), Destination::Unicast);
+        assert_eq!(classify("192.168.1.255"), Destination::Broadcast);
+        assert_eq!(classify("255.255.255.255"), Destination::Broadcast);
+        assert_eq!(classify("239.255.0.1"), Destination::Multicast);
+        assert_eq!(classify("ff02::1"), Destination::Multicast);
+        assert_eq!(classify("fe80::1"), Destination::Unicast);
+    }
+
+    #[test]
+    fn unicast_over_loopback() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = receiver.local_addr().unwrap().to_string();
+
+        let (socket, destination) = connect_udp(&target, &UdpOptions::default()).unwrap();
+        send_packet(&socket, b"hello").unwrap();
+
+        assert_eq!(destination, Destination::Unicast);
+        assert_eq!(receive(&receiver), b"hello");
+    }
+
+    #[test]
+    fn broadcast_enables_so_broadcast() {
+        let (socket, destination) =
+            connect_udp("255.255.255.255:9", &UdpOptions::default()).unwrap();
+
+        assert_eq!(destination, Destination::Broadcast);
+        assert!(socket.broadcast().unwrap());
+    }
+
+    #[test]
+    fn ipv4_multicast_over_loopback() {
+        let group = Ipv4Addr::new(239, 255, 76, 87);
+        let receiver = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        receiver
+            .join_multicast_v4(&group, &Ipv4Addr::LOCALHOST)
+            .unwrap();
+        let port = receiver.local_addr().unwrap().port();
+
+        let options = UdpOptions {
+            multicast_interface: Some("127.0.0.1".to_string()),
+            ..UdpOptions::default()
+        };
+        let (socket, destination) = connect_udp(&format!("{group}:{port}"), &options).unwrap();
+        send_packet(&socket, b"group").unwrap();
+
+        assert_eq!(destination, Destination::Multicast);
+        assert_eq!(receive(&receiver), b"group");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn ipv6_multicast_options_are_applied() {
+        // Loopback has no IPv6 multicast route, so only check the options.
+        let socket = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).unwrap();
+        let options = UdpOptions {
+            multicast_ttl: 3,
+            multicast_interface: Some("lo".to_string()),
+            multicast_loop: false,
+        };
+        setup_multicast(&socket, "ff02::4c57".parse().unwrap(), &options).unwrap();
+
+        let sock = SockRef::from(&socket);
+        assert_eq!(sock.multicast_hops_v6().unwrap(), 3);
+        assert!(!sock.multicast_loop_v6().unwrap());
+        assert_eq!(
+            sock.multicast_if_v6().unwrap(),
+            interface_index("lo").unwrap()
+        );
+    }
+
+    #[test]
+    fn multicast_options_are_applied() {
+        let options = UdpOptions {
+            multicast_ttl: 4,
+            multicast_interface: Some("127.0.0.1".to_string()),
+            multicast_loop: false,
+        };
+        let (socket, _) = connect_udp("239.255.76.87:9", &options).unwrap();
+
+        assert_eq!(socket.multicast_ttl_v4().unwrap(), 4);
+        assert!(!socket.multicast_loop_v4().unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_interfaces() {
+        let options = UdpOptions {
+            multicast_interface: Some("no-such-interface0".to_string()),
+            ..UdpOptions::default()
+        };
+
+        assert!(connect_udp("239.255.76.87:9", &options).is_err());
+        assert!(connect_udp("[ff02::1]:9", &options).is_err());
+    }
+}
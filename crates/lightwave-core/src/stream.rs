@@ -0,0 +1,559 @@
+//! Bookkeeping shared by the streaming clients (music, ambilight): the
+//! events they report while running, the health counters behind them,
+//! and the control channel other threads use to steer them.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::net::SendStats;
+
+/// Something a running streamer reports to its caller.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Periodic health snapshot.
+    Stats(Stats),
+    /// Sends to `target` keep being refused: nothing listens there.
+    Unreachable { target: String, refused: u64 },
+    /// Sends to `target` get through again after [`Event::Unreachable`].
+    Reachable { target: String },
+    /// The server stopped running `preset`, switching to `by` (None if
+    /// it was just stopped).
+    Preempted { preset: String, by: Option<String> },
+    /// `preset` is running again after [`Event::Preempted`].
+    Reclaimed { preset: String },
+    /// Settings were re-read from the config file (SIGHUP).
+    Reloaded,
+    /// Re-reading the config file failed; the old settings stay.
+    ReloadFailed { error: String },
+    /// A tuning parameter was set (or picked for adjusting) by whoever
+    /// steers the stream; reported through [`Control::report`].
+    Tuned { param: String, value: f32 },
+    /// A beat in the music, with the tempo once one is established.
+    Beat {
+        strength: f32,
+        bpm: Option<f32>,
+        confidence: f32,
+    },
+    /// The input has stayed quiet for the silence timeout; the stream
+    /// idles until sound returns.
+    Silence,
+    /// Sound again after [`Event::Silence`].
+    Resumed,
+}
+
+/// Why a stream ended without an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Ctrl+C (SIGINT).
+    Interrupt,
+    /// SIGTERM, e.g. from systemd.
+    Terminate,
+    /// The server switched away from the stream's preset.
+    Preempted,
+    /// The audio ran out: a file played to its end, or stdin closed.
+    EndOfInput,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Interrupt => "interrupt",
+            Self::Terminate => "terminate",
+            Self::Preempted => "preempted",
+            Self::EndOfInput => "end-of-input",
+        })
+    }
+}
+
+/// A process signal a streamer acts on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// Stop with the given reason.
+    Stop(StopReason),
+    /// Re-read settings from the config file.
+    Reload,
+}
+
+/// The signals that steer a stream: SIGINT (Ctrl+C) and SIGTERM stop
+/// it, SIGHUP reloads its settings. Only Ctrl+C exists off Unix.
+pub struct Signals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
+
+impl Signals {
+    /// Start listening; must be called inside a tokio runtime.
+    pub fn new() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt()).context("listening for SIGINT")?,
+                terminate: signal(SignalKind::terminate()).context("listening for SIGTERM")?,
+                hangup: signal(SignalKind::hangup()).context("listening for SIGHUP")?,
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            Ok(Self {
+                ctrl_c: tokio::signal::windows::ctrl_c().context("listening for Ctrl+C")?,
+            })
+        }
+    }
+
+    /// Wait for the next signal.
+    pub async fn recv(&mut self) -> Signal {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => Signal::Stop(StopReason::Interrupt),
+                _ = self.terminate.recv() => Signal::Stop(StopReason::Terminate),
+                _ = self.hangup.recv() => Signal::Reload,
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            self.ctrl_c.recv().await;
+            Signal::Stop(StopReason::Interrupt)
+        }
+    }
+}
+
+/// A request to a running streamer from another thread.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Stop sending until [`Command::Resume`]; capture keeps running.
+    Pause,
+    Resume,
+    Stop(StopReason),
+    /// Pass an event on to the streamer's event handler, so everything
+    /// is reported from one place.
+    Report(Event),
+}
+
+/// Sends [`Command`]s to a streamer; cheap to clone. Commands to a
+/// streamer that has already finished are dropped.
+#[derive(Clone)]
+pub struct Control {
+    tx: mpsc::Sender<Command>,
+}
+
+impl Control {
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    pub fn stop(&self, reason: StopReason) {
+        self.send(Command::Stop(reason));
+    }
+
+    pub fn report(&self, event: Event) {
+        self.send(Command::Report(event));
+    }
+
+    pub fn send(&self, command: Command) {
+        let _ = self.tx.send(command);
+    }
+}
+
+/// The streamer's end of a [`Control`] channel, drained once per tick.
+pub struct Inbox {
+    rx: mpsc::Receiver<Command>,
+    paused: bool,
+}
+
+impl Inbox {
+    /// Whether sending is paused.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Apply pending commands, reporting events through `on_event`;
+    /// returns the reason to stop, if one arrived.
+    pub fn drain(
+        &mut self,
+        on_event: &mut impl FnMut(Event) -> Result<()>,
+    ) -> Result<Option<StopReason>> {
+        while let Ok(command) = self.rx.try_recv() {
+            match command {
+                Command::Pause => self.paused = true,
+                Command::Resume => self.paused = false,
+                Command::Stop(reason) => return Ok(Some(reason)),
+                Command::Report(event) => on_event(event)?,
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A connected [`Control`] and [`Inbox`].
+pub fn channel() -> (Control, Inbox) {
+    let (tx, rx) = mpsc::channel();
+
+    (Control { tx }, Inbox { rx, paused: false })
+}
+
+/// The error a stream ends with when its target is unreachable and it
+/// was told to fail rather than keep trying.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unreachable {
+    pub target: String,
+    pub refused: u64,
+}
+
+impl fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "nothing is listening on {} ({} sends refused)",
+            self.target, self.refused
+        )
+    }
+}
+
+impl Error for Unreachable {}
+
+/// Stream health since it started; `latency_ms` and `fps` cover the
+/// interval since the previous snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    /// Audio buffers (music) or screen frames (ambilight) captured.
+    pub frames_captured: u64,
+    /// Frames encoded and sent, each one or more packets.
+    pub frames_sent: u64,
+    pub packets_sent: u64,
+    /// Send ticks dropped because the loop fell behind.
+    pub ticks_skipped: u64,
+    /// Failed sends, not counting refusals.
+    pub send_errors: u64,
+    /// Sends refused because nothing listens on the target port.
+    pub refused: u64,
+    /// Most recent send error.
+    pub last_error: Option<String>,
+    /// Mean time from capturing data to first sending it.
+    pub latency_ms: Option<f64>,
+    /// Frames sent per second.
+    pub fps: f64,
+    /// Gain the music AGC applied last (averaged over bands in per-band
+    /// mode); absent when there's no AGC.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agc_gain: Option<f32>,
+}
+
+/// Watches a stream's refusal streak, reporting when it crosses the
+/// threshold and when it clears again.
+pub struct Reachability {
+    target: String,
+    threshold: u64,
+    fail: bool,
+    warned: bool,
+}
+
+impl Reachability {
+    /// Warn after `threshold` refusals in a row; with `fail`, the stream
+    /// should end instead (see [`Reachability::enforce`]).
+    pub fn new(target: &str, threshold: u64, fail: bool) -> Self {
+        Self {
+            target: target.to_string(),
+            threshold,
+            fail,
+            warned: false,
+        }
+    }
+
+    /// The event to report after a frame, if reachability changed.
+    pub fn check(&mut self, send: &SendStats) -> Option<Event> {
+        if !self.warned && send.refused_streak >= self.threshold {
+            self.warned = true;
+            return Some(Event::Unreachable {
+                target: self.target.clone(),
+                refused: send.refused_streak,
+            });
+        }
+
+        if self.warned && send.refused_streak == 0 {
+            self.warned = false;
+            return Some(Event::Reachable {
+                target: self.target.clone(),
+            });
+        }
+
+        None
+    }
+
+    /// Fail with [`Unreachable`] if the target is unreachable and the
+    /// stream was told not to keep trying.
+    pub fn enforce(&self, send: &SendStats) -> Result<(), Unreachable> {
+        if self.fail && self.warned {
+            return Err(Unreachable {
+                target: self.target.clone(),
+                refused: send.refused_streak,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The values a streamer sent last, for showing the stream live; clones
+/// share them.
+#[derive(Clone, Debug, Default)]
+pub struct Monitor {
+    latest: Arc<Mutex<Vec<f32>>>,
+}
+
+impl Monitor {
+    /// Copy of the last values sent (empty before the first send).
+    pub fn latest(&self) -> Vec<f32> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub fn record(&self, values: &[f32]) {
+        let mut latest = self.latest.lock().unwrap();
+        latest.clear();
+        latest.extend_from_slice(values);
+    }
+}
+
+/// Tracks send-loop timing: skipped ticks, frame rate and the
+/// capture-to-send latency.
+pub struct Meter {
+    period: Duration,
+    last_tick: Option<Instant>,
+    ticks_skipped: u64,
+    frames_sent: u64,
+    window_start: Instant,
+    window_frames: u64,
+    latency_total: Duration,
+    latency_samples: u32,
+    last_captured: Option<Instant>,
+}
+
+impl Meter {
+    /// A meter for a loop meant to tick every `period`.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            last_tick: None,
+            ticks_skipped: 0,
+            frames_sent: 0,
+            window_start: Instant::now(),
+            window_frames: 0,
+            latency_total: Duration::ZERO,
+            latency_samples: 0,
+            last_captured: None,
+        }
+    }
+
+    /// Record a tick scheduled for `at`; a gap of several periods since
+    /// the previous one means the ticks in between were skipped.
+    pub fn tick(&mut self, at: Instant) {
+        if let Some(last) = self.last_tick {
+            let periods = (at - last).as_secs_f64() / self.period.as_secs_f64();
+            self.ticks_skipped += (periods.round() as u64).saturating_sub(1);
+        }
+
+        self.last_tick = Some(at);
+    }
+
+    /// Record a frame sent at `now` from data captured at `captured_at`.
+    /// Only new data counts towards latency: a repeated frame (static
+    /// screen, stalled capture) isn't late, there's just nothing newer.
+    pub fn sent(&mut self, now: Instant, captured_at: Option<Instant>) {
+        self.frames_sent += 1;
+        self.window_frames += 1;
+
+        if let Some(captured_at) = captured_at
+            && self.last_captured != Some(captured_at)
+        {
+            self.last_captured = Some(captured_at);
+            self.latency_total += now.saturating_duration_since(captured_at);
+            self.latency_samples += 1;
+        }
+    }
+
+    /// Snapshot the counters at `now` and start a new interval.
+    pub fn report(&mut self, now: Instant, frames_captured: u64, send: &SendStats) -> Stats {
+        let elapsed = now
+            .saturating_duration_since(self.window_start)
+            .as_secs_f64();
+
+        let stats = Stats {
+            frames_captured,
+            frames_sent: self.frames_sent,
+            packets_sent: send.packets,
+            ticks_skipped: self.ticks_skipped,
+            send_errors: send.errors,
+            refused: send.refused,
+            last_error: send.last_error.clone(),
+            latency_ms: (self.latency_samples > 0)
+                .then(|| self.latency_total.as_secs_f64() * 1000.0 / self.latency_samples as f64),
+            fps: if elapsed > 0.0 {
+                self.window_frames as f64 / elapsed
+            } else {
+                0.0
+            },
+            agc_gain: None,
+        };
+
+        self.window_start = now;
+        self.window_frames = 0;
+        self.latency_total = Duration::ZERO;
+        self.latency_samples = 0;
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sighup_asks_for_a_reload() {
+        let mut signals = Signals::new().unwrap();
+
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        assert_eq!(signals.recv().await, Signal::Reload);
+    }
+
+    #[test]
+    fn inbox_applies_commands_in_order() {
+        let (control, mut inbox) = channel();
+        let mut events = Vec::new();
+
+        control.pause();
+        control.report(Event::Reclaimed {
+            preset: "Ambilight".to_string(),
+        });
+        let mut record = |event| {
+            events.push(event);
+            Ok(())
+        };
+        assert_eq!(inbox.drain(&mut record).unwrap(), None);
+        assert!(inbox.paused());
+        assert_eq!(events.len(), 1);
+
+        control.resume();
+        control.stop(StopReason::Preempted);
+        control.pause();
+        assert_eq!(
+            inbox.drain(&mut |_| Ok(())).unwrap(),
+            Some(StopReason::Preempted)
+        );
+        assert!(!inbox.paused());
+    }
+
+    #[test]
+    fn reachability_warns_once_and_recovers() {
+        let mut reachability = Reachability::new("host:5555", 3, false);
+        let streak = |refused_streak| SendStats {
+            refused_streak,
+            ..SendStats::default()
+        };
+
+        assert_eq!(reachability.check(&streak(2)), None);
+        assert_eq!(
+            reachability.check(&streak(3)),
+            Some(Event::Unreachable {
+                target: "host:5555".to_string(),
+                refused: 3,
+            })
+        );
+        assert_eq!(reachability.check(&streak(9)), None);
+        assert!(reachability.enforce(&streak(9)).is_ok());
+        assert_eq!(
+            reachability.check(&streak(0)),
+            Some(Event::Reachable {
+                target: "host:5555".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn failing_reachability_ends_the_stream() {
+        let mut reachability = Reachability::new("host:5555", 1, true);
+        let refused = SendStats {
+            refused_streak: 1,
+            ..SendStats::default()
+        };
+
+        assert!(reachability.enforce(&refused).is_ok());
+        reachability.check(&refused);
+        assert_eq!(
+            reachability.enforce(&refused),
+            Err(Unreachable {
+                target: "host:5555".to_string(),
+                refused: 1,
+            })
+        );
+    }
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn counts_skipped_ticks_from_gaps() {
+        let mut meter = Meter::new(PERIOD);
+        let start = Instant::now();
+
+        for n in [0, 1, 2, 5, 6] {
+            meter.tick(start + PERIOD * n);
+        }
+
+        let stats = meter.report(start, 0, &SendStats::default());
+        assert_eq!(stats.ticks_skipped, 2);
+    }
+
+    #[test]
+    fn reports_rate_and_latency_per_interval() {
+        let start = Instant::now();
+        let mut meter = Meter::new(PERIOD);
+        meter.window_start = start;
+
+        for n in 1..=50 {
+            let now = start + PERIOD * n;
+            meter.sent(now, Some(now - Duration::from_millis(4)));
+        }
+
+        // Resending old data doesn't count as latency.
+        let last = start + PERIOD * 50 - Duration::from_millis(4);
+        meter.sent(start + PERIOD * 50, Some(last));
+
+        let stats = meter.report(start + Duration::from_millis(500), 7, &SendStats::default());
+        assert_eq!(stats.frames_sent, 51);
+        assert_eq!(stats.frames_captured, 7);
+        assert!((stats.fps - 102.0).abs() < 1e-6, "fps {}", stats.fps);
+        assert!((stats.latency_ms.unwrap() - 4.0).abs() < 1e-6);
+
+        // The next interval starts from scratch; totals carry on.
+        let stats = meter.report(start + Duration::from_millis(600), 7, &SendStats::default());
+        assert_eq!(stats.frames_sent, 51);
+        assert_eq!(stats.fps, 0.0);
+        assert_eq!(stats.latency_ms, None);
+    }
+}
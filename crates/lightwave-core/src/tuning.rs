@@ -0,0 +1,173 @@
+//! Live-adjustable streamer parameters, shared between a running
+//! streamer and whatever steers it (daemon socket, keyboard, stdin).
+
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Serialize;
+
+/// One tunable value with its allowed range and a convenient step for
+/// nudging it up or down.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Param {
+    pub name: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+impl Param {
+    /// The range widens to include `value`, so a setting accepted at
+    /// startup can always be set back.
+    pub fn new(name: &'static str, value: f32, range: RangeInclusive<f32>, step: f32) -> Self {
+        Self {
+            name,
+            value,
+            min: range.start().min(value),
+            max: range.end().max(value),
+            step,
+        }
+    }
+}
+
+/// A handle on a streamer's tunable parameters; clones share them.
+///
+/// Every [`set`](Tuning::set) bumps a version number, so the streamer can
+/// cheaply check once per frame whether anything needs re-applying.
+#[derive(Clone, Debug)]
+pub struct Tuning {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    params: Vec<Param>,
+    version: u64,
+}
+
+impl Tuning {
+    pub fn new(params: Vec<Param>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner { params, version: 0 })),
+        }
+    }
+
+    /// Snapshot of all parameters, in declaration order.
+    pub fn params(&self) -> Vec<Param> {
+        self.inner.lock().unwrap().params.clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .params
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| param.value)
+    }
+
+    /// Set `name` to `value`, rejecting unknown names and values out of
+    /// range.
+    pub fn set(&self, name: &str, value: f32) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let names = param_names(&inner.params);
+
+        let param = inner
+            .params
+            .iter_mut()
+            .find(|param| param.name == name)
+            .ok_or_else(|| anyhow!("unknown parameter {name:?}; expected {names}"))?;
+
+        if !(param.min..=param.max).contains(&value) {
+            bail!(
+                "{name} must be in {}..={}, got {value}",
+                param.min,
+                param.max
+            );
+        }
+
+        param.value = value;
+        inner.version += 1;
+
+        Ok(())
+    }
+
+    /// Move `name` by `steps` of its step size, stopping at the ends of its
+    /// range; returns the new value.
+    pub fn nudge(&self, name: &str, steps: i32) -> Result<f32> {
+        let param = self
+            .params()
+            .into_iter()
+            .find(|param| param.name == name)
+            .ok_or_else(|| {
+                let names = param_names(&self.inner.lock().unwrap().params);
+                anyhow!("unknown parameter {name:?}; expected {names}")
+            })?;
+
+        // Snap to the step grid so repeated nudges don't drift.
+        let value = ((param.value / param.step).round() + steps as f32) * param.step;
+        let value = value.clamp(param.min, param.max);
+        self.set(name, value)?;
+
+        Ok(value)
+    }
+
+    /// Changes every time a parameter is set.
+    pub fn version(&self) -> u64 {
+        self.inner.lock().unwrap().version
+    }
+}
+
+fn param_names(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|param| param.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuning() -> Tuning {
+        Tuning::new(vec![
+            Param::new("gain", 4.0, 0.01..=100.0, 0.25),
+            Param::new("gamma", 2.2, 0.1..=5.0, 0.1),
+        ])
+    }
+
+    #[test]
+    fn set_is_shared_and_bumps_the_version() {
+        let tuning = tuning();
+        let handle = tuning.clone();
+
+        handle.set("gain", 8.0).unwrap();
+
+        assert_eq!(tuning.get("gain"), Some(8.0));
+        assert_eq!(tuning.version(), 1);
+    }
+
+    #[test]
+    fn nudge_steps_and_clamps() {
+        let tuning = tuning();
+
+        assert!((tuning.nudge("gamma", 3).unwrap() - 2.5).abs() < 1e-5);
+        assert!((tuning.nudge("gamma", -1).unwrap() - 2.4).abs() < 1e-5);
+        assert_eq!(tuning.nudge("gamma", 100).unwrap(), 5.0);
+        assert_eq!(tuning.nudge("gain", -1000).unwrap(), 0.01);
+        assert!(tuning.nudge("depth", 1).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_out_of_range_values() {
+        let tuning = tuning();
+
+        assert!(tuning.set("vividness", 1.0).is_err());
+        assert!(tuning.set("gamma", 0.0).is_err());
+        assert!(tuning.set("gain", f32::NAN).is_err());
+        assert_eq!(tuning.version(), 0);
+    }
+}
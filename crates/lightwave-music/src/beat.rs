@@ -0,0 +1,208 @@
+//! Beat detection: onsets from spectral flux over an adaptive threshold,
+//! and a tempo estimate from the intervals between them.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::dsp::remaining;
+
+/// Time constant of the flux mean and deviation the threshold follows.
+const FLUX_WINDOW: Duration = Duration::from_secs(1);
+
+/// Deviations above the mean flux an onset must reach.
+const SPREAD: f32 = 1.5;
+
+/// Flux an onset must exceed however quiet it's been, so hiss and
+/// rounding noise during silence don't count.
+const MIN_FLUX: f32 = 0.05;
+
+/// Shortest gap between onsets; one note's attack shouldn't fire twice.
+const REFRACTORY: Duration = Duration::from_millis(100);
+
+/// How far back onsets count towards the tempo.
+const TEMPO_WINDOW: Duration = Duration::from_secs(8);
+
+/// Tempos are folded into one octave, `MIN_BPM..2 * MIN_BPM`, so a
+/// skipped beat or an off-beat onset still votes for the right tempo.
+const MIN_BPM: f32 = 80.0;
+
+/// Votes within this many BPM of each other agree.
+const BPM_TOLERANCE: f32 = 2.0;
+
+/// Intervals needed before reporting a tempo.
+const MIN_INTERVALS: usize = 3;
+
+/// A detected beat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beat {
+    /// How far the onset cleared the threshold, 0..=1.
+    pub strength: f32,
+    /// Tempo once enough beats have been seen.
+    pub bpm: Option<f32>,
+    /// Share of recent beat intervals that agree with `bpm`, 0..=1.
+    pub confidence: f32,
+}
+
+pub struct Detector {
+    /// Compressed bin levels of the previous frame.
+    previous: Vec<f32>,
+    mean: f32,
+    deviation: f32,
+    /// Time since the first frame.
+    clock: Duration,
+    onsets: VecDeque<Duration>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            mean: 0.0,
+            deviation: 0.0,
+            clock: Duration::ZERO,
+            onsets: VecDeque::new(),
+        }
+    }
+
+    /// Look for an onset in `bins`, `elapsed` after the previous frame.
+    pub fn detect(&mut self, bins: &[f32], elapsed: Duration) -> Option<Beat> {
+        self.clock += elapsed;
+
+        // Log-compress so a loud bass doesn't drown out every other band.
+        let levels = bins.iter().map(|&bin| (1.0 + 10.0 * bin.max(0.0)).ln());
+
+        if self.previous.len() != bins.len() {
+            self.previous = levels.collect();
+            return None;
+        }
+
+        let mut flux = 0.0;
+        for (previous, level) in self.previous.iter_mut().zip(levels) {
+            flux += (level - *previous).max(0.0);
+            *previous = level;
+        }
+
+        let threshold = (self.mean + SPREAD * self.deviation).max(MIN_FLUX);
+        let ready = self
+            .onsets
+            .back()
+            .is_none_or(|&last| self.clock - last >= REFRACTORY);
+
+        let rate = remaining(FLUX_WINDOW, elapsed);
+        self.mean = flux + (self.mean - flux) * rate;
+        let distance = (flux - self.mean).abs();
+        self.deviation = distance + (self.deviation - distance) * rate;
+
+        if flux <= threshold || !ready {
+            return None;
+        }
+
+        self.onsets.push_back(self.clock);
+        while self
+            .onsets
+            .front()
+            .is_some_and(|&onset| self.clock - onset > TEMPO_WINDOW)
+        {
+            self.onsets.pop_front();
+        }
+
+        let (bpm, confidence) = match self.tempo() {
+            Some((bpm, confidence)) => (Some(bpm), confidence),
+            None => (None, 0.0),
+        };
+
+        Some(Beat {
+            strength: 1.0 - threshold / flux,
+            bpm,
+            confidence,
+        })
+    }
+
+    /// The tempo most recent intervals agree on, and the share that do.
+    fn tempo(&self) -> Option<(f32, f32)> {
+        let tempos: Vec<f32> = self
+            .onsets
+            .iter()
+            .zip(self.onsets.iter().skip(1))
+            .map(|(&a, &b)| fold(60.0 / (b - a).as_secs_f32()))
+            .collect();
+
+        if tempos.len() < MIN_INTERVALS {
+            return None;
+        }
+
+        let agreeing = |bpm: f32| {
+            tempos
+                .iter()
+                .filter(|&&other| (other - bpm).abs() <= BPM_TOLERANCE)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        let best = tempos
+            .iter()
+            .map(|&bpm| agreeing(bpm))
+            .max_by_key(Vec::len)?;
+
+        let bpm = best.iter().sum::<f32>() / best.len() as f32;
+        Some((bpm, best.len() as f32 / tempos.len() as f32))
+    }
+}
+
+/// Double or halve `bpm` into `MIN_BPM..2 * MIN_BPM`.
+fn fold(mut bpm: f32) -> f32 {
+    while bpm < MIN_BPM {
+        bpm *= 2.0;
+    }
+    while bpm >= 2.0 * MIN_BPM {
+        bpm /= 2.0;
+    }
+    bpm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `seconds` of 10 ms frames: quiet, with a kick every `period`.
+    fn kicks(detector: &mut Detector, period: Duration, seconds: u32) -> Vec<Beat> {
+        let frame = Duration::from_millis(10);
+        let every = (period.as_millis() / frame.as_millis()) as u32;
+
+        (0..seconds * 100)
+            .filter_map(|i| {
+                let level = if i % every == 0 { 0.9 } else { 0.02 };
+                detector.detect(&[level, level / 2.0, 0.01], frame)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_tempo_of_a_steady_kick() {
+        let mut detector = Detector::new();
+        let beats = kicks(&mut detector, Duration::from_millis(500), 8);
+
+        assert!(beats.len() >= 14, "found {} of 16 beats", beats.len());
+
+        let last = beats.last().unwrap();
+        let bpm = last.bpm.expect("a tempo by now");
+        assert!((bpm - 120.0).abs() < 1.0, "got {bpm} BPM");
+        assert!(last.confidence > 0.9, "confidence {}", last.confidence);
+        assert!(last.strength > 0.0 && last.strength <= 1.0);
+    }
+
+    #[test]
+    fn silence_has_no_beats() {
+        let mut detector = Detector::new();
+        let frame = Duration::from_millis(10);
+
+        assert!((0..500).all(|_| detector.detect(&[0.01; 4], frame).is_none()));
+    }
+
+    #[test]
+    fn folds_tempos_into_one_octave() {
+        assert_eq!(fold(60.0), 120.0);
+        assert_eq!(fold(240.0), 120.0);
+        assert_eq!(fold(100.0), 100.0);
+    }
+}
@@ -1,8 +1,11 @@
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow, bail};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Host, HostId, Sample, SampleFormat, StreamConfig};
+use cpal::{Device, FromSample, Host, HostId, Sample, SampleFormat, StreamConfig};
 
 pub fn list_devices() -> Result<Vec<String>> {
     let host = cpal::default_host();
@@ -18,18 +21,242 @@ pub fn list_devices() -> Result<Vec<String>> {
     Ok(devices)
 }
 
-/// Mono samples from a capture device, kept in a ring holding the most
-/// recent `fft_size` of them.
+/// Which channels of the device are analyzed, each into its own spectrum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channels {
+    /// All channels downmixed to one.
+    #[default]
+    Mono,
+    /// Left, then right.
+    Stereo,
+    Left,
+    Right,
+    /// Mid (L+R)/2, then side (L-R)/2: what's panned off-center.
+    MidSide,
+}
+
+impl Channels {
+    /// Spectra per frame.
+    pub fn count(self) -> usize {
+        match self {
+            Self::Mono | Self::Left | Self::Right => 1,
+            Self::Stereo | Self::MidSide => 2,
+        }
+    }
+
+    /// Split an interleaved frame into our channels; the first `count()`
+    /// are used. A mono device is both left and right.
+    fn split<S>(self, frame: &[S]) -> [f32; 2]
+    where
+        S: Sample,
+        f32: FromSample<S>,
+    {
+        let left = f32::from_sample(frame[0]);
+        let right = f32::from_sample(frame[frame.len().min(2) - 1]);
+
+        match self {
+            Self::Mono => {
+                let sum: f32 = frame.iter().map(|&s| f32::from_sample(s)).sum();
+                [sum / frame.len() as f32, 0.0]
+            }
+            Self::Stereo => [left, right],
+            Self::Left => [left, 0.0],
+            Self::Right => [right, 0.0],
+            Self::MidSide => [(left + right) / 2.0, (left - right) / 2.0],
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mono" => Ok(Self::Mono),
+            "stereo" => Ok(Self::Stereo),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "mid-side" => Ok(Self::MidSide),
+            _ => Err(format!(
+                "unknown channels {s:?}; expected mono, stereo, left, right or mid-side"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mono => "mono",
+            Self::Stereo => "stereo",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::MidSide => "mid-side",
+        })
+    }
+}
+
+/// Where the streamer's audio comes from: a capture device, a file,
+/// stdin or a test signal. Each fills a [`Feed`] as samples arrive.
+pub trait Source {
+    /// What's being captured, for display.
+    fn name(&self) -> &str;
+
+    fn sample_rate(&self) -> u32;
+
+    /// The rings the source fills.
+    fn feed(&self) -> &Feed;
+}
+
+/// A source's samples split into [`Channels`], each kept in a ring
+/// holding the most recent `fft_size` of them plus a second more, so a
+/// consumer that runs late can still analyze what arrived in between.
+/// Clones share the rings: one for the producer, one for the reader.
+#[derive(Clone)]
+pub struct Feed {
+    shared: Arc<(Mutex<Ring>, Condvar)>,
+    channels: Channels,
+}
+
+impl Feed {
+    /// Rings for `channels` at `sample_rate`. An `on_demand` feed's
+    /// producer waits for [`Feed::request`] before each write, for
+    /// sources read as fast as the analysis runs rather than in real
+    /// time.
+    pub(crate) fn new(
+        channels: Channels,
+        fft_size: usize,
+        sample_rate: u32,
+        on_demand: bool,
+    ) -> Self {
+        let mut ring = Ring::new(channels.count(), fft_size + sample_rate as usize);
+        ring.demand = on_demand.then_some(0);
+
+        Self {
+            shared: Arc::new((Mutex::new(ring), Condvar::new())),
+            channels,
+        }
+    }
+
+    fn ring(&self) -> MutexGuard<'_, Ring> {
+        self.shared.0.lock().unwrap()
+    }
+
+    /// Push interleaved frames of `width` channels; false once nothing
+    /// reads the feed any more.
+    pub(crate) fn push<S>(&self, data: &[S], width: usize) -> bool
+    where
+        S: Sample,
+        f32: FromSample<S>,
+    {
+        let mut ring = self.ring();
+        for frame in data.chunks_exact(width) {
+            ring.push(&self.channels.split(frame));
+        }
+        ring.buffers += 1;
+        ring.updated = Some(Instant::now());
+        drop(ring);
+
+        self.shared.1.notify_all();
+        Arc::strong_count(&self.shared) > 1
+    }
+
+    /// Mark the source exhausted, or failed.
+    pub(crate) fn end(&self, result: Result<()>) {
+        self.ring().ended = Some(result.map_err(|err| format!("{err:#}")));
+        self.shared.1.notify_all();
+    }
+
+    /// Block an on-demand feed's producer until more is requested; false
+    /// once nothing reads the feed any more.
+    pub(crate) fn wait_for_demand(&self) -> bool {
+        let (ring, wake) = &*self.shared;
+        let mut ring = ring.lock().unwrap();
+
+        while ring.demand.is_some_and(|demand| demand <= ring.written) {
+            if Arc::strong_count(&self.shared) == 1 {
+                return false;
+            }
+            // Time out now and then to notice the reader is gone.
+            ring = wake
+                .wait_timeout(ring, Duration::from_millis(100))
+                .unwrap()
+                .0;
+        }
+
+        true
+    }
+
+    /// Whether the producer waits for [`Feed::request`].
+    pub fn on_demand(&self) -> bool {
+        self.ring().demand.is_some()
+    }
+
+    /// Have an on-demand producer write `frames` more and wait for them;
+    /// returns how many arrived, fewer (down to 0) at the end.
+    pub fn request(&self, frames: u64) -> u64 {
+        let (ring, wake) = &*self.shared;
+        let mut ring = ring.lock().unwrap();
+        let start = ring.written;
+
+        ring.demand = Some(start + frames);
+        wake.notify_all();
+        while ring.written < start + frames && ring.ended.is_none() {
+            ring = wake.wait(ring).unwrap();
+        }
+
+        (ring.written - start).min(frames)
+    }
+
+    /// Set once the source has nothing more: a file played to its end, or
+    /// stdin closed. Errors are why it stopped early.
+    pub fn ended(&self) -> Option<Result<(), String>> {
+        self.ring().ended.clone()
+    }
+
+    /// Copy out `channel`'s newest `out.len()` samples, oldest first,
+    /// returning when they arrived (None before the first buffer).
+    pub fn snapshot(&self, channel: usize, out: &mut [f32]) -> Option<Instant> {
+        let ring = self.ring();
+        ring.copy_until(channel, ring.written, out);
+        ring.updated
+    }
+
+    /// Copy out `channel`'s `out.len()` samples before the `end`th sample
+    /// captured, oldest first; false if they've been overwritten or
+    /// haven't arrived yet.
+    pub fn window(&self, channel: usize, end: u64, out: &mut [f32]) -> bool {
+        self.ring().copy_until(channel, end, out)
+    }
+
+    /// Samples captured per channel so far, and when the latest arrived.
+    pub fn position(&self) -> (u64, Option<Instant>) {
+        let ring = self.ring();
+        (ring.written, ring.updated)
+    }
+
+    /// Buffers received so far.
+    pub fn buffers(&self) -> u64 {
+        self.ring().buffers
+    }
+}
+
+/// Samples from a capture device.
 pub struct Capture {
     // Dropping the stream stops the capture callbacks.
     _stream: cpal::Stream,
-    ring: Arc<Mutex<Ring>>,
+    feed: Feed,
     name: String,
     sample_rate: u32,
 }
 
 impl Capture {
-    pub fn open(filter: Option<&str>, sample_rate: Option<u32>, fft_size: usize) -> Result<Self> {
+    pub fn open(
+        filter: Option<&str>,
+        sample_rate: Option<u32>,
+        fft_size: usize,
+        channels: Channels,
+    ) -> Result<Self> {
         let host = cpal::default_host();
 
         let (device, name) = match filter {
@@ -39,48 +266,84 @@ impl Capture {
 
         let (config, format) = pick_config(&device, sample_rate)?;
 
-        let ring = Arc::new(Mutex::new(Ring {
-            samples: vec![0.0; fft_size],
-            pos: 0,
-        }));
+        let feed = Feed::new(channels, fft_size, config.sample_rate, false);
 
-        let stream = build_stream(&device, &config, format, Arc::clone(&ring))?;
+        let stream = build_stream(&device, &config, format, feed.clone())?;
         stream.play().context("starting audio capture stream")?;
 
         Ok(Self {
             _stream: stream,
-            ring,
+            feed,
             name,
             sample_rate: config.sample_rate,
         })
     }
+}
 
-    pub fn name(&self) -> &str {
+impl Source for Capture {
+    fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn sample_rate(&self) -> u32 {
+    fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
 
-    pub fn snapshot(&self, out: &mut [f32]) {
-        let ring = self.ring.lock().unwrap();
-        let (newer, older) = ring.samples.split_at(ring.pos);
-
-        out[..older.len()].copy_from_slice(older);
-        out[older.len()..].copy_from_slice(newer);
+    fn feed(&self) -> &Feed {
+        &self.feed
     }
 }
 
 struct Ring {
-    samples: Vec<f32>,
-    pos: usize,
+    /// One ring per channel, all the same length.
+    channels: Vec<Vec<f32>>,
+    /// Samples pushed per channel so far; the next go at `written % len`.
+    written: u64,
+    buffers: u64,
+    updated: Option<Instant>,
+    /// How far an on-demand producer should write; None for the rest.
+    demand: Option<u64>,
+    ended: Option<Result<(), String>>,
 }
 
 impl Ring {
-    fn push(&mut self, sample: f32) {
-        self.samples[self.pos] = sample;
-        self.pos = (self.pos + 1) % self.samples.len();
+    fn new(channels: usize, len: usize) -> Self {
+        Self {
+            channels: vec![vec![0.0; len]; channels],
+            written: 0,
+            buffers: 0,
+            updated: None,
+            demand: None,
+            ended: None,
+        }
+    }
+
+    /// Push one sample to each channel.
+    fn push(&mut self, frame: &[f32]) {
+        for (samples, &sample) in self.channels.iter_mut().zip(frame) {
+            let len = samples.len() as u64;
+            samples[(self.written % len) as usize] = sample;
+        }
+        self.written += 1;
+    }
+
+    /// See [`Feed::window`]. Before the first `len` samples, the ring's
+    /// initial silence stands in for what came before.
+    fn copy_until(&self, channel: usize, end: u64, out: &mut [f32]) -> bool {
+        let samples = &self.channels[channel];
+        let len = samples.len() as u64;
+        let count = out.len() as u64;
+        if end > self.written || end + len < self.written + count {
+            return false;
+        }
+
+        // end - count, kept from underflowing; only its remainder matters.
+        let start = ((end + len - count) % len) as usize;
+        let (tail, head) = samples.split_at(start);
+        let (front, back) = out.split_at_mut(head.len().min(out.len()));
+        front.copy_from_slice(&head[..front.len()]);
+        back.copy_from_slice(&tail[..back.len()]);
+        true
     }
 }
 
@@ -155,22 +418,17 @@ fn build_stream(
     device: &Device,
     config: &StreamConfig,
     format: SampleFormat,
-    ring: Arc<Mutex<Ring>>,
+    feed: Feed,
 ) -> Result<cpal::Stream> {
-    let channels = config.channels as usize;
+    let width = config.channels as usize;
     let err_fn = |err| eprintln!("audio stream error: {err}");
 
-    // Downmix interleaved frames to mono and feed the ring.
     macro_rules! stream_as {
         ($sample:ty) => {
             device.build_input_stream(
                 *config,
                 move |data: &[$sample], _: &cpal::InputCallbackInfo| {
-                    let mut ring = ring.lock().unwrap();
-                    for frame in data.chunks_exact(channels) {
-                        let sum: f32 = frame.iter().map(|&s| f32::from_sample(s)).sum();
-                        ring.push(sum / channels as f32);
-                    }
+                    feed.push(data, width);
                 },
                 err_fn,
                 None,
@@ -194,3 +452,41 @@ fn build_stream(
 
     stream.context("building audio capture stream")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_windows_end_anywhere_still_held() {
+        let mut ring = Ring::new(1, 4);
+        ring.push(&[1.0]);
+        ring.push(&[2.0]);
+
+        let mut out = [9.0; 3];
+        assert!(ring.copy_until(0, 2, &mut out));
+        assert_eq!(out, [0.0, 1.0, 2.0]);
+
+        for sample in 3..=6 {
+            ring.push(&[sample as f32]);
+        }
+
+        assert!(ring.copy_until(0, 6, &mut out));
+        assert_eq!(out, [4.0, 5.0, 6.0]);
+        assert!(ring.copy_until(0, 5, &mut out));
+        assert_eq!(out, [3.0, 4.0, 5.0]);
+        assert!(!ring.copy_until(0, 4, &mut out), "2.0 was overwritten");
+        assert!(!ring.copy_until(0, 7, &mut out), "7.0 hasn't arrived");
+    }
+
+    #[test]
+    fn splits_frames_into_channels() {
+        let frame = [0.5f32, -0.25];
+
+        assert_eq!(Channels::Mono.split(&frame)[0], 0.125);
+        assert_eq!(Channels::Stereo.split(&frame), [0.5, -0.25]);
+        assert_eq!(Channels::Right.split(&frame)[0], -0.25);
+        assert_eq!(Channels::MidSide.split(&frame), [0.125, 0.375]);
+        assert_eq!(Channels::Stereo.split(&[0.5f32]), [0.5, 0.5]);
+    }
+}
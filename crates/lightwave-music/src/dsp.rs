@@ -1,11 +1,395 @@
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Result, bail};
 use realfft::num_complex::Complex;
 use realfft::{RealFftPlanner, RealToComplex};
 
-/// Windowed FFT folded into log-spaced frequency bins normalized to 0..=1.
+/// How bin amplitudes map to output levels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scale {
+    /// Amplitude as is.
+    #[default]
+    Linear,
+    /// Decibels, with a floor..ceiling range mapped to 0..=1.
+    Db,
+    /// Square root: lifts quiet bins, less than decibels do.
+    Sqrt,
+}
+
+impl Scale {
+    /// Map `amplitude` to a level; 0..=1 covers the useful range, but
+    /// louder amplitudes may go past 1.
+    pub fn map(self, amplitude: f32, floor_db: f32, ceiling_db: f32) -> f32 {
+        self.map_level(amplitude, 20.0, floor_db, ceiling_db)
+    }
+
+    /// Like [`Scale::map`], for a power (squared amplitude), which is
+    /// 10·log10 of it in decibels.
+    pub fn map_power(self, power: f32, floor_db: f32, ceiling_db: f32) -> f32 {
+        self.map_level(power, 10.0, floor_db, ceiling_db)
+    }
+
+    fn map_level(self, level: f32, db_per_decade: f32, floor_db: f32, ceiling_db: f32) -> f32 {
+        match self {
+            Self::Linear => level,
+            Self::Sqrt => level.sqrt(),
+            Self::Db => {
+                let db = db_per_decade * level.log10();
+                ((db - floor_db) / (ceiling_db - floor_db)).max(0.0)
+            }
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "db" => Ok(Self::Db),
+            "sqrt" => Ok(Self::Sqrt),
+            _ => Err(format!("unknown scale {s:?}; expected linear, db or sqrt")),
+        }
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Linear => "linear",
+            Self::Db => "db",
+            Self::Sqrt => "sqrt",
+        })
+    }
+}
+
+/// How output bins divide the analyzed frequency range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FreqScale {
+    /// Equal frequency ratios per bin.
+    #[default]
+    Log,
+    /// Equal steps in mels, the pitch scale: near-linear below ~1 kHz,
+    /// logarithmic above.
+    Mel,
+    /// Equal steps in barks, the ear's critical bands.
+    Bark,
+    /// Equal steps in Hz.
+    Linear,
+    /// Fractional-octave bands from the lowest frequency up; the band
+    /// width, not a bin count, fixes how many there are.
+    Octave,
+}
+
+impl FreqScale {
+    /// Frequency in Hz to this scale's units.
+    fn warp(self, freq: f32) -> f32 {
+        match self {
+            Self::Log | Self::Octave => freq.ln(),
+            Self::Mel => 2595.0 * (1.0 + freq / 700.0).log10(),
+            // Traunmüller's approximation.
+            Self::Bark => 26.81 * freq / (1960.0 + freq) - 0.53,
+            Self::Linear => freq,
+        }
+    }
+
+    /// The inverse of [`FreqScale::warp`].
+    fn unwarp(self, value: f32) -> f32 {
+        match self {
+            Self::Log | Self::Octave => value.exp(),
+            Self::Mel => 700.0 * (10f32.powf(value / 2595.0) - 1.0),
+            Self::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+            Self::Linear => value,
+        }
+    }
+}
+
+impl FromStr for FreqScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(Self::Log),
+            "mel" => Ok(Self::Mel),
+            "bark" => Ok(Self::Bark),
+            "linear" => Ok(Self::Linear),
+            "octave" => Ok(Self::Octave),
+            _ => Err(format!(
+                "unknown scale type {s:?}; expected log, mel, bark, linear or octave"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FreqScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Log => "log",
+            Self::Mel => "mel",
+            Self::Bark => "bark",
+            Self::Linear => "linear",
+            Self::Octave => "octave",
+        })
+    }
+}
+
+/// How the FFT bins falling in one output bin combine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Reduction {
+    /// The loudest one: a pure tone shows at full strength however wide
+    /// the bin.
+    #[default]
+    Peak,
+    /// Mean power (squared amplitude); loud bins stand out more.
+    Power,
+    /// Root mean square: the bin's overall energy as an amplitude.
+    Rms,
+}
+
+impl FromStr for Reduction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peak" => Ok(Self::Peak),
+            "power" => Ok(Self::Power),
+            "rms" => Ok(Self::Rms),
+            _ => Err(format!(
+                "unknown reduction {s:?}; expected peak, power or rms"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Reduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Peak => "peak",
+            Self::Power => "power",
+            Self::Rms => "rms",
+        })
+    }
+}
+
+/// A standard loudness weighting curve.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Weighting {
+    #[default]
+    None,
+    /// IEC 61672 A-weighting: the ear at moderate levels; cuts deep bass
+    /// hard (-30 dB at 50 Hz).
+    A,
+    /// C-weighting: the ear at high levels; nearly flat, trimming only
+    /// the extremes.
+    C,
+}
+
+impl Weighting {
+    /// The curve's gain at `freq`, 0 dB at 1 kHz.
+    pub fn db(self, freq: f32) -> f32 {
+        const F1: f32 = 20.6;
+        const F2: f32 = 107.7;
+        const F3: f32 = 737.9;
+        const F4: f32 = 12_194.0;
+
+        let f2 = freq * freq;
+        match self {
+            Self::None => 0.0,
+            Self::A => {
+                let r = F4 * F4 * f2 * f2
+                    / ((f2 + F1 * F1) * ((f2 + F2 * F2) * (f2 + F3 * F3)).sqrt() * (f2 + F4 * F4));
+                20.0 * r.log10() + 2.0
+            }
+            Self::C => {
+                let r = F4 * F4 * f2 / ((f2 + F1 * F1) * (f2 + F4 * F4));
+                20.0 * r.log10() + 0.06
+            }
+        }
+    }
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "a" => Ok(Self::A),
+            "c" => Ok(Self::C),
+            _ => Err(format!("unknown weighting {s:?}; expected none, a or c")),
+        }
+    }
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::A => "a",
+            Self::C => "c",
+        })
+    }
+}
+
+/// Shape of the window the FFT sees, trading frequency resolution for
+/// leakage and amplitude accuracy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Window {
+    /// A good all-rounder.
+    #[default]
+    Hann,
+    /// Narrower peaks than Hann, but more leakage far from them.
+    Hamming,
+    /// 4-term Blackman-Harris: wide peaks, almost no leakage, so quiet
+    /// bands next to loud ones stay dark.
+    BlackmanHarris,
+    /// Widest peaks, but a tone reads at its true amplitude wherever it
+    /// falls between FFT bins.
+    FlatTop,
+    /// Kaiser with β = [`KAISER_BETA`], close to Blackman-Harris.
+    Kaiser,
+}
+
+/// Kaiser window shape: higher is wider with less leakage.
+pub const KAISER_BETA: f32 = 8.6;
+
+impl Window {
+    /// The periodic window of `size` samples.
+    fn coefficients(self, size: usize) -> Vec<f32> {
+        // Sum of cosines: a0 - a1 cos(x) + a2 cos(2x) - ...
+        let cosines = |terms: &[f32]| -> Vec<f32> {
+            (0..size)
+                .map(|i| {
+                    let x = 2.0 * PI * i as f32 / size as f32;
+                    terms
+                        .iter()
+                        .enumerate()
+                        .map(|(k, a)| {
+                            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                            sign * a * (k as f32 * x).cos()
+                        })
+                        .sum()
+                })
+                .collect()
+        };
+
+        match self {
+            Self::Hann => cosines(&[0.5, 0.5]),
+            Self::Hamming => cosines(&[0.54, 0.46]),
+            Self::BlackmanHarris => cosines(&[0.35875, 0.48829, 0.14128, 0.01168]),
+            Self::FlatTop => cosines(&[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_16,
+                0.083_578_95,
+                0.006_947_37,
+            ]),
+            Self::Kaiser => {
+                let half = size as f32 / 2.0;
+                (0..size)
+                    .map(|i| {
+                        let r = (i as f32 - half) / half;
+                        bessel_i0(KAISER_BETA * (1.0 - r * r).max(0.0).sqrt())
+                            / bessel_i0(KAISER_BETA)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Modified Bessel function of the first kind, order 0, by its series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / (2.0 * k as f32)).powi(2);
+        sum += term;
+        if term < sum * 1e-9 {
+            break;
+        }
+    }
+    sum
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hann" => Ok(Self::Hann),
+            "hamming" => Ok(Self::Hamming),
+            "blackman-harris" => Ok(Self::BlackmanHarris),
+            "flat-top" => Ok(Self::FlatTop),
+            "kaiser" => Ok(Self::Kaiser),
+            _ => Err(format!(
+                "unknown window {s:?}; expected hann, hamming, blackman-harris, flat-top or kaiser"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Hann => "hann",
+            Self::Hamming => "hamming",
+            Self::BlackmanHarris => "blackman-harris",
+            Self::FlatTop => "flat-top",
+            Self::Kaiser => "kaiser",
+        })
+    }
+}
+
+/// Where the output bins sit in the spectrum and how each is measured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    /// Number of bins, except with [`FreqScale::Octave`].
+    pub bins: usize,
+    pub min_freq: f32,
+    pub max_freq: f32,
+    pub scale: FreqScale,
+    /// Bands per octave with [`FreqScale::Octave`] (3 for third-octave).
+    pub octave_fraction: u32,
+    pub reduction: Reduction,
+}
+
+impl Layout {
+    /// Bin edges in Hz from `min_freq` to `max_freq`, one more than there
+    /// are bins.
+    fn edges(&self, max_freq: f32) -> Vec<f32> {
+        let min_freq = self.min_freq;
+
+        if self.scale == FreqScale::Octave {
+            let fraction = self.octave_fraction as f32;
+            // Forgive rounding error: 40 Hz to 10240 Hz is 8 octaves, not 8.0001.
+            let bands = ((max_freq / min_freq).log2() * fraction - 1e-3)
+                .ceil()
+                .max(1.0) as usize;
+
+            return (0..=bands)
+                .map(|band| (min_freq * 2f32.powf(band as f32 / fraction)).min(max_freq))
+                .collect();
+        }
+
+        let (lo, hi) = (self.scale.warp(min_freq), self.scale.warp(max_freq));
+        (0..=self.bins)
+            .map(|i| {
+                self.scale
+                    .unwarp(lo + (hi - lo) * i as f32 / self.bins as f32)
+            })
+            .collect()
+    }
+}
+
+/// Windowed FFT folded into frequency bins (see [`Layout`]): sine
+/// amplitude times the gain, on the chosen [`Scale`], not yet clamped
+/// (see [`Agc`]).
 pub struct Analyzer {
     fft: Arc<dyn RealToComplex<f32>>,
     window: Vec<f32>,
@@ -14,29 +398,34 @@ pub struct Analyzer {
     scratch: Vec<Complex<f32>>,
     /// Half-open FFT-bin ranges, one per output bin.
     ranges: Vec<(usize, usize)>,
+    reduction: Reduction,
+    /// Center frequency of each output bin in Hz.
+    centers: Vec<f32>,
+    /// Per-bin amplitude factors from [`Analyzer::set_weighting`].
+    weights: Vec<f32>,
     output: Vec<f32>,
     /// 2 / sum(window): maps a windowed peak back to sine amplitude.
     norm: f32,
     gain: f32,
+    scale: Scale,
+    floor_db: f32,
+    ceiling_db: f32,
 }
 
 impl Analyzer {
-    pub fn new(
-        fft_size: usize,
-        sample_rate: u32,
-        bins: usize,
-        min_freq: f32,
-        max_freq: f32,
-        gain: f32,
-    ) -> Result<Self> {
+    pub fn new(fft_size: usize, sample_rate: u32, layout: &Layout, gain: f32) -> Result<Self> {
         let fft = RealFftPlanner::<f32>::new().plan_fft_forward(fft_size);
 
-        let window: Vec<f32> = (0..fft_size)
-            .map(|i| 0.5 * (1.0 - (2.0 * PI * i as f32 / fft_size as f32).cos()))
-            .collect();
+        let window = Window::default().coefficients(fft_size);
         let norm = 2.0 / window.iter().sum::<f32>();
 
-        let ranges = bin_ranges(fft_size, sample_rate, bins, min_freq, max_freq)?;
+        let ranges = bin_ranges(fft_size, sample_rate, layout)?;
+
+        let hz_per_bin = sample_rate as f32 / fft_size as f32;
+        let centers = ranges
+            .iter()
+            .map(|&(lo, hi)| (lo as f32 * hi.max(lo + 1) as f32).sqrt() * hz_per_bin)
+            .collect();
 
         Ok(Self {
             input: fft.make_input_vec(),
@@ -44,13 +433,56 @@ impl Analyzer {
             scratch: fft.make_scratch_vec(),
             fft,
             window,
+            output: vec![0.0; ranges.len()],
+            weights: vec![1.0; ranges.len()],
             ranges,
-            output: vec![0.0; bins],
+            reduction: layout.reduction,
+            centers,
             norm,
             gain,
+            scale: Scale::Linear,
+            floor_db: -60.0,
+            ceiling_db: 0.0,
         })
     }
 
+    /// Map amplitudes through `scale`; `floor_db..ceiling_db` is the
+    /// decibel range shown as 0..=1 by [`Scale::Db`].
+    pub fn with_scale(mut self, scale: Scale, floor_db: f32, ceiling_db: f32) -> Self {
+        self.scale = scale;
+        self.floor_db = floor_db;
+        self.ceiling_db = ceiling_db;
+        self
+    }
+
+    /// Window the samples with `window` instead of Hann.
+    pub fn with_window(mut self, window: Window) -> Self {
+        self.window = window.coefficients(self.window.len());
+        self.norm = 2.0 / self.window.iter().sum::<f32>();
+        self
+    }
+
+    /// Output bins per frame; with octave bands, set by the layout.
+    pub fn bins(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Center frequency of each output bin in Hz.
+    pub fn centers(&self) -> &[f32] {
+        &self.centers
+    }
+
+    /// Weight each bin's amplitude by `db` at its center frequency.
+    pub fn set_weighting(&mut self, db: impl Fn(f32) -> f32) {
+        for (weight, &freq) in self.weights.iter_mut().zip(&self.centers) {
+            *weight = 10f32.powf(db(freq) / 20.0);
+        }
+    }
+
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+
     pub fn analyze(&mut self, samples: &[f32]) -> &[f32] {
         for ((dst, &sample), &w) in self.input.iter_mut().zip(samples).zip(&self.window) {
             *dst = sample * w;
@@ -60,28 +492,294 @@ impl Analyzer {
             .process_with_scratch(&mut self.input, &mut self.spectrum, &mut self.scratch)
             .expect("buffer lengths are fixed at construction");
 
-        for (out, &(lo, hi)) in self.output.iter_mut().zip(&self.ranges) {
-            let peak = self.spectrum[lo..hi]
-                .iter()
-                .map(|c| c.norm())
-                .fold(0.0f32, f32::max);
+        for ((out, &(lo, hi)), &weight) in
+            self.output.iter_mut().zip(&self.ranges).zip(&self.weights)
+        {
+            let bucket = &self.spectrum[lo..hi];
+            let mean_square =
+                || bucket.iter().map(|c| c.norm_sqr()).sum::<f32>() / bucket.len().max(1) as f32;
+
+            let amplitude = self.norm
+                * weight
+                * match self.reduction {
+                    Reduction::Peak => bucket.iter().map(|c| c.norm()).fold(0.0f32, f32::max),
+                    Reduction::Power | Reduction::Rms => mean_square().sqrt(),
+                };
 
-            *out = (peak * self.norm * self.gain).clamp(0.0, 1.0);
+            // Gain scales the level as sent, so squaring power leaves it be.
+            *out = match self.reduction {
+                Reduction::Power => self.scale.map_power(
+                    amplitude * amplitude * self.gain,
+                    self.floor_db,
+                    self.ceiling_db,
+                ),
+                Reduction::Peak | Reduction::Rms => {
+                    self.scale
+                        .map(amplitude * self.gain, self.floor_db, self.ceiling_db)
+                }
+            };
         }
 
         &self.output
     }
 }
 
-fn bin_ranges(
-    fft_size: usize,
-    sample_rate: u32,
-    bins: usize,
-    min_freq: f32,
-    max_freq: f32,
-) -> Result<Vec<(usize, usize)>> {
+/// How long the AGC's level estimate takes to rise to a louder passage;
+/// short, so a sudden loud passage doesn't clip for long.
+const AGC_ATTACK: Duration = Duration::from_millis(100);
+
+/// How long it takes to fall after the music gets quieter; long, so the
+/// gain doesn't pump between beats.
+const AGC_RELEASE: Duration = Duration::from_secs(5);
+
+/// Which levels the AGC normalizes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AgcMode {
+    /// Fixed gain only.
+    #[default]
+    Off,
+    /// One gain for the whole spectrum, from its loudest bin.
+    Global,
+    /// A gain per bin, so every band reaches the target on its own.
+    PerBand,
+}
+
+impl FromStr for AgcMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "global" => Ok(Self::Global),
+            "per-band" => Ok(Self::PerBand),
+            _ => Err(format!(
+                "unknown agc mode {s:?}; expected off, global or per-band"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AgcMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Global => "global",
+            Self::PerBand => "per-band",
+        })
+    }
+}
+
+/// Automatic gain control: tracks a rolling peak level (globally or per
+/// bin) and scales bins so it sits at the target, within
+/// `1/max_gain..=max_gain`. Output is clamped to 0..=1 in every mode.
+pub struct Agc {
+    mode: AgcMode,
+    target: f32,
+    max_gain: f32,
+    /// Rolling peak estimates: one, or one per bin.
+    levels: Vec<f32>,
+    gains: Vec<f32>,
+    output: Vec<f32>,
+}
+
+impl Agc {
+    pub fn new(mode: AgcMode, bins: usize, target: f32, max_gain: f32) -> Self {
+        let tracked = match mode {
+            AgcMode::Off => 0,
+            AgcMode::Global => 1,
+            AgcMode::PerBand => bins,
+        };
+
+        Self {
+            mode,
+            target,
+            max_gain,
+            // Start from the target, i.e. unity gain, and adapt from there.
+            levels: vec![target; tracked],
+            gains: vec![1.0; tracked],
+            output: vec![0.0; bins],
+        }
+    }
+
+    /// The gain applied last, averaged over bins in per-band mode; None
+    /// when off.
+    pub fn gain(&self) -> Option<f32> {
+        if self.gains.is_empty() {
+            return None;
+        }
+
+        Some(self.gains.iter().sum::<f32>() / self.gains.len() as f32)
+    }
+
+    /// Normalize `input`, `elapsed` after the previous call.
+    pub fn apply(&mut self, input: &[f32], elapsed: Duration) -> &[f32] {
+        let attack = remaining(AGC_ATTACK, elapsed);
+        let release = remaining(AGC_RELEASE, elapsed);
+        let min_gain = self.max_gain.recip();
+
+        let track = |level: &mut f32, gain: &mut f32, peak: f32| {
+            let rate = if peak > *level { attack } else { release };
+            *level = peak + (*level - peak) * rate;
+            *gain = (self.target / *level).clamp(min_gain, self.max_gain);
+        };
+
+        match self.mode {
+            AgcMode::Off => {}
+            AgcMode::Global => {
+                let peak = input.iter().copied().fold(0.0, f32::max);
+                track(&mut self.levels[0], &mut self.gains[0], peak);
+            }
+            AgcMode::PerBand => {
+                for ((level, gain), &peak) in self.levels.iter_mut().zip(&mut self.gains).zip(input)
+                {
+                    track(level, gain, peak);
+                }
+            }
+        }
+
+        for (i, (out, &value)) in self.output.iter_mut().zip(input).enumerate() {
+            let gain = match self.mode {
+                AgcMode::Off => 1.0,
+                AgcMode::Global => self.gains[0],
+                AgcMode::PerBand => self.gains[i],
+            };
+            *out = (value * gain).clamp(0.0, 1.0);
+        }
+
+        &self.output
+    }
+}
+
+/// Per-bin envelope follower: levels rise with the attack time constant
+/// and fall with the release one, measured in wall time so the result
+/// doesn't depend on how often it runs. With peak hold, each bin's peak
+/// stays put for the hold time before falling at the release rate.
+pub struct Smoother {
+    attack: Duration,
+    release: Duration,
+    peak_hold: Option<Duration>,
+    levels: Vec<f32>,
+    /// Held peaks and how long each has been held.
+    peaks: Vec<(f32, Duration)>,
+    output: Vec<f32>,
+}
+
+impl Smoother {
+    pub fn new(
+        bins: usize,
+        attack: Duration,
+        release: Duration,
+        peak_hold: Option<Duration>,
+    ) -> Self {
+        Self {
+            attack,
+            release,
+            peak_hold,
+            levels: vec![0.0; bins],
+            peaks: vec![(0.0, Duration::ZERO); bins],
+            output: vec![0.0; bins],
+        }
+    }
+
+    pub fn set_attack(&mut self, attack: Duration) {
+        self.attack = attack;
+    }
+
+    pub fn set_release(&mut self, release: Duration) {
+        self.release = release;
+    }
+
+    /// Move every bin toward `input`, `elapsed` after the previous call.
+    pub fn apply(&mut self, input: &[f32], elapsed: Duration) -> &[f32] {
+        let attack = remaining(self.attack, elapsed);
+        let release = remaining(self.release, elapsed);
+
+        for (((&target, level), (peak, held)), out) in input
+            .iter()
+            .zip(&mut self.levels)
+            .zip(&mut self.peaks)
+            .zip(&mut self.output)
+        {
+            let rate = if target > *level { attack } else { release };
+            *level = target + (*level - target) * rate;
+
+            *out = match self.peak_hold {
+                None => *level,
+                Some(hold) => {
+                    if *level >= *peak {
+                        *peak = *level;
+                        *held = Duration::ZERO;
+                    } else if *held < hold {
+                        *held += elapsed;
+                    } else {
+                        *peak = *level + (*peak - *level) * release;
+                    }
+                    *peak
+                }
+            };
+        }
+
+        &self.output
+    }
+}
+
+/// Noise gate with hysteresis: opens once the input reaches the
+/// threshold and closes only once it falls `hysteresis` dB below, so a
+/// level hovering at the threshold doesn't flicker.
+pub struct Gate {
+    open_db: f32,
+    close_db: f32,
+    open: bool,
+}
+
+impl Gate {
+    /// A closed gate opening at `threshold_db` dBFS.
+    pub fn new(threshold_db: f32, hysteresis_db: f32) -> Self {
+        Self {
+            open_db: threshold_db,
+            close_db: threshold_db - hysteresis_db,
+            open: false,
+        }
+    }
+
+    /// Follow an RMS amplitude (1.0 = full scale); whether the gate is
+    /// open now.
+    pub fn update(&mut self, rms: f32) -> bool {
+        let db = 20.0 * rms.max(1e-10).log10();
+
+        if self.open {
+            self.open = db >= self.close_db;
+        } else {
+            self.open = db >= self.open_db;
+        }
+
+        self.open
+    }
+}
+
+/// Root mean square of `samples`.
+pub(crate) fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Fraction of the distance to its target a one-pole filter with time
+/// constant `tau` still has to go after `elapsed`.
+pub(crate) fn remaining(tau: Duration, elapsed: Duration) -> f32 {
+    if tau.is_zero() {
+        return 0.0;
+    }
+
+    (-elapsed.as_secs_f32() / tau.as_secs_f32()).exp()
+}
+
+fn bin_ranges(fft_size: usize, sample_rate: u32, layout: &Layout) -> Result<Vec<(usize, usize)>> {
     let nyquist = sample_rate as f32 / 2.0;
-    let max_freq = max_freq.min(nyquist);
+    let min_freq = layout.min_freq;
+    let max_freq = layout.max_freq.min(nyquist);
 
     if min_freq >= max_freq {
         bail!(
@@ -89,17 +787,27 @@ fn bin_ranges(
         );
     }
 
+    let edges = layout.edges(max_freq);
+    let bins = edges.len() - 1;
+
+    // Past fft_size/2 usable bins, trailing ranges collapse to empty
+    let usable = fft_size / 2;
+    if bins > usable {
+        bail!(
+            "{bins} bins exceed the {usable} FFT bins available at fft-size {fft_size}; \
+             use fewer bins or raise fft-size"
+        );
+    }
+
     let spectrum_len = fft_size / 2 + 1;
     let hz_per_bin = sample_rate as f32 / fft_size as f32;
     // Skip the DC bin; it only encodes the signal's mean.
     let fft_bin = |freq: f32| ((freq / hz_per_bin) as usize).clamp(1, fft_size / 2);
 
-    let ratio = max_freq / min_freq;
     let mut ranges = Vec::with_capacity(bins);
     let mut lo = fft_bin(min_freq);
 
-    for i in 0..bins {
-        let edge = min_freq * ratio.powf((i + 1) as f32 / bins as f32);
+    for &edge in &edges[1..] {
         // Give every output bin at least one FFT bin, sharing the top one
         // when the FFT is too small to resolve them all.
         let hi = fft_bin(edge).max(lo + 1).min(spectrum_len).max(lo);
@@ -115,6 +823,17 @@ fn bin_ranges(
 mod tests {
     use super::*;
 
+    fn layout(bins: usize, min_freq: f32, max_freq: f32) -> Layout {
+        Layout {
+            bins,
+            min_freq,
+            max_freq,
+            scale: FreqScale::Log,
+            octave_fraction: 3,
+            reduction: Reduction::Peak,
+        }
+    }
+
     #[test]
     fn sine_lands_in_the_expected_bin() {
         let sample_rate = 48_000;
@@ -122,8 +841,13 @@ mod tests {
         let bins = 32;
         let (min_freq, max_freq) = (40.0f32, 16_000.0f32);
 
-        let mut analyzer =
-            Analyzer::new(fft_size, sample_rate, bins, min_freq, max_freq, 1.0).unwrap();
+        let mut analyzer = Analyzer::new(
+            fft_size,
+            sample_rate,
+            &layout(bins, min_freq, max_freq),
+            1.0,
+        )
+        .unwrap();
 
         let freq = 440.0f32;
         let samples: Vec<f32> = (0..fft_size)
@@ -152,10 +876,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn windows_trade_leakage_for_amplitude_accuracy() {
+        let (sample_rate, fft_size) = (48_000, 2048);
+        // Halfway between two FFT bins: the worst case for scalloping.
+        let freq = 43.5 * sample_rate as f32 / fft_size as f32;
+        let samples: Vec<f32> = (0..fft_size)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect();
+
+        // One bin around the tone and one far above it.
+        let measure = |window| {
+            let layout = Layout {
+                scale: FreqScale::Linear,
+                ..layout(2, 900.0, 7100.0)
+            };
+            let mut analyzer = Analyzer::new(fft_size, sample_rate, &layout, 1.0)
+                .unwrap()
+                .with_window(window);
+            let output = analyzer.analyze(&samples);
+            (output[0], 20.0 * output[1].log10())
+        };
+
+        let (hann, _) = measure(Window::Hann);
+        let (flat_top, _) = measure(Window::FlatTop);
+        assert!(hann < 0.9, "Hann scallops, got {hann}");
+        assert!((flat_top - 1.0).abs() < 0.01, "flat-top got {flat_top}");
+
+        let (_, hamming) = measure(Window::Hamming);
+        let (_, blackman_harris) = measure(Window::BlackmanHarris);
+        let (_, kaiser) = measure(Window::Kaiser);
+        assert!(hamming > -90.0, "Hamming leaks, got {hamming} dB");
+        assert!(blackman_harris < -90.0, "got {blackman_harris} dB");
+        assert!(kaiser < -80.0, "got {kaiser} dB");
+    }
+
+    #[test]
+    fn db_scale_maps_the_range_to_unit() {
+        let db = |amplitude| Scale::Db.map(amplitude, -60.0, 0.0);
+
+        assert!((db(1.0) - 1.0).abs() < 1e-6);
+        assert!((db(10f32.powf(-30.0 / 20.0)) - 0.5).abs() < 1e-6);
+        assert_eq!(db(1e-4), 0.0);
+        assert_eq!(db(0.0), 0.0);
+        assert!(db(2.0) > 1.0, "headroom above the ceiling is kept");
+        assert_eq!(Scale::Sqrt.map(0.25, -60.0, 0.0), 0.5);
+    }
+
+    #[test]
+    fn agc_brings_quiet_and_loud_music_to_the_target() {
+        let mut agc = Agc::new(AgcMode::Global, 2, 0.8, 10.0);
+        assert_eq!(agc.gain(), Some(1.0));
+
+        // A minute of quiet music: long past the release time.
+        for _ in 0..600 {
+            agc.apply(&[0.2, 0.1], Duration::from_millis(100));
+        }
+        let quiet = agc.apply(&[0.2, 0.1], Duration::ZERO).to_vec();
+        assert!((quiet[0] - 0.8).abs() < 0.01, "got {quiet:?}");
+        assert!((quiet[1] - 0.4).abs() < 0.01, "global keeps the balance");
+
+        for _ in 0..20 {
+            agc.apply(&[4.0, 2.0], Duration::from_millis(100));
+        }
+        let loud = agc.apply(&[4.0, 2.0], Duration::ZERO)[0];
+        assert!((loud - 0.8).abs() < 0.01, "got {loud}");
+    }
+
+    #[test]
+    fn agc_gain_is_bounded() {
+        let mut agc = Agc::new(AgcMode::PerBand, 2, 0.8, 4.0);
+
+        for _ in 0..600 {
+            agc.apply(&[0.01, 100.0], Duration::from_millis(100));
+        }
+
+        assert_eq!(agc.gains, [4.0, 0.25]);
+        assert_eq!(agc.apply(&[0.01, 100.0], Duration::ZERO), [0.04, 1.0]);
+        assert!(Agc::new(AgcMode::Off, 2, 0.8, 4.0).gain().is_none());
+    }
+
+    #[test]
+    fn smoothing_follows_wall_time_not_call_rate() {
+        let ms = Duration::from_millis;
+        let mut fast = Smoother::new(1, Duration::ZERO, ms(100), None);
+        let mut slow = Smoother::new(1, Duration::ZERO, ms(100), None);
+
+        // Zero attack jumps straight to the input.
+        assert_eq!(fast.apply(&[1.0], ms(10)), [1.0]);
+        assert_eq!(slow.apply(&[1.0], ms(50)), [1.0]);
+
+        for _ in 0..10 {
+            fast.apply(&[0.0], ms(10));
+        }
+        let fast = fast.apply(&[0.0], ms(0))[0];
+        let slow = slow.apply(&[0.0], ms(100))[0];
+
+        // One time constant in: 1/e of the way left to fall.
+        assert!((fast - (-1.0f32).exp()).abs() < 1e-4, "got {fast}");
+        assert!((fast - slow).abs() < 1e-4, "{fast} vs {slow}");
+    }
+
+    #[test]
+    fn zero_times_pass_levels_through() {
+        let ms = Duration::from_millis;
+        let mut smoother = Smoother::new(2, Duration::ZERO, Duration::ZERO, None);
+
+        assert_eq!(smoother.apply(&[0.3, 0.9], ms(16)), [0.3, 0.9]);
+        assert_eq!(smoother.apply(&[0.7, 0.1], ms(16)), [0.7, 0.1]);
+        assert_eq!(smoother.apply(&[0.2, 0.5], ms(0)), [0.2, 0.5]);
+    }
+
+    #[test]
+    fn peak_hold_waits_before_falling() {
+        let ms = Duration::from_millis;
+        let mut smoother = Smoother::new(1, Duration::ZERO, Duration::ZERO, Some(ms(200)));
+
+        assert_eq!(smoother.apply(&[0.8], ms(10)), [0.8]);
+        assert_eq!(smoother.apply(&[0.1], ms(100)), [0.8]);
+        assert_eq!(smoother.apply(&[0.1], ms(100)), [0.8]);
+        // Held long enough; zero release drops straight to the level.
+        assert_eq!(smoother.apply(&[0.1], ms(100)), [0.1]);
+    }
+
+    #[test]
+    fn gate_holds_open_through_the_hysteresis() {
+        let mut gate = Gate::new(-50.0, 6.0);
+        let level = |db: f32| 10f32.powf(db / 20.0);
+
+        assert!(!gate.update(level(-52.0)), "starts closed");
+        assert!(gate.update(level(-49.0)));
+        assert!(gate.update(level(-55.0)), "still within the hysteresis");
+        assert!(!gate.update(level(-57.0)));
+        assert!(!gate.update(level(-52.0)), "reopens only at the threshold");
+        assert!(!gate.update(0.0));
+        assert!((rms(&[0.5, -0.5, 0.5, -0.5]) - 0.5).abs() < 1e-6);
+    }
+
     #[test]
     fn ranges_are_contiguous_and_in_bounds() {
         let fft_size = 1024;
-        let ranges = bin_ranges(fft_size, 44_100, 48, 40.0, 16_000.0).unwrap();
+        let ranges = bin_ranges(fft_size, 44_100, &layout(48, 40.0, 16_000.0)).unwrap();
 
         let mut prev_hi = ranges[0].0;
         for &(lo, hi) in &ranges {
@@ -168,6 +1029,151 @@ mod tests {
 
     #[test]
     fn rejects_min_freq_above_nyquist() {
-        assert!(bin_ranges(1024, 8_000, 16, 5_000.0, 16_000.0).is_err());
+        assert!(bin_ranges(1024, 8_000, &layout(16, 5_000.0, 16_000.0)).is_err());
+    }
+
+    #[test]
+    fn scales_span_the_range_in_order() {
+        for scale in [
+            FreqScale::Log,
+            FreqScale::Mel,
+            FreqScale::Bark,
+            FreqScale::Linear,
+        ] {
+            let edges = Layout {
+                scale,
+                ..layout(24, 40.0, 16_000.0)
+            }
+            .edges(16_000.0);
+
+            assert_eq!(edges.len(), 25);
+            assert!(
+                (edges[0] - 40.0).abs() < 0.01,
+                "{scale} starts at {}",
+                edges[0]
+            );
+            assert!(
+                (edges[24] - 16_000.0).abs() < 1.0,
+                "{scale} ends at {}",
+                edges[24]
+            );
+            assert!(edges.windows(2).all(|pair| pair[0] < pair[1]), "{scale}");
+        }
+
+        // Log puts over half the bins below 1 kHz; mel gives the bass
+        // fewer, wider ones.
+        let mel = Layout {
+            scale: FreqScale::Mel,
+            ..layout(24, 40.0, 16_000.0)
+        }
+        .edges(16_000.0);
+        assert!(mel[12] > 1_000.0);
+    }
+
+    #[test]
+    fn octave_bands_fix_the_bin_count() {
+        let third = Layout {
+            scale: FreqScale::Octave,
+            ..layout(32, 100.0, 800.0)
+        };
+        let edges = third.edges(800.0);
+
+        assert_eq!(edges.len(), 10, "3 octaves of thirds: {edges:?}");
+        assert!((edges[3] - 200.0).abs() < 0.01);
+
+        let sixth = Layout {
+            octave_fraction: 6,
+            ..third
+        };
+        assert_eq!(sixth.edges(800.0).len(), 19);
+    }
+
+    #[test]
+    fn weighting_curves_match_the_standard() {
+        // IEC 61672 table values, ±0.1 dB.
+        for (freq, a, c) in [
+            (50.0, -30.2, -1.3),
+            (100.0, -19.1, -0.3),
+            (1_000.0, 0.0, 0.0),
+            (4_000.0, 1.0, -0.8),
+            (10_000.0, -2.5, -4.4),
+        ] {
+            assert!((Weighting::A.db(freq) - a).abs() < 0.1, "A at {freq} Hz");
+            assert!((Weighting::C.db(freq) - c).abs() < 0.1, "C at {freq} Hz");
+        }
+    }
+
+    #[test]
+    fn weights_follow_bin_centers() {
+        let mut analyzer = Analyzer::new(2048, 48_000, &layout(16, 40.0, 16_000.0), 1.0).unwrap();
+        let centers = analyzer.centers().to_vec();
+        assert!(centers.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // A +3 dB/octave tilt doubles the amplitude two octaves up.
+        analyzer.set_weighting(|freq| 3.0 * (freq / 1_000.0).log2());
+        let ratio = analyzer.weights[15] / analyzer.weights[0];
+        let octaves = (centers[15] / centers[0]).log2();
+        assert!((20.0 * ratio.log10() - 3.0 * octaves).abs() < 0.01);
+    }
+
+    #[test]
+    fn reductions_measure_wide_bins_differently() {
+        let sample_rate = 48_000;
+        let fft_size = 1024;
+        let freq = 3_000.0f32;
+        let samples: Vec<f32> = (0..fft_size)
+            .map(|i| (2.0 * PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect();
+
+        let level = |reduction| {
+            let layout = Layout {
+                reduction,
+                ..layout(1, 1_000.0, 8_000.0)
+            };
+            Analyzer::new(fft_size, sample_rate, &layout, 1.0)
+                .unwrap()
+                .analyze(&samples)[0]
+        };
+
+        let (peak, power, rms) = (
+            level(Reduction::Peak),
+            level(Reduction::Power),
+            level(Reduction::Rms),
+        );
+        // One tone in a bin ~150 FFT bins wide: the mean dilutes it.
+        assert!(peak > 0.7, "peak {peak}");
+        assert!(rms < peak / 4.0, "rms {rms}");
+        assert!((power - rms * rms).abs() < 1e-6);
+    }
+
+    #[test]
+    fn power_in_decibels_spans_the_same_range() {
+        let sample_rate = 48_000;
+        let fft_size = 1024;
+        let samples: Vec<f32> = (0..fft_size)
+            .map(|i| 0.1 * (2.0 * PI * 3_000.0 * i as f32 / sample_rate as f32).sin())
+            .collect();
+
+        let level = |reduction, scale, gain| {
+            let layout = Layout {
+                reduction,
+                ..layout(1, 1_000.0, 8_000.0)
+            };
+            Analyzer::new(fft_size, sample_rate, &layout, gain)
+                .unwrap()
+                .with_scale(scale, -60.0, 0.0)
+                .analyze(&samples)[0]
+        };
+
+        // Power is rms squared: the same decibels, so the same level.
+        let rms = level(Reduction::Rms, Scale::Db, 1.0);
+        let power = level(Reduction::Power, Scale::Db, 1.0);
+        assert!(rms > 0.1 && rms < 0.9, "rms {rms}");
+        assert!((power - rms).abs() < 1e-5, "power {power} vs rms {rms}");
+
+        // Gain multiplies the power once, not squared.
+        let plain = level(Reduction::Power, Scale::Linear, 1.0);
+        let doubled = level(Reduction::Power, Scale::Linear, 2.0);
+        assert!((doubled - 2.0 * plain).abs() < 1e-6, "{doubled} vs {plain}");
     }
 }
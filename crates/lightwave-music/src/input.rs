@@ -0,0 +1,478 @@
+//! Audio sources besides capture devices: WAV and FLAC files, raw PCM on
+//! stdin and built-in test signals, each fed from a thread of its own.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+
+use crate::capture::{Capture, Channels, Feed, Source};
+use crate::signal::{Generator, TestSignal};
+
+/// Frames per write: 10 ms at 48 kHz.
+const CHUNK: usize = 480;
+
+/// Sample rate of test signals unless one is asked for.
+const SIGNAL_RATE: u32 = 48_000;
+
+/// Where the audio comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// A capture device, by case-insensitive substring of its name; None
+    /// = the default.
+    Device(Option<String>),
+    /// A WAV or FLAC file, played in real time or, if not `realtime`,
+    /// analyzed as fast as possible: each packet then covers a frame's
+    /// worth of audio however long it took.
+    File {
+        path: PathBuf,
+        realtime: bool,
+    },
+    /// Raw interleaved PCM on stdin, analyzed as it arrives or, if
+    /// `realtime`, paced as it would play: a decoder or `cat` writes far
+    /// faster than that, and unpaced most of its audio would be skipped.
+    Stdin {
+        format: PcmFormat,
+        sample_rate: u32,
+        channels: u16,
+        realtime: bool,
+    },
+    Signal(TestSignal),
+}
+
+impl Input {
+    /// Open the source, splitting it into `channels` with rings for
+    /// `fft_size`-sample windows. `sample_rate` applies to capture devices
+    /// and test signals; files and stdin bring their own.
+    pub(crate) fn open(
+        &self,
+        sample_rate: Option<u32>,
+        fft_size: usize,
+        channels: Channels,
+    ) -> Result<Box<dyn Source>> {
+        Ok(match self {
+            Self::Device(filter) => Box::new(Capture::open(
+                filter.as_deref(),
+                sample_rate,
+                fft_size,
+                channels,
+            )?),
+            Self::File { path, realtime } => {
+                let pace = if *realtime {
+                    Pace::RealTime
+                } else {
+                    Pace::OnDemand
+                };
+                Box::new(open_file(path, pace, fft_size, channels)?)
+            }
+            &Self::Stdin {
+                format,
+                sample_rate,
+                channels: width,
+                realtime,
+            } => {
+                let pace = if realtime {
+                    Pace::RealTime
+                } else {
+                    Pace::AsRead
+                };
+                Box::new(open_stdin(
+                    format,
+                    sample_rate,
+                    width,
+                    pace,
+                    fft_size,
+                    channels,
+                )?)
+            }
+            &Self::Signal(signal) => {
+                let sample_rate = sample_rate.unwrap_or(SIGNAL_RATE);
+                let mut generator = Generator::new(signal, sample_rate);
+
+                Box::new(Producer::spawn(
+                    format!("test signal ({signal})"),
+                    sample_rate,
+                    1,
+                    Pace::RealTime,
+                    fft_size,
+                    channels,
+                    move |writer| write_all(writer, generator.by_ref().map(Ok)),
+                )?)
+            }
+        })
+    }
+}
+
+/// Sample encodings read from stdin, all little-endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PcmFormat {
+    #[default]
+    S16le,
+    S24le,
+    S32le,
+    F32le,
+}
+
+impl PcmFormat {
+    fn bytes(self) -> usize {
+        match self {
+            Self::S16le => 2,
+            Self::S24le => 3,
+            Self::S32le | Self::F32le => 4,
+        }
+    }
+
+    /// One sample from its `bytes()` bytes.
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            Self::S16le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32_768.0,
+            // Into the top of an i32, so the sign comes along.
+            Self::S24le => {
+                i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2_147_483_648.0
+            }
+            Self::S32le => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                    / 2_147_483_648.0
+            }
+            Self::F32le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+impl FromStr for PcmFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s16le" => Ok(Self::S16le),
+            "s24le" => Ok(Self::S24le),
+            "s32le" => Ok(Self::S32le),
+            "f32le" => Ok(Self::F32le),
+            _ => Err(format!(
+                "unknown format {s:?}; expected s16le, s24le, s32le or f32le"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PcmFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::S16le => "s16le",
+            Self::S24le => "s24le",
+            Self::S32le => "s32le",
+            Self::F32le => "f32le",
+        })
+    }
+}
+
+/// How a producer times its writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pace {
+    /// As the audio would play.
+    RealTime,
+    /// When the streamer asks for more (see [`Feed::request`]).
+    OnDemand,
+    /// As fast as it can be read; whatever writes it sets the pace.
+    AsRead,
+}
+
+/// A source filled by a thread of ours.
+struct Producer {
+    name: String,
+    sample_rate: u32,
+    feed: Feed,
+}
+
+impl Producer {
+    /// Run `produce` on its own thread, writing frames of `width` channels
+    /// at `sample_rate`; what it returns ends the feed.
+    fn spawn(
+        name: String,
+        sample_rate: u32,
+        width: usize,
+        pace: Pace,
+        fft_size: usize,
+        channels: Channels,
+        produce: impl FnOnce(&mut Writer) -> Result<()> + Send + 'static,
+    ) -> Result<Self> {
+        if sample_rate == 0 || width == 0 {
+            bail!("{name} has no audio ({sample_rate} Hz, {width} channels)");
+        }
+
+        let feed = Feed::new(channels, fft_size, sample_rate, pace == Pace::OnDemand);
+        let mut writer = Writer {
+            feed: feed.clone(),
+            width,
+            sample_rate,
+            pace,
+            started: Instant::now(),
+            frames: 0,
+        };
+
+        thread::Builder::new()
+            .name("lightwave-input".to_string())
+            .spawn(move || {
+                let result = produce(&mut writer);
+                writer.feed.end(result);
+            })
+            .context("starting the input thread")?;
+
+        Ok(Self {
+            name,
+            sample_rate,
+            feed,
+        })
+    }
+}
+
+impl Source for Producer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn feed(&self) -> &Feed {
+        &self.feed
+    }
+}
+
+/// Hands a producer's samples to its feed at the producer's pace.
+struct Writer {
+    feed: Feed,
+    width: usize,
+    sample_rate: u32,
+    pace: Pace,
+    started: Instant,
+    frames: u64,
+}
+
+impl Writer {
+    /// Write interleaved samples, whole frames of them; false once
+    /// nothing reads the feed and the producer should stop.
+    fn write(&mut self, samples: &[f32]) -> bool {
+        match self.pace {
+            Pace::RealTime => {
+                let due = self.started
+                    + Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+            Pace::OnDemand => {
+                if !self.feed.wait_for_demand() {
+                    return false;
+                }
+            }
+            Pace::AsRead => {}
+        }
+
+        self.frames += (samples.len() / self.width) as u64;
+        self.feed.push(samples, self.width)
+    }
+}
+
+/// Write `samples` (interleaved) in chunks until they run out or nothing
+/// reads them.
+fn write_all(writer: &mut Writer, samples: impl Iterator<Item = Result<f32>>) -> Result<()> {
+    let mut chunk = Vec::with_capacity(CHUNK * writer.width);
+
+    for sample in samples {
+        chunk.push(sample?);
+        if chunk.len() == chunk.capacity() {
+            if !writer.write(&chunk) {
+                return Ok(());
+            }
+            chunk.clear();
+        }
+    }
+
+    if !chunk.is_empty() {
+        writer.write(&chunk);
+    }
+    Ok(())
+}
+
+/// A WAV or FLAC file, told apart by its first bytes.
+fn open_file(path: &Path, pace: Pace, fft_size: usize, channels: Channels) -> Result<Producer> {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .with_context(|| format!("reading {}", path.display()))?;
+
+    let name = path.display().to_string();
+
+    match &magic {
+        b"RIFF" => {
+            let mut reader = hound::WavReader::open(path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let spec = reader.spec();
+
+            Producer::spawn(
+                name,
+                spec.sample_rate,
+                spec.channels.into(),
+                pace,
+                fft_size,
+                channels,
+                move |writer| match spec.sample_format {
+                    hound::SampleFormat::Float => write_all(
+                        writer,
+                        reader
+                            .samples::<f32>()
+                            .map(|sample| sample.context("decoding WAV")),
+                    ),
+                    hound::SampleFormat::Int => {
+                        let scale = full_scale(spec.bits_per_sample.into());
+                        write_all(
+                            writer,
+                            reader
+                                .samples::<i32>()
+                                .map(|sample| Ok(sample.context("decoding WAV")? as f32 * scale)),
+                        )
+                    }
+                },
+            )
+        }
+        b"fLaC" => {
+            let mut reader = claxon::FlacReader::open(path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let info = reader.streaminfo();
+            let scale = full_scale(info.bits_per_sample);
+
+            Producer::spawn(
+                name,
+                info.sample_rate,
+                info.channels as usize,
+                pace,
+                fft_size,
+                channels,
+                move |writer| {
+                    write_all(
+                        writer,
+                        reader
+                            .samples()
+                            .map(|sample| Ok(sample.context("decoding FLAC")? as f32 * scale)),
+                    )
+                },
+            )
+        }
+        _ => bail!("{} is neither WAV nor FLAC", path.display()),
+    }
+}
+
+/// Factor mapping `bits`-bit integer samples to -1..1.
+fn full_scale(bits: u32) -> f32 {
+    1.0 / (1u64 << (bits.clamp(1, 32) - 1)) as f32
+}
+
+fn open_stdin(
+    format: PcmFormat,
+    sample_rate: u32,
+    width: u16,
+    pace: Pace,
+    fft_size: usize,
+    channels: Channels,
+) -> Result<Producer> {
+    let width = usize::from(width);
+    let frame = format.bytes() * width;
+
+    Producer::spawn(
+        format!("stdin ({format})"),
+        sample_rate,
+        width,
+        pace,
+        fft_size,
+        channels,
+        move |writer| {
+            let mut stdin = io::stdin().lock();
+            let mut bytes = vec![0; CHUNK * frame];
+            let mut samples = Vec::with_capacity(CHUNK * width);
+
+            loop {
+                let read = read_full(&mut stdin, &mut bytes).context("reading stdin")?;
+
+                samples.clear();
+                samples.extend(
+                    bytes[..read - read % frame]
+                        .chunks_exact(format.bytes())
+                        .map(|sample| format.decode(sample)),
+                );
+
+                if !writer.write(&samples) || read < bytes.len() {
+                    return Ok(());
+                }
+            }
+        },
+    )
+}
+
+/// Fill `buf` unless the reader runs out first; returns how much was read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_pcm_formats() {
+        assert_eq!(PcmFormat::S16le.decode(&[0x00, 0x40]), 0.5);
+        assert_eq!(PcmFormat::S16le.decode(&[0x00, 0x80]), -1.0);
+        assert_eq!(PcmFormat::S24le.decode(&[0x00, 0x00, 0xc0]), -0.5);
+        assert_eq!(PcmFormat::S32le.decode(&[0, 0, 0, 0x40]), 0.5);
+        assert_eq!(PcmFormat::F32le.decode(&0.25f32.to_le_bytes()), 0.25);
+    }
+
+    #[test]
+    fn on_demand_sources_write_what_is_asked() {
+        let source = Producer::spawn(
+            "ramp".to_string(),
+            1000,
+            2,
+            Pace::OnDemand,
+            64,
+            Channels::Stereo,
+            |writer| {
+                let ramp = (0..2000).map(|i| Ok((i / 2) as f32 + (i % 2) as f32 / 2.0));
+                write_all(writer, ramp)
+            },
+        )
+        .unwrap();
+        let feed = source.feed();
+
+        assert!(feed.on_demand());
+        assert_eq!(feed.request(100), 100);
+
+        // Writes come in whole chunks, so more may have arrived.
+        let (written, _) = feed.position();
+        let mut out = [0.0; 2];
+        assert!(feed.window(1, 100, &mut out));
+        assert_eq!(out, [98.5, 99.5]);
+        assert!(written >= 100);
+
+        while feed.request(CHUNK as u64) > 0 {}
+        assert_eq!(feed.position().0, 1000);
+        assert_eq!(feed.ended(), Some(Ok(())));
+    }
+}
@@ -1,36 +1,137 @@
+mod beat;
 mod capture;
 mod dsp;
+mod input;
+mod pink;
+mod signal;
+mod silence;
 
-use std::net::UdpSocket;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, bail};
+use lightwave_core::config::{Config as FileConfig, Music as FileMusic};
+use lightwave_core::net::{
+    Destination, Encoding, PacketFormat, Protocol, SendOptions, Sender, StreamKind,
+};
+use lightwave_core::stream::{
+    self, Control, Event, Inbox, Meter, Monitor, Reachability, Signal, Signals, Stats, StopReason,
+};
+use lightwave_core::tuning::{Param, Tuning};
 use tokio::time::MissedTickBehavior;
 
-pub use capture::list_devices;
+pub use capture::{Channels, Feed, Source, list_devices};
+pub use dsp::{AgcMode, FreqScale, KAISER_BETA, Reduction, Scale, Weighting, Window};
+pub use input::{Input, PcmFormat};
+pub use pink::calibrate_pink;
+pub use signal::TestSignal;
+pub use silence::{Fallback, OnSilence};
+
+/// The shortest hop is the FFT size over this: 128 samples at the default
+/// 2048, some 375 windows a second at 48 kHz.
+const MIN_HOP_DIVISOR: usize = 16;
 
 pub struct Config {
-    /// Case-insensitive substring match on device names; None = default input.
-    /// On PipeWire an output sink matches here, capturing its monitor.
-    pub device: Option<String>,
-    /// Capture sample rate in Hz; None = device preference.
+    /// Where the audio comes from. On PipeWire an output sink matches
+    /// [`Input::Device`], capturing its monitor.
+    pub input: Input,
+    /// Sample rate in Hz of a capture device or test signal; None = the
+    /// device's preference, or 48 kHz.
     pub sample_rate: Option<u32>,
     /// FFT window size in samples (power of two).
     pub fft_size: usize,
-    /// Frequency bins per UDP packet.
+    /// Channels analyzed; each packet holds one spectrum per channel,
+    /// one after the other.
+    pub channels: Channels,
+    /// Window function applied before the FFT.
+    pub window: Window,
+    /// Analyze a window every `hop` samples of audio (`fft_size / 16` up
+    /// to `fft_size`), sending each bin's loudest level since the previous
+    /// packet; None = one window, the newest, per packet.
+    pub hop: Option<usize>,
+    /// Frequency bins per UDP packet; with [`FreqScale::Octave`], the
+    /// range and `octave_fraction` decide instead.
     pub bins: usize,
-    /// Linear gain applied to bin magnitudes before clamping to 0..=1.
+    /// Linear gain applied to bin magnitudes before the AGC and clamping
+    /// to 0..=1.
     pub gain: f32,
+    /// Loudness curve weighting each bin by its center frequency.
+    pub weighting: Weighting,
+    /// Extra dB per octave, 0 at 1 kHz; positive lifts the highs.
+    pub tilt: f32,
+    /// Apply the config file's equalization curve (`[music] eq`, from
+    /// [`calibrate_pink`]), re-reading it on SIGHUP.
+    pub equalize: bool,
+    /// How amplitudes map to levels.
+    pub scale: Scale,
+    /// Decibel range mapped to 0..=1 by [`Scale::Db`].
+    pub floor_db: f32,
+    pub ceiling_db: f32,
+    /// Automatic gain control on top of `gain`.
+    pub agc: AgcMode,
+    /// Level the AGC steers bins toward, in 0..=1.
+    pub agc_target: f32,
+    /// The AGC's gain stays within `1/agc_max_gain..=agc_max_gain`.
+    pub agc_max_gain: f32,
     /// Analyzed frequency range in Hz.
     pub min_freq: f32,
     pub max_freq: f32,
+    /// How bins divide the range.
+    pub scale_type: FreqScale,
+    /// Bands per octave with [`FreqScale::Octave`].
+    pub octave_fraction: u32,
+    /// How each bin's FFT bins combine.
+    pub reduction: Reduction,
+    /// Time constants for bins rising and falling; zero follows the
+    /// signal instantly.
+    pub attack: Duration,
+    pub release: Duration,
+    /// How long each bin's peak is held before falling at the release
+    /// rate; None = no peak hold.
+    pub peak_hold: Option<Duration>,
+    /// Noise gate threshold in dBFS: while the input stays below it
+    /// (less `gate_hysteresis` once open), bins go dark and the AGC holds
+    /// its gain; None = no gate.
+    pub gate: Option<f32>,
+    /// How far in dB the input must fall below the gate to close it.
+    pub gate_hysteresis: f32,
+    /// How long the input must stay quiet (gated, or without a gate, at
+    /// -70 dBFS) before it counts as silent, reported as
+    /// [`Event::Silence`]; None = never.
+    pub silence_timeout: Option<Duration>,
+    /// What the stream does while silent.
+    pub on_silence: OnSilence,
     /// UDP packets per second.
     pub fps: u32,
     /// UDP target, e.g. "192.168.1.20:5555".
     pub target: String,
+    /// Output protocol, packet format and payload encoding.
+    pub output: SendOptions,
+    /// Detect beats, reporting each as an [`Event::Beat`].
+    pub beats: bool,
+    /// Also send beats here, as framed beat packets; implies `beats`.
+    pub beat_target: Option<String>,
+    /// How often to report [`Event::Stats`]; None = never.
+    pub stats_interval: Option<Duration>,
+    /// Refusals in a row before reporting [`Event::Unreachable`].
+    pub unreachable_after: u64,
+    /// End the stream with [`Unreachable`] instead of just reporting it.
+    ///
+    /// [`Unreachable`]: lightwave_core::stream::Unreachable
+    pub fail_on_unreachable: bool,
 }
 
 impl Config {
+    fn layout(&self) -> dsp::Layout {
+        dsp::Layout {
+            bins: self.bins,
+            min_freq: self.min_freq,
+            max_freq: self.max_freq,
+            scale: self.scale_type,
+            octave_fraction: self.octave_fraction,
+            reduction: self.reduction,
+        }
+    }
+
     fn validate(&self) -> Result<()> {
         if !self.fft_size.is_power_of_two() || self.fft_size < 64 {
             bail!(
@@ -39,13 +140,31 @@ impl Config {
             );
         }
 
+        // Each hop is a full FFT; much below this a packet takes hundreds
+        // of them and the stream falls behind.
+        let min_hop = self.fft_size / MIN_HOP_DIVISOR;
+        if let Some(hop) = self.hop
+            && !(min_hop..=self.fft_size).contains(&hop)
+        {
+            bail!(
+                "hop must be between fft-size/{MIN_HOP_DIVISOR} ({min_hop}) and fft-size ({}) \
+                 samples, got {hop}",
+                self.fft_size
+            );
+        }
+
         if self.bins == 0 {
             bail!("bins must be at least 1");
         }
 
-        // Past fft_size/2 usable bins, trailing ranges collapse to empty
+        if self.octave_fraction == 0 {
+            bail!("octave-fraction must be at least 1");
+        }
+
+        // Past fft_size/2 usable bins, trailing ranges collapse to empty.
+        // Octave bands are counted once the sample rate is known.
         let usable = self.fft_size / 2;
-        if self.bins > usable {
+        if self.scale_type != FreqScale::Octave && self.bins > usable {
             bail!(
                 "bins {} exceeds the {usable} FFT bins available at fft-size {}; \
                  reduce bins or raise fft-size",
@@ -58,6 +177,45 @@ impl Config {
             bail!("gain must be a finite positive number, got {}", self.gain);
         }
 
+        if !self.floor_db.is_finite()
+            || !self.ceiling_db.is_finite()
+            || self.floor_db >= self.ceiling_db
+        {
+            bail!(
+                "floor-db {} must be below ceiling-db {}",
+                self.floor_db,
+                self.ceiling_db
+            );
+        }
+
+        if !(self.agc_target > 0.0 && self.agc_target <= 1.0) {
+            bail!("agc-target must be in (0, 1], got {}", self.agc_target);
+        }
+
+        if !self.tilt.is_finite() {
+            bail!("tilt must be a finite number, got {}", self.tilt);
+        }
+
+        if !self.agc_max_gain.is_finite() || self.agc_max_gain < 1.0 {
+            bail!(
+                "agc-max-gain must be a finite number >= 1, got {}",
+                self.agc_max_gain
+            );
+        }
+
+        if let Some(gate) = self.gate
+            && (!gate.is_finite() || gate > 0.0)
+        {
+            bail!("gate must be a level in dBFS, at most 0, got {gate}");
+        }
+
+        if !self.gate_hysteresis.is_finite() || self.gate_hysteresis < 0.0 {
+            bail!(
+                "gate-hysteresis must be a finite number of dB >= 0, got {}",
+                self.gate_hysteresis
+            );
+        }
+
         if self.fps == 0 {
             bail!("fps must be at least 1");
         }
@@ -76,94 +234,532 @@ impl Config {
             );
         }
 
+        if self.stats_interval == Some(Duration::ZERO) {
+            bail!("stats interval must be positive");
+        }
+
+        if self.unreachable_after == 0 {
+            bail!("unreachable-after must be at least 1");
+        }
+
         Ok(())
     }
 }
 
 /// Captures audio, runs the FFT, and streams binned spectra over UDP
-/// in the visualizer's packet format (packed little-endian f32, 0..=1).
+/// in the visualizer's packet format (packed little-endian f32, 0..=1,
+/// optionally framed).
 pub struct Streamer {
-    capture: capture::Capture,
+    source: Box<dyn Source>,
+    /// Whether the source waits to be asked for audio, which then goes
+    /// as fast as it's analyzed, a packet period at a time.
+    on_demand: bool,
     analyzer: dsp::Analyzer,
-    socket: UdpSocket,
+    agc: dsp::Agc,
+    smoother: dsp::Smoother,
+    beats: Option<beat::Detector>,
+    sender: Sender,
+    beat_sender: Option<Sender>,
     samples: Vec<f32>,
-    packet: Vec<u8>,
+    /// Gates the bins when `gated`, and always tells sound from silence.
+    gate: dsp::Gate,
+    gated: bool,
+    idler: silence::Idler,
+    /// RMS of the loudest channel in the latest window.
+    loudness: f32,
+    hop: Option<usize>,
+    /// Capture position the next hop's window ends at.
+    next_hop: u64,
+    /// Every channel's spectrum in the latest window.
+    frame: Vec<f32>,
+    /// Levels analyzed for the next packet.
+    levels: Vec<f32>,
     period: Duration,
+    /// When the previous frame was analyzed, to advance the smoother.
+    last_frame: Option<Instant>,
+    meter: Meter,
+    stats_interval: Option<Duration>,
+    reachability: Reachability,
+    control: Control,
+    inbox: Inbox,
+    tuning: Tuning,
+    monitor: Monitor,
+    /// Tuning version last applied to the analyzer.
+    tuned: u64,
+    weighting: Weighting,
+    tilt: f32,
+    equalize: bool,
 }
 
 impl Streamer {
     pub fn new(config: &Config) -> Result<Self> {
         config.validate()?;
 
-        let capture = capture::Capture::open(
-            config.device.as_deref(),
-            config.sample_rate,
-            config.fft_size,
-        )?;
+        let source = config
+            .input
+            .open(config.sample_rate, config.fft_size, config.channels)?;
 
-        let analyzer = dsp::Analyzer::new(
+        let mut analyzer = dsp::Analyzer::new(
             config.fft_size,
-            capture.sample_rate(),
-            config.bins,
-            config.min_freq,
-            config.max_freq,
+            source.sample_rate(),
+            &config.layout(),
             config.gain,
-        )?;
+        )?
+        .with_window(config.window)
+        .with_scale(config.scale, config.floor_db, config.ceiling_db);
+        let bins = analyzer.bins();
+
+        let eq = if config.equalize {
+            FileConfig::load()?.music
+        } else {
+            FileMusic::default()
+        };
+        analyzer.set_weighting(weighting(config.weighting, config.tilt, &eq));
+
+        // Channels' spectra go out back to back, and the AGC and smoothing
+        // treat them as one.
+        let values = bins * config.channels.count();
+        let agc = dsp::Agc::new(config.agc, values, config.agc_target, config.agc_max_gain);
 
-        let socket = lightwave_core::net::connect_udp(&config.target)?;
+        let smoother = dsp::Smoother::new(values, config.attack, config.release, config.peak_hold);
+
+        let sender = Sender::connect(&config.target, StreamKind::Music, &config.output)?;
+
+        let beat_sender = config
+            .beat_target
+            .as_deref()
+            .map(|target| {
+                // Beats aren't pixels; whatever drives the strip, they go
+                // out as LightWave packets.
+                let options = SendOptions {
+                    protocol: Protocol::LightWave,
+                    format: PacketFormat::Framed,
+                    encoding: Encoding::F32,
+                    udp: config.output.udp.clone(),
+                    ..SendOptions::default()
+                };
+                Sender::connect(target, StreamKind::Beat, &options)
+            })
+            .transpose()?;
+        let period = Duration::from_secs(1) / config.fps;
+        let (control, inbox) = stream::channel();
 
         Ok(Self {
-            capture,
+            on_demand: source.feed().on_demand(),
+            source,
             analyzer,
-            socket,
+            agc,
+            smoother,
+            beats: (config.beats || beat_sender.is_some()).then(beat::Detector::new),
+            sender,
+            beat_sender,
             samples: vec![0.0; config.fft_size],
-            packet: Vec::with_capacity(config.bins * 4),
-            period: Duration::from_secs(1) / config.fps,
+            gate: dsp::Gate::new(
+                config.gate.unwrap_or(silence::SILENCE_DB),
+                config.gate_hysteresis,
+            ),
+            gated: config.gate.is_some(),
+            idler: silence::Idler::new(values, config.silence_timeout, config.on_silence.clone()),
+            loudness: 0.0,
+            hop: config.hop,
+            next_hop: 0,
+            frame: vec![0.0; values],
+            levels: vec![0.0; values],
+            period,
+            last_frame: None,
+            meter: Meter::new(period),
+            stats_interval: config.stats_interval,
+            reachability: Reachability::new(
+                &config.target,
+                config.unreachable_after,
+                config.fail_on_unreachable,
+            ),
+            control,
+            inbox,
+            tuning: Tuning::new(vec![
+                Param::new("gain", config.gain, 0.01..=1000.0, 0.25),
+                Param::new("attack", millis(config.attack), 0.0..=1000.0, 5.0),
+                Param::new("release", millis(config.release), 0.0..=5000.0, 25.0),
+            ]),
+            monitor: Monitor::default(),
+            tuned: 0,
+            weighting: config.weighting,
+            tilt: config.tilt,
+            equalize: config.equalize,
         })
     }
 
     pub fn device_name(&self) -> &str {
-        self.capture.name()
+        self.source.name()
     }
 
     pub fn sample_rate(&self) -> u32 {
-        self.capture.sample_rate()
+        self.source.sample_rate()
+    }
+
+    /// Bins per channel, which octave bands set themselves.
+    pub fn bins(&self) -> usize {
+        self.analyzer.bins()
+    }
+
+    /// Whether packets go to a unicast, broadcast or multicast address.
+    pub fn destination(&self) -> Destination {
+        self.sender.destination()
+    }
+
+    /// A handle for pausing, resuming or stopping the stream from
+    /// another thread, and for reporting events through it.
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// A handle for adjusting `gain`, `attack` and `release` (in ms)
+    /// while streaming.
+    pub fn tuning(&self) -> Tuning {
+        self.tuning.clone()
+    }
+
+    /// A handle on the values sent last, for live displays.
+    pub fn monitor(&self) -> Monitor {
+        self.monitor.clone()
+    }
+
+    /// Health counters so far, and the AGC's gain; see [`Stats`].
+    pub fn stats(&mut self) -> Stats {
+        let mut stats = self.meter.report(
+            Instant::now(),
+            self.source.feed().buffers(),
+            self.sender.stats(),
+        );
+        stats.agc_gain = self.agc.gain();
+        stats
     }
 
-    /// Stream packets until Ctrl+C.
-    pub fn run(mut self) -> Result<()> {
+    /// Stream packets until Ctrl+C, SIGTERM, a [`Control::stop`] or the
+    /// end of the input, passing events to `on_event`; an error from it
+    /// ends the stream. SIGHUP re-reads the config file.
+    pub fn run(mut self, mut on_event: impl FnMut(Event) -> Result<()>) -> Result<StopReason> {
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
             .context("building tokio runtime")?;
 
         runtime.block_on(async {
-            let mut ticker = tokio::time::interval(self.period);
-            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut ticker = (!self.on_demand).then(|| {
+                let mut ticker = tokio::time::interval(self.period);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                ticker
+            });
 
-            let ctrl_c = tokio::signal::ctrl_c();
-            tokio::pin!(ctrl_c);
+            let mut stats_ticker = self
+                .stats_interval
+                .map(|every| tokio::time::interval_at(tokio::time::Instant::now() + every, every));
+
+            let mut signals = Signals::new()?;
 
             loop {
                 tokio::select! {
-                    result = &mut ctrl_c => {
-                        return result.context("waiting for Ctrl+C");
+                    signal = signals.recv() => match signal {
+                        Signal::Stop(reason) => return Ok(reason),
+                        Signal::Reload => on_event(match self.reload() {
+                            Ok(()) => Event::Reloaded,
+                            Err(err) => Event::ReloadFailed {
+                                error: format!("{err:#}"),
+                            },
+                        })?,
+                    },
+                    at = next_tick(ticker.as_mut()) => {
+                        self.meter.tick(at);
+
+                        if let Some(reason) = self.inbox.drain(&mut on_event)? {
+                            return Ok(reason);
+                        }
+
+                        if self.inbox.paused() {
+                            // Audio read on demand has no ticker to wait
+                            // on; look at the inbox once a period instead
+                            // of spinning.
+                            if ticker.is_none() {
+                                tokio::time::sleep(self.period).await;
+                            }
+                            continue;
+                        }
+
+                        for event in self.send_frame() {
+                            on_event(event)?;
+                        }
+
+                        if let Some(end) = self.source.feed().ended() {
+                            return end
+                                .map(|()| StopReason::EndOfInput)
+                                .map_err(anyhow::Error::msg);
+                        }
+
+                        if let Some(event) = self.reachability.check(self.sender.stats()) {
+                            on_event(event)?;
+                            self.reachability.enforce(self.sender.stats())?;
+                        }
+                    }
+                    _ = async { stats_ticker.as_mut().unwrap().tick().await },
+                        if stats_ticker.is_some() =>
+                    {
+                        on_event(Event::Stats(self.stats()))?;
                     }
-                    _ = ticker.tick() => self.send_frame()?,
                 }
             }
         })
     }
 
-    fn send_frame(&mut self) -> Result<()> {
-        self.capture.snapshot(&mut self.samples);
-        let bins = self.analyzer.analyze(&self.samples);
+    fn reload(&mut self) -> Result<()> {
+        // Read the file even without equalization, so a broken edit
+        // surfaces now rather than at the next start.
+        let file = FileConfig::load()?;
+        if self.equalize {
+            self.analyzer
+                .set_weighting(weighting(self.weighting, self.tilt, &file.music));
+        }
+        Ok(())
+    }
 
-        self.packet.clear();
-        for &bin in bins {
-            self.packet.extend_from_slice(&bin.to_le_bytes());
+    /// Send the next spectrum, returning the events in it: a beat, the
+    /// input falling silent or sound returning.
+    fn send_frame(&mut self) -> Vec<Event> {
+        let version = self.tuning.version();
+        if version != self.tuned {
+            if let Some(gain) = self.tuning.get("gain") {
+                self.analyzer.set_gain(gain);
+            }
+            if let Some(attack) = self.tuning.get("attack") {
+                self.smoother
+                    .set_attack(Duration::from_secs_f32(attack / 1000.0));
+            }
+            if let Some(release) = self.tuning.get("release") {
+                self.smoother
+                    .set_release(Duration::from_secs_f32(release / 1000.0));
+            }
+            self.tuned = version;
         }
 
-        lightwave_core::net::send_packet(&self.socket, &self.packet)
+        let now = Instant::now();
+        let wall = self
+            .last_frame
+            .replace(now)
+            .map_or(self.period, |last| now - last);
+
+        // Audio read on demand covers a period per packet, however fast
+        // the packets go.
+        let elapsed = if self.on_demand {
+            let frames = self.source.sample_rate() as f64 * self.period.as_secs_f64();
+            self.source.feed().request(frames.round() as u64);
+            self.period
+        } else {
+            wall
+        };
+
+        let (captured_at, beat) = match self.hop {
+            Some(hop) => self.analyze_hops(hop),
+            None => self.analyze_newest(elapsed),
+        };
+        let sound = self.gate.update(self.loudness);
+        let mut events: Vec<Event> = self.idler.update(sound, elapsed).into_iter().collect();
+
+        // A closed gate darkens the bins without the AGC seeing it, so its
+        // gain doesn't climb to bring up the hiss.
+        let bins = if self.gated && !sound {
+            self.levels.fill(0.0);
+            &self.levels
+        } else {
+            self.agc.apply(&self.levels, elapsed)
+        };
+        let bins = self.smoother.apply(bins, elapsed);
+
+        if let Some(values) = self.idler.output(bins, elapsed) {
+            self.sender.send(values);
+            self.monitor.record(values);
+            self.meter.sent(Instant::now(), captured_at);
+        }
+
+        if let Some(beat) = beat {
+            if let Some(sender) = &mut self.beat_sender {
+                sender.send(&[beat.strength, beat.bpm.unwrap_or(0.0), beat.confidence]);
+            }
+
+            events.push(Event::Beat {
+                strength: beat.strength,
+                bpm: beat.bpm,
+                confidence: beat.confidence,
+            });
+        }
+
+        events
+    }
+
+    /// Analyze the newest window, `elapsed` after the previous one.
+    fn analyze_newest(&mut self, elapsed: Duration) -> (Option<Instant>, Option<beat::Beat>) {
+        let (written, captured_at) = self.source.feed().position();
+        if let Some(loudness) = self.analyze_window(written) {
+            self.loudness = loudness;
+        }
+        let beat = self
+            .beats
+            .as_mut()
+            .and_then(|detector| detector.detect(&self.frame, elapsed));
+        self.levels.copy_from_slice(&self.frame);
+
+        (captured_at, beat)
+    }
+
+    /// Analyze each channel's window ending at capture position `end`
+    /// into `frame`, returning the loudest channel's RMS; None if the
+    /// ring no longer holds the window.
+    fn analyze_window(&mut self, end: u64) -> Option<f32> {
+        let bins = self.analyzer.bins();
+        let mut loudness = 0.0f32;
+
+        for (channel, spectrum) in self.frame.chunks_exact_mut(bins).enumerate() {
+            if !self.source.feed().window(channel, end, &mut self.samples) {
+                return None;
+            }
+            loudness = loudness.max(dsp::rms(&self.samples));
+            spectrum.copy_from_slice(self.analyzer.analyze(&self.samples));
+        }
+
+        Some(loudness)
+    }
+
+    /// Analyze every hop of audio since the previous packet, keeping each
+    /// bin's loudest level so a transient between packets still shows,
+    /// and the strongest beat. Until a hop completes, the previous levels
+    /// go out again.
+    fn analyze_hops(&mut self, hop: usize) -> (Option<Instant>, Option<beat::Beat>) {
+        let (written, captured_at) = self.source.feed().position();
+        let sample_rate = self.source.sample_rate();
+        let hop_time = Duration::from_secs_f64(hop as f64 / sample_rate as f64);
+
+        // More than the ring holds behind (after a pause, say): start over
+        // from the newest audio.
+        if written.saturating_sub(self.next_hop) > u64::from(sample_rate) {
+            self.next_hop = written;
+        }
+
+        let mut fresh = false;
+        let mut strongest: Option<beat::Beat> = None;
+
+        while self.next_hop <= written {
+            let end = self.next_hop;
+            self.next_hop += hop as u64;
+
+            let Some(loudness) = self.analyze_window(end) else {
+                continue;
+            };
+
+            let bins = &self.frame;
+
+            if let Some(beat) = self
+                .beats
+                .as_mut()
+                .and_then(|detector| detector.detect(bins, hop_time))
+                && strongest.is_none_or(|strongest| beat.strength > strongest.strength)
+            {
+                strongest = Some(beat);
+            }
+
+            if fresh {
+                for (level, &bin) in self.levels.iter_mut().zip(bins) {
+                    *level = level.max(bin);
+                }
+                self.loudness = self.loudness.max(loudness);
+            } else {
+                self.levels.copy_from_slice(bins);
+                self.loudness = loudness;
+                fresh = true;
+            }
+        }
+
+        (captured_at, strongest)
+    }
+}
+
+/// The next tick, or without a ticker (audio read on demand) right away,
+/// once signals and stats have had a look in.
+async fn next_tick(ticker: Option<&mut tokio::time::Interval>) -> Instant {
+    match ticker {
+        Some(ticker) => ticker.tick().await.into_std(),
+        None => {
+            tokio::task::yield_now().await;
+            Instant::now()
+        }
+    }
+}
+
+/// Per-bin correction in dB: the weighting curve, the tilt and the
+/// equalization, added up.
+fn weighting(curve: Weighting, tilt: f32, eq: &FileMusic) -> impl Fn(f32) -> f32 {
+    move |freq| curve.db(freq) + tilt * (freq / 1000.0).log2() + eq.eq_db(freq)
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            input: Input::Device(None),
+            sample_rate: None,
+            fft_size: 2048,
+            channels: Channels::Mono,
+            window: Window::Hann,
+            hop: None,
+            bins: 32,
+            gain: 4.0,
+            weighting: Weighting::None,
+            tilt: 0.0,
+            equalize: false,
+            scale: Scale::Linear,
+            floor_db: -60.0,
+            ceiling_db: 0.0,
+            agc: AgcMode::Off,
+            agc_target: 0.8,
+            agc_max_gain: 10.0,
+            min_freq: 40.0,
+            max_freq: 16000.0,
+            scale_type: FreqScale::Log,
+            octave_fraction: 3,
+            reduction: Reduction::Peak,
+            attack: Duration::ZERO,
+            release: Duration::ZERO,
+            peak_hold: None,
+            gate: None,
+            gate_hysteresis: 6.0,
+            silence_timeout: None,
+            on_silence: OnSilence::Keep,
+            fps: 60,
+            target: "127.0.0.1:5555".to_string(),
+            output: SendOptions::default(),
+            beats: false,
+            beat_target: None,
+            stats_interval: None,
+            unreachable_after: 30,
+            fail_on_unreachable: false,
+        }
+    }
+
+    #[test]
+    fn hop_stays_within_a_sixteenth_and_all_of_the_window() {
+        let hop = |hop| Config {
+            hop: Some(hop),
+            ..config()
+        };
+
+        assert!(config().validate().is_ok());
+        assert!(hop(128).validate().is_ok());
+        assert!(hop(2048).validate().is_ok());
+        assert!(hop(127).validate().is_err());
+        assert!(hop(1).validate().is_err());
+        assert!(hop(2049).validate().is_err());
     }
 }
@@ -0,0 +1,129 @@
+//! Measuring how the capture chain colors pink noise, for the
+//! equalization curve [`Config::equalize`] applies.
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+
+use crate::Config;
+use crate::capture::Channels;
+use crate::dsp::{Analyzer, Reduction};
+
+/// Largest correction either way; a deeper notch is more likely a dead
+/// band than something worth boosting.
+const MAX_CORRECTION_DB: f32 = 24.0;
+
+/// Mean level below which nothing was playing.
+const SILENCE_DB: f32 = -80.0;
+
+/// Capture `duration` of pink noise playing through the system and return
+/// the per-bin correction that levels it, as `[center frequency in Hz,
+/// dB]` points for the config file's `[music] eq`. Uses `config`'s input,
+/// FFT size and window, bin layout and fps, but not its channels (the
+/// curve applies to each), gain, weighting or scaling. `duration` is of
+/// audio, so a file read as fast as possible takes less.
+pub fn calibrate_pink(config: &Config, duration: Duration) -> Result<Vec<[f32; 2]>> {
+    config.validate()?;
+
+    let source = config
+        .input
+        .open(config.sample_rate, config.fft_size, Channels::Mono)?;
+    let feed = source.feed();
+    let sample_rate = source.sample_rate();
+
+    let mut analyzer = Analyzer::new(config.fft_size, sample_rate, &config.layout(), 1.0)?
+        .with_window(config.window);
+
+    // Power levels are squared amplitudes, so the same dB is half the
+    // factor.
+    let db_per_decade = match config.reduction {
+        Reduction::Power => 10.0,
+        Reduction::Peak | Reduction::Rms => 20.0,
+    };
+
+    let period = Duration::from_secs(1) / config.fps;
+    let step = (sample_rate as f64 * period.as_secs_f64()).round() as u64;
+    let length = (sample_rate as f64 * duration.as_secs_f64()) as u64;
+    let mut samples = vec![0.0; config.fft_size];
+    let mut totals = vec![0.0; analyzer.bins()];
+    let mut frames = 0;
+    let mut start = None;
+
+    loop {
+        if feed.on_demand() {
+            feed.request(step);
+        } else {
+            thread::sleep(period);
+        }
+
+        let (written, _) = feed.position();
+        let ended = feed.ended();
+        if let Some(Err(err)) = ended {
+            bail!("{err}");
+        }
+
+        // Wait for a full window, so none is part silence.
+        if written >= config.fft_size as u64 {
+            let start = *start.get_or_insert(written);
+            if written - start >= length {
+                break;
+            }
+
+            feed.snapshot(0, &mut samples);
+
+            // Average in dB: noise levels swing, and the curve is in dB.
+            for (total, &level) in totals.iter_mut().zip(analyzer.analyze(&samples)) {
+                *total += db_per_decade * level.max(1e-9).log10();
+            }
+            frames += 1;
+        }
+
+        if ended.is_some() {
+            break;
+        }
+    }
+
+    if frames == 0 {
+        bail!("no audio arrived from {}", source.name());
+    }
+
+    let levels: Vec<f32> = totals.iter().map(|total| total / frames as f32).collect();
+    let corrections = corrections(&levels)?;
+
+    Ok(analyzer
+        .centers()
+        .iter()
+        .zip(corrections)
+        .map(|(&freq, db)| [freq, db])
+        .collect())
+}
+
+/// What each bin needs to reach the mean of `levels` (in dB).
+fn corrections(levels: &[f32]) -> Result<Vec<f32>> {
+    let mean = levels.iter().sum::<f32>() / levels.len() as f32;
+
+    if mean < SILENCE_DB {
+        bail!("only silence captured ({mean:.0} dB); is the pink noise playing?");
+    }
+
+    Ok(levels
+        .iter()
+        .map(|level| (mean - level).clamp(-MAX_CORRECTION_DB, MAX_CORRECTION_DB))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_every_bin_to_the_mean() {
+        assert_eq!(
+            corrections(&[-20.0, -26.0, -14.0, -20.0]).unwrap(),
+            [0.0, 6.0, -6.0, 0.0]
+        );
+        assert_eq!(corrections(&[-10.0, -70.0]).unwrap(), [-24.0, 24.0]);
+        assert!(corrections(&[-120.0, -110.0]).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Built-in test signals, for trying the visualizer (or testing it)
+//! without music playing.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Sweep range in Hz, and how long one pass takes in seconds.
+const SWEEP_FROM: f32 = 20.0;
+const SWEEP_TO: f32 = 20_000.0;
+const SWEEP_SECONDS: f32 = 10.0;
+
+/// Length of each click's noise burst, and its decay time constant.
+const CLICK_SECONDS: f32 = 0.01;
+const CLICK_DECAY: f32 = 0.002;
+
+/// Tempo of `clicks` without one given.
+const DEFAULT_BPM: f32 = 120.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestSignal {
+    /// A sine gliding from 20 Hz to 20 kHz every 10 seconds, at even
+    /// speed per octave.
+    Sweep,
+    /// Equal energy per octave, like music on average; what
+    /// `--calibrate-pink` expects to hear.
+    Pink,
+    /// A burst of noise on every beat at this many BPM.
+    Clicks(f32),
+}
+
+impl FromStr for TestSignal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sweep" => Ok(Self::Sweep),
+            "pink" => Ok(Self::Pink),
+            "clicks" => Ok(Self::Clicks(DEFAULT_BPM)),
+            _ => match s.strip_prefix("clicks:").map(str::parse::<f32>) {
+                Some(Ok(bpm)) if bpm.is_finite() && bpm > 0.0 => Ok(Self::Clicks(bpm)),
+                Some(_) => Err(format!("bad tempo in {s:?}; expected clicks:<bpm>")),
+                None => Err(format!(
+                    "unknown signal {s:?}; expected sweep, pink, clicks or clicks:<bpm>"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TestSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sweep => f.write_str("sweep"),
+            Self::Pink => f.write_str("pink"),
+            Self::Clicks(bpm) => write!(f, "clicks:{bpm}"),
+        }
+    }
+}
+
+/// An endless mono stream of a [`TestSignal`].
+pub(crate) struct Generator {
+    signal: TestSignal,
+    sample_rate: f32,
+    /// Samples generated so far.
+    position: u64,
+    phase: f32,
+    /// xorshift32 state for white noise.
+    noise: u32,
+    /// Filter state turning white noise pink.
+    pink: [f32; 7],
+}
+
+impl Generator {
+    pub(crate) fn new(signal: TestSignal, sample_rate: u32) -> Self {
+        Self {
+            signal,
+            sample_rate: sample_rate as f32,
+            position: 0,
+            phase: 0.0,
+            noise: 0x9e37_79b9,
+            pink: [0.0; 7],
+        }
+    }
+
+    /// Uniform white noise in -1..1.
+    fn white(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+impl Iterator for Generator {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = match self.signal {
+            TestSignal::Sweep => {
+                let pass = (SWEEP_SECONDS * self.sample_rate) as u64;
+                let t = (self.position % pass) as f32 / self.sample_rate;
+                let freq = SWEEP_FROM * (SWEEP_TO / SWEEP_FROM).powf(t / SWEEP_SECONDS);
+
+                self.phase = (self.phase + 2.0 * PI * freq / self.sample_rate) % (2.0 * PI);
+                0.5 * self.phase.sin()
+            }
+            TestSignal::Pink => {
+                // Paul Kellet's refined filter: within 0.05 dB of pink
+                // above 9 Hz.
+                let white = self.white();
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.055_517_9;
+                b[1] = 0.99332 * b[1] + white * 0.075_075_9;
+                b[2] = 0.96900 * b[2] + white * 0.153_852;
+                b[3] = 0.86650 * b[3] + white * 0.310_485_6;
+                b[4] = 0.55000 * b[4] + white * 0.532_952_2;
+                b[5] = -0.7616 * b[5] - white * 0.016_898;
+                let pink = b.iter().sum::<f32>() + white * 0.5362;
+                b[6] = white * 0.115_926;
+                pink * 0.11
+            }
+            TestSignal::Clicks(bpm) => {
+                let beat = 60.0 / bpm * self.sample_rate;
+                let t = (self.position as f64 % beat as f64) as f32 / self.sample_rate;
+
+                if t < CLICK_SECONDS {
+                    0.9 * self.white() * (-t / CLICK_DECAY).exp()
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::beat::Detector;
+    use crate::dsp::{Analyzer, FreqScale, Layout, Reduction};
+
+    #[test]
+    fn clicks_hold_their_tempo() {
+        let (sample_rate, fft_size, hop) = (48_000, 1024, 480);
+        let layout = Layout {
+            bins: 16,
+            min_freq: 40.0,
+            max_freq: 16_000.0,
+            scale: FreqScale::Log,
+            octave_fraction: 3,
+            reduction: Reduction::Peak,
+        };
+        let mut analyzer = Analyzer::new(fft_size, sample_rate, &layout, 1.0).unwrap();
+        let mut detector = Detector::new();
+
+        let mut clicks = Generator::new("clicks:128".parse().unwrap(), sample_rate);
+        let mut window = vec![0.0; fft_size];
+        let mut last = None;
+
+        // Eight seconds, a window every 10 ms.
+        for _ in 0..800 {
+            window.drain(..hop);
+            window.extend(clicks.by_ref().take(hop));
+
+            let bins = analyzer.analyze(&window);
+            last = detector.detect(bins, Duration::from_millis(10)).or(last);
+        }
+
+        let bpm = last.and_then(|beat| beat.bpm).expect("a tempo by now");
+        assert!((bpm - 128.0).abs() < 2.0, "got {bpm} BPM");
+    }
+
+    #[test]
+    fn parses_signals() {
+        assert_eq!("clicks".parse(), Ok(TestSignal::Clicks(120.0)));
+        assert_eq!("clicks:90".parse(), Ok(TestSignal::Clicks(90.0)));
+        assert!("clicks:-1".parse::<TestSignal>().is_err());
+        assert_eq!(TestSignal::Clicks(90.0).to_string(), "clicks:90");
+    }
+}
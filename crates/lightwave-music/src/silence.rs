@@ -0,0 +1,260 @@
+//! Noticing when the music stops, and what the stream sends meanwhile.
+
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use lightwave_core::Client;
+use lightwave_core::stream::Event;
+use serde_json::{Value, json};
+
+/// Level in dBFS below which the input counts as silent when no noise
+/// gate sets it: digital silence, give or take dither.
+pub(crate) const SILENCE_DB: f32 = -70.0;
+
+/// How long [`OnSilence::Fade`] takes to go dark.
+const FADE_OUT: Duration = Duration::from_secs(2);
+
+/// What the stream does once the input has been silent for a while.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OnSilence {
+    /// Keep streaming as before; only report the silence.
+    #[default]
+    Keep,
+    /// Stop sending, letting the preset fall back to its idle look.
+    Stop,
+    /// Send dark frames.
+    Zeros,
+    /// Fade the levels out, then stop sending.
+    Fade,
+    /// Stop sending while the server runs this preset instead. Switching
+    /// is up to whoever runs the stream, with a [`Fallback`]; the streamer
+    /// only goes quiet.
+    Preset(String),
+}
+
+impl FromStr for OnSilence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "stop" => Ok(Self::Stop),
+            "zeros" => Ok(Self::Zeros),
+            "fade" => Ok(Self::Fade),
+            _ => match s.strip_prefix("preset:") {
+                Some("") => Err(format!("missing preset in {s:?}; expected preset:<name>")),
+                Some(name) => Ok(Self::Preset(name.to_string())),
+                None => Err(format!(
+                    "unknown silence action {s:?}; expected keep, stop, zeros, fade or \
+                     preset:<name>"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for OnSilence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => f.write_str("keep"),
+            Self::Stop => f.write_str("stop"),
+            Self::Zeros => f.write_str("zeros"),
+            Self::Fade => f.write_str("fade"),
+            Self::Preset(name) => write!(f, "preset:{name}"),
+        }
+    }
+}
+
+/// Times how long the input has been quiet, and once that reaches the
+/// timeout, idles the output as [`OnSilence`] says until sound returns.
+pub(crate) struct Idler {
+    timeout: Option<Duration>,
+    on_silence: OnSilence,
+    /// Quiet so far, in stream time.
+    quiet: Duration,
+    silent: bool,
+    /// Fraction of the levels still shown while fading out.
+    fade: f32,
+    output: Vec<f32>,
+}
+
+impl Idler {
+    /// An idler for `values` levels per packet; a `timeout` of None never
+    /// idles.
+    pub(crate) fn new(values: usize, timeout: Option<Duration>, on_silence: OnSilence) -> Self {
+        Self {
+            timeout,
+            on_silence,
+            quiet: Duration::ZERO,
+            silent: false,
+            fade: 1.0,
+            output: vec![0.0; values],
+        }
+    }
+
+    /// Note another `elapsed` of sound, or of quiet; the event if the
+    /// input just fell silent or came back.
+    pub(crate) fn update(&mut self, sound: bool, elapsed: Duration) -> Option<Event> {
+        if sound {
+            self.quiet = Duration::ZERO;
+            self.fade = 1.0;
+            return std::mem::take(&mut self.silent).then_some(Event::Resumed);
+        }
+
+        self.quiet += elapsed;
+        if self.silent || self.timeout.is_none_or(|timeout| self.quiet < timeout) {
+            return None;
+        }
+
+        self.silent = true;
+        Some(Event::Silence)
+    }
+
+    /// What to send in place of `levels`, `elapsed` after the previous
+    /// packet; None to send nothing.
+    pub(crate) fn output<'a>(
+        &'a mut self,
+        levels: &'a [f32],
+        elapsed: Duration,
+    ) -> Option<&'a [f32]> {
+        if !self.silent {
+            return Some(levels);
+        }
+
+        match self.on_silence {
+            OnSilence::Keep => Some(levels),
+            OnSilence::Zeros => {
+                self.output.fill(0.0);
+                Some(&self.output)
+            }
+            // The last step sends a dark frame; after that, nothing.
+            OnSilence::Fade if self.fade > 0.0 => {
+                self.fade = (self.fade - elapsed.as_secs_f32() / FADE_OUT.as_secs_f32()).max(0.0);
+                for (out, &level) in self.output.iter_mut().zip(levels) {
+                    *out = level * self.fade;
+                }
+                Some(&self.output)
+            }
+            OnSilence::Fade | OnSilence::Stop | OnSilence::Preset(_) => None,
+        }
+    }
+}
+
+/// Runs an [`OnSilence::Preset`] fallback preset while the input is
+/// silent, and the stream's own preset again once sound returns.
+pub struct Fallback<'a> {
+    client: Client,
+    fallback: String,
+    /// The stream's preset, with its args.
+    preset: (String, Value),
+    stand_by: Option<Box<dyn Fn(bool) + Send + Sync + 'a>>,
+}
+
+impl<'a> Fallback<'a> {
+    pub fn new(client: Client, fallback: &str, preset: &str, args: Value) -> Self {
+        Self {
+            client,
+            fallback: fallback.to_string(),
+            preset: (preset.to_string(), args),
+            stand_by: None,
+        }
+    }
+
+    /// Call `stand_by(true)` before handing the server to the fallback and
+    /// `stand_by(false)` once it's back, so whatever watches for the
+    /// stream's preset being preempted doesn't mistake the fallback for
+    /// someone else's.
+    pub fn with_stand_by(mut self, stand_by: impl Fn(bool) + Send + Sync + 'a) -> Self {
+        self.stand_by = Some(Box::new(stand_by));
+        self
+    }
+
+    /// Switch presets on [`Event::Silence`] and [`Event::Resumed`];
+    /// other events are ignored.
+    pub fn event(&self, event: &Event) -> Result<()> {
+        let silent = match event {
+            Event::Silence => true,
+            Event::Resumed => false,
+            _ => return Ok(()),
+        };
+
+        // Events arrive inside the streamer's async runtime, where the
+        // blocking HTTP client must not run; switch from a plain thread.
+        thread::scope(|scope| scope.spawn(|| self.switch(silent)).join())
+            .unwrap_or_else(|_| Err(anyhow!("switching presets panicked")))
+    }
+
+    fn switch(&self, silent: bool) -> Result<()> {
+        if silent {
+            self.stand_by(true);
+            return self
+                .client
+                .start(&self.fallback, &json!({}))
+                .with_context(|| format!("switching to preset {}", self.fallback));
+        }
+
+        // Only take back what the fallback holds; anyone else switching
+        // meanwhile is the supervisor's to deal with.
+        let (preset, args) = &self.preset;
+        let result = self.client.running().and_then(|running| {
+            if running.is_some_and(|running| running.name == self.fallback) {
+                self.client.start(preset, args)?;
+            }
+            Ok(())
+        });
+        self.stand_by(false);
+
+        result.with_context(|| format!("switching back to preset {preset}"))
+    }
+
+    fn stand_by(&self, standing_by: bool) {
+        if let Some(stand_by) = &self.stand_by {
+            stand_by(standing_by);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idles_after_the_timeout_and_fades_out() {
+        let ms = Duration::from_millis;
+        let mut idler = Idler::new(1, Some(ms(1000)), OnSilence::Fade);
+
+        assert_eq!(idler.update(false, ms(600)), None);
+        assert_eq!(
+            idler.update(true, ms(100)),
+            None,
+            "sound restarts the clock"
+        );
+        assert_eq!(idler.update(false, ms(600)), None);
+        assert_eq!(idler.update(false, ms(600)), Some(Event::Silence));
+        assert_eq!(idler.update(false, ms(600)), None, "reported once");
+
+        assert_eq!(idler.output(&[0.8], ms(1000)), Some(&[0.4][..]));
+        assert_eq!(idler.output(&[0.8], ms(1000)), Some(&[0.0][..]));
+        assert_eq!(idler.output(&[0.8], ms(1000)), None);
+
+        assert_eq!(idler.update(true, ms(100)), Some(Event::Resumed));
+        assert_eq!(idler.output(&[0.8], ms(100)), Some(&[0.8][..]));
+    }
+
+    #[test]
+    fn parses_silence_actions() {
+        assert_eq!("zeros".parse(), Ok(OnSilence::Zeros));
+        assert_eq!(
+            "preset:Rainbow".parse(),
+            Ok(OnSilence::Preset("Rainbow".to_string()))
+        );
+        assert!("preset:".parse::<OnSilence>().is_err());
+        assert_eq!(
+            OnSilence::Preset("Rainbow".into()).to_string(),
+            "preset:Rainbow"
+        );
+    }
+}
@@ -1,9 +1,19 @@
-use anyhow::{Context, Result};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
 use owo_colors::OwoColorize;
 use serde_json::json;
 
 use lightwave_ambilight::{Config, Edge, Streamer};
-use lightwave_core::Client;
+use lightwave_core::{
+    Client,
+    net::{Encoding, PacketFormat, Protocol, SendOptions, UdpOptions},
+};
+
+use crate::commands::controls::Controls;
+use crate::commands::stream::{
+    OnPreempt, Reporter, Supervisor, restore_or_stop, stats_interval, stream_url,
+};
 
 #[derive(clap::Args)]
 pub struct AmbilightArgs {
@@ -44,44 +54,191 @@ pub struct AmbilightArgs {
     #[arg(long)]
     reselect: bool,
 
-    /// UDP port the ambilight preset listens on
-    #[arg(long, default_value_t = 5556)]
-    port: u16,
+    /// Output protocol: lightwave (the server's ambilight preset), or ddp,
+    /// e131 or artnet to drive an LED controller directly
+    #[arg(long, default_value = "lightwave")]
+    protocol: Protocol,
+
+    /// Host to stream to [default: the server's host]
+    #[arg(long)]
+    host: Option<String>,
+
+    /// UDP port to stream to [default: 5556 for lightwave, else the
+    /// protocol's standard port]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// First e131/artnet universe; frames beyond 170 pixels continue in
+    /// the following universes [default: 1 for e131, 0 for artnet]
+    #[arg(long)]
+    universe: Option<u16>,
+
+    /// E1.31 source priority (0-200)
+    #[arg(long, default_value_t = 100)]
+    priority: u8,
+
+    /// Hops multicast packets may travel (1 = local subnet only)
+    #[arg(long, default_value_t = 1)]
+    multicast_ttl: u32,
+
+    /// Interface for multicast targets: a name, or an IPv4 address /
+    /// IPv6 interface index [default: by route]
+    #[arg(long)]
+    multicast_interface: Option<String>,
+
+    /// Don't loop multicast back to listeners on this host
+    #[arg(long)]
+    no_multicast_loop: bool,
+
+    /// Packet layout: legacy (bare f32 values) or framed (versioned
+    /// header with stream kind, count, sequence number and timestamp)
+    #[arg(long, default_value = "legacy")]
+    packet_format: PacketFormat,
+
+    /// Payload encoding: f32, u16, u8 (quantized with temporal dithering)
+    /// or delta (u8, only changed elements); all but f32 need
+    /// --packet-format framed
+    #[arg(long, default_value = "f32")]
+    encoding: Encoding,
 
     /// Name of the ambilight preset on the server
     #[arg(long, default_value = "Ambilight")]
     preset: String,
 
-    /// Stream UDP only; don't start/stop the preset (assume it's running)
+    /// Seconds between stream stats: a status line, or "stats" events
+    /// with --json [default: 1, or 5 with --json; 0 = off]
+    #[arg(long)]
+    stats_interval: Option<f64>,
+
+    /// Refused sends in a row before warning that nothing is listening
+    #[arg(long, default_value_t = 30)]
+    unreachable_after: u64,
+
+    /// Exit (status 3) instead of just warning when nothing is listening
+    #[arg(long)]
+    fail_on_unreachable: bool,
+
+    /// When the server switches to another preset: exit, wait (pause
+    /// until the server is idle, then restart the preset) or reclaim
+    /// (restart it right away). A preset stopped outright ends the
+    /// stream unless reclaiming
+    #[arg(long, default_value = "exit")]
+    on_preempt: OnPreempt,
+
+    /// Seconds between checks that the preset is still running (0 = off)
+    #[arg(long, default_value_t = 5.0)]
+    preempt_interval: f64,
+
+    /// On exit, restart whatever preset was running before instead of
+    /// stopping (with its default args; the server doesn't report them)
+    #[arg(long)]
+    restore: bool,
+
+    /// Stream UDP only; don't start/stop the preset (assume it's running).
+    /// Implied by protocols other than lightwave
     #[arg(long)]
     no_start: bool,
 }
 
-pub fn run(client: &Client, args: &AmbilightArgs, json_mode: bool) -> Result<()> {
-    let target = format!("{}:{}", client.host(), args.port);
-
-    let config = Config {
-        boxes: args.boxes,
-        edge: args.edge,
-        depth: args.depth,
-        vividness: args.vividness,
-        gamma: args.gamma,
-        min_saturation: args.min_saturation,
-        reverse: args.reverse,
-        fps: args.fps,
-        reselect: args.reselect,
-        target: target.clone(),
-    };
+impl AmbilightArgs {
+    /// UDP port to stream to.
+    pub fn port(&self) -> u16 {
+        self.port.or(self.protocol.default_port()).unwrap_or(5556)
+    }
+
+    /// `host:port` to stream to, defaulting to the server's host.
+    pub fn target(&self, client: &Client) -> String {
+        format!(
+            "{}:{}",
+            self.host.as_deref().unwrap_or(client.host()),
+            self.port()
+        )
+    }
+
+    /// Whether to start and stop the preset. Other protocols feed a
+    /// controller directly; there's no preset to run.
+    pub fn manages_preset(&self) -> bool {
+        !self.no_start && self.protocol == Protocol::LightWave
+    }
+
+    pub fn preset(&self) -> &str {
+        &self.preset
+    }
+
+    pub fn config(
+        &self,
+        client: &Client,
+        profile: Option<&str>,
+        json_mode: bool,
+    ) -> Result<Config> {
+        Ok(Config {
+            boxes: self.boxes,
+            edge: self.edge,
+            depth: self.depth,
+            vividness: self.vividness,
+            gamma: self.gamma,
+            min_saturation: self.min_saturation,
+            reverse: self.reverse,
+            profile: profile.map(str::to_string),
+            fps: self.fps,
+            reselect: self.reselect,
+            target: self.target(client),
+            output: SendOptions {
+                protocol: self.protocol,
+                format: self.packet_format,
+                encoding: self.encoding,
+                universe: self.universe,
+                priority: self.priority,
+                udp: UdpOptions {
+                    multicast_ttl: self.multicast_ttl,
+                    multicast_interface: self.multicast_interface.clone(),
+                    multicast_loop: !self.no_multicast_loop,
+                },
+            },
+            stats_interval: stats_interval(self.stats_interval, json_mode)?,
+            unreachable_after: self.unreachable_after,
+            fail_on_unreachable: self.fail_on_unreachable,
+        })
+    }
+}
+
+pub fn run(
+    client: &Client,
+    args: &AmbilightArgs,
+    profile: Option<&str>,
+    json_mode: bool,
+) -> Result<()> {
+    let port = args.port();
+    let target = args.target(client);
+    let manage_preset = args.manages_preset();
+    let config = args.config(client, profile, json_mode)?;
+
+    let preempt_interval = Duration::try_from_secs_f64(args.preempt_interval)
+        .map_err(|_| anyhow!("preempt-interval must be a finite number >= 0"))?;
 
     let streamer = Streamer::new(&config)?;
     let (width, height) = streamer.size();
 
-    if !args.no_start {
+    // Remember what to put back on exit (our own preset doesn't count).
+    let previous = if manage_preset && args.restore {
         client
-            .start(&args.preset, &json!({ "port": args.port }))
+            .running()
+            .context("checking the running preset")?
+            .map(|running| running.name)
+            .filter(|name| *name != args.preset)
+    } else {
+        None
+    };
+
+    let start_args = json!({ "port": port });
+    if manage_preset {
+        client
+            .start(&args.preset, &start_args)
             .with_context(|| format!("starting preset {}", args.preset))?;
     }
 
+    let controls = Controls::spawn(streamer.tuning(), streamer.control(), json_mode);
+
     if json_mode {
         // First line on stdout confirms the capture and socket are up and the
         // preset was started; streaming begins right after. Consumers can block
@@ -92,16 +249,21 @@ pub fn run(client: &Client, args: &AmbilightArgs, json_mode: bool) -> Result<()>
             "width": width,
             "height": height,
             "target": target,
+            "protocol": args.protocol.to_string(),
+            "destination": streamer.destination().to_string(),
             "boxes": args.boxes,
             "edge": args.edge.to_string(),
             "fps": args.fps,
+            "packet_format": args.packet_format.to_string(),
+            "encoding": args.encoding.to_string(),
+            "tuning": streamer.tuning().params(),
         }))?;
     } else {
         println!(
             "\n  {} {}  {}",
             "▦".bright_cyan(),
             format!("{width}×{height}").bright_white().bold(),
-            format!("→ udp://{target}").dimmed()
+            format!("→ {}", stream_url(args.protocol, &target)).dimmed()
         );
         println!(
             "  {} {} edge · {} boxes · depth {} · vividness {} · gamma {} · {} fps",
@@ -114,27 +276,56 @@ pub fn run(client: &Client, args: &AmbilightArgs, json_mode: bool) -> Result<()>
             args.fps
         );
         println!(
-            "  {} streaming, press {} to stop\n",
+            "  {} streaming, press {} to stop",
             "▶".bright_green(),
             "Ctrl+C".bright_yellow().bold()
         );
+        if controls.keyboard() {
+            println!(
+                "  {} {} pick a setting, {} adjust it",
+                "›".dimmed(),
+                "↑↓".bright_yellow().bold(),
+                "←→".bright_yellow().bold()
+            );
+        }
+        println!();
+    }
+
+    let mut reporter = Reporter::new(json_mode);
+    if args.protocol == Protocol::LightWave {
+        reporter = reporter.with_preset(client, &args.preset);
+    }
+    if controls.keyboard() {
+        reporter = reporter.with_tuning(streamer.tuning());
     }
+    let supervisor = (manage_preset && preempt_interval > Duration::ZERO).then(|| {
+        Supervisor::spawn(
+            client,
+            &args.preset,
+            start_args,
+            args.on_preempt,
+            preempt_interval,
+            streamer.control(),
+        )
+    });
 
-    let result = streamer.run();
+    let result = streamer.run(|event| reporter.event(event));
+    reporter.finish();
+    drop(controls);
 
-    if !args.no_start
-        && let Err(err) = client.stop()
-    {
-        eprintln!("warning: failed to stop preset: {err:#}");
+    // Once preempted, the running preset is someone else's to stop.
+    let preempted = supervisor.is_some_and(Supervisor::finish);
+    if manage_preset && !preempted {
+        restore_or_stop(client, previous.as_deref());
     }
 
-    result?;
+    let reason = result?;
 
     if json_mode {
         // Errors skip this: they surface as the final {"ok":false,...} line.
         crate::commands::print_json(&json!({
             "event": "stop",
-            "reason": "interrupt",
+            "reason": reason.to_string(),
         }))?;
     } else {
         println!("  {} stopped", "■".bright_red());
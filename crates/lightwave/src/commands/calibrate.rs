@@ -0,0 +1,222 @@
+use std::io::{self, Write};
+
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+use serde_json::json;
+
+use lightwave_core::{
+    Client,
+    calibration::Profile,
+    color::{parse_hex_rgb, to_hex},
+    config::Config,
+};
+
+#[derive(clap::Args)]
+pub struct CalibrateArgs {
+    /// Relative change per tint correction (0.04 = 4%)
+    #[arg(long, default_value_t = 0.04)]
+    step: f32,
+
+    /// Grey level used to balance the strip's low end
+    #[arg(long, default_value_t = 0.2)]
+    grey: f32,
+}
+
+/// Channels a reported tint says are too strong.
+fn tint_channels(tint: char) -> Option<&'static [usize]> {
+    match tint {
+        'r' => Some(&[0]),
+        'g' => Some(&[1]),
+        'b' => Some(&[2]),
+        'c' => Some(&[1, 2]),
+        'm' => Some(&[0, 2]),
+        'y' => Some(&[0, 1]),
+        _ => None,
+    }
+}
+
+/// Walk the user through balancing full white (white point) and a dim
+/// grey (per-channel gamma) by eye, then save the result as the
+/// `--profile` profile. The matrix and gain of an existing profile are
+/// kept; edit them in the config file.
+pub fn run(
+    client: &Client,
+    args: &CalibrateArgs,
+    profile: Option<&str>,
+    json_mode: bool,
+) -> Result<()> {
+    if !(args.step > 0.0 && args.step < 0.5) {
+        bail!("step must be in (0, 0.5), got {}", args.step);
+    }
+
+    if !(args.grey > 0.0 && args.grey < 1.0) {
+        bail!("grey must be in (0, 1), got {}", args.grey);
+    }
+
+    let mut config = Config::load()?;
+    let name = profile
+        .or(config.profile.as_deref())
+        .unwrap_or("default")
+        .to_string();
+    let mut candidate = config.profiles.get(&name).cloned().unwrap_or_default();
+    let shrink = 1.0 - args.step;
+
+    let say = |text: String| {
+        if json_mode {
+            eprintln!("{text}");
+        } else {
+            println!("{text}");
+        }
+    };
+
+    say(format!(
+        "\n  {} calibrating profile {}",
+        "◐".bright_cyan(),
+        name.bright_white().bold()
+    ));
+    say(format!(
+        "  {} at each patch, type the tint you see — {}ed {}reen {}lue {}yan {}agenta {}ellow\n    \
+         (repeat a letter for a bigger step), Enter once it looks neutral, q to abort\n",
+        "›".dimmed(),
+        "r".bold(),
+        "g".bold(),
+        "b".bold(),
+        "c".bold(),
+        "m".bold(),
+        "y".bold()
+    ));
+
+    say(format!("  {} full white", "1.".bold()));
+    balance(
+        client,
+        &mut candidate,
+        1.0,
+        json_mode,
+        |profile, channels| {
+            for &i in channels {
+                profile.white_point[i] *= shrink;
+            }
+
+            let max = profile.white_point.iter().copied().fold(0.0, f32::max);
+            for v in &mut profile.white_point {
+                *v /= max;
+            }
+        },
+    )?;
+
+    // Dimming channel i by `shrink` at level L means L^γ' = L^γ · shrink.
+    let gamma_floor = candidate
+        .gamma
+        .iter()
+        .copied()
+        .fold(f32::INFINITY, f32::min);
+    let delta = shrink.ln() / args.grey.ln();
+
+    say(format!("  {} dim grey", "2.".bold()));
+    balance(
+        client,
+        &mut candidate,
+        args.grey,
+        json_mode,
+        |profile, channels| {
+            for &i in channels {
+                profile.gamma[i] += delta;
+            }
+
+            // Raising every gamma together only dims; keep the lowest in place.
+            let min = profile.gamma.iter().copied().fold(f32::INFINITY, f32::min);
+            for g in &mut profile.gamma {
+                *g -= min - gamma_floor;
+            }
+        },
+    )?;
+
+    candidate.validate()?;
+    client.set_color(&to_hex(candidate.apply_srgb([1.0; 3])))?;
+
+    config.profiles.insert(name.clone(), candidate.clone());
+    if config.profile.is_none() {
+        config.profile = Some(name.clone());
+    }
+    let path = config.save()?;
+
+    if json_mode {
+        crate::commands::print_ok_json(json!({
+            "action": "calibrate",
+            "profile": name,
+            "path": path.display().to_string(),
+            "white_point": candidate.white_point,
+            "gamma": candidate.gamma,
+        }))?;
+    } else {
+        println!(
+            "  {} saved {} to {}",
+            "✓".bright_green(),
+            name.bright_white().bold(),
+            path.display().dimmed()
+        );
+        println!(
+            "  {} white point {:.3?} · gamma {:.3?}\n",
+            "›".dimmed(),
+            candidate.white_point,
+            candidate.gamma
+        );
+    }
+
+    Ok(())
+}
+
+/// Show a grey patch at `level` through the candidate profile until the
+/// user calls it neutral, letting `adjust` react to each reported tint.
+fn balance(
+    client: &Client,
+    candidate: &mut Profile,
+    level: f32,
+    json_mode: bool,
+    mut adjust: impl FnMut(&mut Profile, &[usize]),
+) -> Result<()> {
+    loop {
+        let hex = to_hex(candidate.apply_srgb([level; 3]));
+        client.set_color(&hex)?;
+
+        let [r, g, b] = parse_hex_rgb(&hex).unwrap_or([255; 3]);
+        let prompt = format!("    {} {} tint? ", "██".truecolor(r, g, b), hex.dimmed());
+        let answer = read_answer(&prompt, json_mode)?;
+
+        if answer.is_empty() {
+            return Ok(());
+        }
+
+        if answer == "q" {
+            bail!("calibration aborted; nothing saved");
+        }
+
+        for tint in answer.chars() {
+            match tint_channels(tint) {
+                Some(channels) => adjust(candidate, channels),
+                None => eprintln!("    unknown tint {tint:?}; use r, g, b, c, m or y"),
+            }
+        }
+    }
+}
+
+fn read_answer(prompt: &str, json_mode: bool) -> Result<String> {
+    if json_mode {
+        eprint!("{prompt}");
+        io::stderr().flush()?;
+    } else {
+        print!("{prompt}");
+        io::stdout().flush()?;
+    }
+
+    let mut line = String::new();
+    let read = io::stdin()
+        .read_line(&mut line)
+        .context("reading calibration answer")?;
+
+    if read == 0 {
+        bail!("calibration aborted (end of input); nothing saved");
+    }
+
+    Ok(line.trim().to_lowercase())
+}
@@ -0,0 +1,279 @@
+//! Shell completion scripts. The static part comes from clap; on top of
+//! it each script asks `lightwave __complete` for preset names, a preset's
+//! `--arg` flags and their values, which only the server knows.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Command;
+
+use lightwave_core::Client;
+use lightwave_core::cache::{self, Cache, Cached};
+
+/// Completing is interactive; a slow server gets no candidates rather
+/// than a stuck prompt.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "elvish" => Ok(Self::Elvish),
+            _ => Err(format!(
+                "unknown shell {s:?}; expected bash, zsh, fish or elvish"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+            Self::Elvish => "elvish",
+        })
+    }
+}
+
+/// Print the completion script for `shell`.
+pub fn print(shell: Shell, cmd: &mut Command) -> Result<()> {
+    let generator = match shell {
+        Shell::Bash => clap_complete::Shell::Bash,
+        Shell::Zsh => clap_complete::Shell::Zsh,
+        Shell::Fish => clap_complete::Shell::Fish,
+        Shell::Elvish => clap_complete::Shell::Elvish,
+    };
+
+    let mut script = Vec::new();
+    clap_complete::generate(generator, cmd, "lightwave", &mut script);
+    let script = String::from_utf8(script).context("completion script is not UTF-8")?;
+
+    let script = match shell {
+        Shell::Bash => script + BASH,
+        // The generated `_lightwave` becomes the fallback; the file still
+        // ends by calling (or registering) `_lightwave`, now ours.
+        Shell::Zsh => {
+            let script = script.replacen("\n_lightwave() {\n", "\n_lightwave_static() {\n", 1);
+            match script.rfind("\nif [ \"$funcstack[1]\" = \"_lightwave\" ]") {
+                Some(at) => format!("{}\n{ZSH}{}", &script[..at], &script[at..]),
+                None => script + ZSH,
+            }
+        }
+        Shell::Fish => script + FISH,
+        Shell::Elvish => script + ELVISH,
+    };
+
+    io::stdout()
+        .lock()
+        .write_all(script.as_bytes())
+        .context("writing completion script")
+}
+
+const BASH: &str = r#"
+_lightwave_dynamic() {
+    local line=${COMP_LINE:0:COMP_POINT} words out
+    read -ra words <<< "$line"
+    [[ $line == *[[:space:]] ]] && words+=("")
+
+    if out=$("$1" __complete -- "${words[@]:1}" 2>/dev/null); then
+        local IFS=$'\n'
+        COMPREPLY=($out)
+        return 0
+    fi
+
+    _lightwave "$@"
+}
+
+if [[ "${BASH_VERSINFO[0]}" -eq 4 && "${BASH_VERSINFO[1]}" -ge 4 || "${BASH_VERSINFO[0]}" -gt 4 ]]; then
+    complete -F _lightwave_dynamic -o nosort -o bashdefault -o default lightwave
+else
+    complete -F _lightwave_dynamic -o bashdefault -o default lightwave
+fi
+"#;
+
+const ZSH: &str = r#"
+_lightwave() {
+    local out
+    if out=$(${words[1]} __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null); then
+        local -a candidates
+        candidates=(${(f)out})
+        compadd -a candidates
+        return
+    fi
+
+    _lightwave_static "$@"
+}
+"#;
+
+const FISH: &str = r#"
+function __fish_lightwave_dynamic
+    set -l words (commandline -opc)
+    command $words[1] __complete -- $words[2..-1] (commandline -ct) 2>/dev/null
+end
+
+complete -c lightwave -n "__fish_lightwave_using_subcommand info start" -f -a "(__fish_lightwave_dynamic)"
+"#;
+
+const ELVISH: &str = r#"
+var lightwave-static = $edit:completion:arg-completer[lightwave]
+set edit:completion:arg-completer[lightwave] = {|@words|
+    try {
+        var candidates = [((external $words[0]) __complete -- $@words[1..] 2>/dev/null)]
+        all $candidates
+    } catch {
+        $lightwave-static $@words
+    }
+}
+"#;
+
+/// Complete the last of `words` (the command line after `lightwave`,
+/// the word being typed last) and print one candidate per line. Exits 1
+/// when there is nothing dynamic to offer, so the script falls back to
+/// the static completions.
+pub fn complete(base: &str, words: &[String]) -> Result<()> {
+    let Some(candidates) = candidates(base, words) else {
+        std::process::exit(1);
+    };
+
+    let mut stdout = io::stdout().lock();
+    for candidate in candidates {
+        writeln!(stdout, "{candidate}")?;
+    }
+
+    Ok(())
+}
+
+/// Where on the command line the word being completed sits.
+struct Position<'a> {
+    server: Option<&'a str>,
+    /// `info` or `start`, and the preset once it's typed.
+    command: &'a str,
+    preset: Option<&'a str>,
+    /// Words typed after the preset.
+    rest: &'a [String],
+    current: &'a str,
+}
+
+impl<'a> Position<'a> {
+    /// `None` when completing anything but a preset or its args.
+    fn find(words: &'a [String]) -> Option<Self> {
+        let (current, done) = words.split_last()?;
+        let mut server = None;
+        let mut command = None;
+        let mut preset = None;
+        let mut rest: &[String] = &[];
+        let mut i = 0;
+
+        while i < done.len() {
+            let word = done[i].as_str();
+            i += 1;
+
+            match word {
+                "--server" | "--profile" => {
+                    // The word being completed is this flag's value.
+                    let value = done.get(i)?;
+                    if word == "--server" {
+                        server = Some(value.as_str());
+                    }
+                    i += 1;
+                }
+                _ if word.starts_with("--server=") => server = Some(&word["--server=".len()..]),
+                _ if word.starts_with('-') => {}
+                _ if command.is_none() => command = Some(word),
+                _ => {
+                    preset = Some(word);
+                    rest = &done[i..];
+                    break;
+                }
+            }
+        }
+
+        Some(Self {
+            server,
+            command: command.filter(|command| matches!(*command, "info" | "start"))?,
+            preset,
+            rest,
+            current,
+        })
+    }
+}
+
+fn candidates(base: &str, words: &[String]) -> Option<Vec<String>> {
+    let position = Position::find(words)?;
+    let current = position.current;
+
+    let client = Client::with_timeout(position.server.unwrap_or(base), TIMEOUT).ok()?;
+    let cache = Cache::new(client, cache::DEFAULT_MAX_AGE);
+
+    let Some(preset) = position.preset else {
+        if current.starts_with('-') {
+            return None;
+        }
+
+        let presets = cache
+            .presets()
+            .map(|list| list.value.presets)
+            .unwrap_or_default();
+        return Some(matching(presets.into_iter().map(|p| p.name), current));
+    };
+
+    if position.command != "start" {
+        return None;
+    }
+
+    let Ok(Cached { value: info, .. }) = cache.preset_info(preset) else {
+        return Some(Vec::new());
+    };
+
+    // Right after `--name`: that arg's value.
+    if let Some(flag) = position
+        .rest
+        .last()
+        .and_then(|word| word.strip_prefix("--"))
+        && let Some(arg) = info.args.iter().find(|arg| arg.name == flag)
+    {
+        return Some(matching(super::start::suggestions(arg), current));
+    }
+
+    if !current.is_empty() && !current.starts_with('-') {
+        return Some(Vec::new());
+    }
+
+    let flags = info
+        .args
+        .iter()
+        .filter(|arg| {
+            !position
+                .rest
+                .iter()
+                .any(|word| word.strip_prefix("--") == Some(arg.name.as_str()))
+        })
+        .map(|arg| format!("--{}", arg.name))
+        .chain(["--help".to_string()]);
+
+    Some(matching(flags, current))
+}
+
+fn matching(candidates: impl IntoIterator<Item = String>, prefix: &str) -> Vec<String> {
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect()
+}
@@ -0,0 +1,260 @@
+//! Live tuning for the streaming commands: arrow keys when stdin is a
+//! terminal, JSON commands on stdin with `--json`.
+
+use std::io::{self, BufRead};
+#[cfg(unix)]
+use std::io::{IsTerminal, Read};
+use std::thread;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
+
+use lightwave_core::stream::{Control, Event};
+use lightwave_core::tuning::Tuning;
+
+/// Reads stdin on a background thread while a stream runs. Dropping it
+/// puts the terminal back the way it was.
+pub struct Controls {
+    keyboard: bool,
+    #[cfg(unix)]
+    _terminal: Option<Cbreak>,
+}
+
+impl Controls {
+    /// JSON commands with `--json`; keys when stdin is a terminal (Unix
+    /// only); nothing otherwise.
+    pub fn spawn(tuning: Tuning, control: Control, json_mode: bool) -> Self {
+        if json_mode {
+            thread::spawn(move || commands(&tuning, &control));
+            return Self::none();
+        }
+
+        #[cfg(unix)]
+        if io::stdin().is_terminal()
+            && let Some(terminal) = Cbreak::enable()
+        {
+            thread::spawn(move || keys(&tuning, &control));
+            return Self {
+                keyboard: true,
+                _terminal: Some(terminal),
+            };
+        }
+
+        Self::none()
+    }
+
+    /// Whether arrow keys adjust the tuning.
+    pub fn keyboard(&self) -> bool {
+        self.keyboard
+    }
+
+    /// Nothing reads stdin (it's carrying audio, say).
+    pub fn none() -> Self {
+        Self {
+            keyboard: false,
+            #[cfg(unix)]
+            _terminal: None,
+        }
+    }
+}
+
+/// Apply one JSON command per line until stdin closes:
+///
+/// ```json
+/// {"cmd":"set","param":"gamma","value":1.8}
+/// {"cmd":"nudge","param":"gain","steps":-2}
+/// {"cmd":"tuning"}
+/// ```
+///
+/// Changes are confirmed by a `tuned` event, bad commands get a warning.
+fn commands(tuning: &Tuning, control: &Control) {
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            return;
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Err(err) = command(&line, tuning, control) {
+            let _ = super::print_json(&json!({
+                "event": "warning",
+                "kind": "command",
+                "detail": format!("{err:#}"),
+            }));
+        }
+    }
+}
+
+fn command(line: &str, tuning: &Tuning, control: &Control) -> Result<()> {
+    let command: Value = serde_json::from_str(line).context("invalid command")?;
+    let param = || {
+        command["param"]
+            .as_str()
+            .ok_or_else(|| anyhow!("missing \"param\""))
+    };
+
+    let (param, value) = match command["cmd"].as_str() {
+        Some("set") => {
+            let param = param()?;
+            let value = command["value"]
+                .as_f64()
+                .ok_or_else(|| anyhow!("missing numeric \"value\""))?;
+            tuning.set(param, value as f32)?;
+            (param, value as f32)
+        }
+        Some("nudge") => {
+            let param = param()?;
+            let steps = command["steps"].as_i64().unwrap_or(1);
+            let steps = i32::try_from(steps).context("steps out of range")?;
+            (param, tuning.nudge(param, steps)?)
+        }
+        Some("tuning") => {
+            return super::print_json(&json!({
+                "event": "tuning",
+                "params": tuning.params(),
+            }));
+        }
+        Some(cmd) => bail!("unknown command {cmd:?}; expected set, nudge or tuning"),
+        None => bail!("missing \"cmd\""),
+    };
+
+    control.report(Event::Tuned {
+        param: param.to_string(),
+        value,
+    });
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Pick a parameter with ↑/↓ (or Tab) and adjust it with ←/→ (or -/+),
+/// reporting each change so the status line redraws.
+#[cfg(unix)]
+fn keys(tuning: &Tuning, control: &Control) {
+    let names = tuning
+        .params()
+        .iter()
+        .map(|param| param.name)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return;
+    }
+
+    let mut selected = 0;
+    let mut pending = Vec::new();
+    let mut buf = [0u8; 32];
+    let mut stdin = io::stdin().lock();
+
+    loop {
+        let len = match stdin.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(len) => len,
+        };
+        pending.extend_from_slice(&buf[..len]);
+
+        for key in parse_keys(&mut pending) {
+            match key {
+                Key::Up => selected = (selected + names.len() - 1) % names.len(),
+                Key::Down => selected = (selected + 1) % names.len(),
+                Key::Left | Key::Right => {
+                    let steps = if key == Key::Left { -1 } else { 1 };
+                    let _ = tuning.nudge(names[selected], steps);
+                }
+            }
+
+            let param = names[selected];
+            control.report(Event::Tuned {
+                param: param.to_string(),
+                value: tuning.get(param).unwrap_or_default(),
+            });
+        }
+    }
+}
+
+/// Take the keys out of `pending`, leaving an incomplete escape sequence
+/// for the next read. Anything else is ignored.
+#[cfg(unix)]
+fn parse_keys(pending: &mut Vec<u8>) -> Vec<Key> {
+    const ESC: u8 = 0x1b;
+
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < pending.len() {
+        let key = match pending[i] {
+            ESC if pending.len() - i < 3 => break,
+            // CSI (`ESC [`) or SS3 (`ESC O`, application cursor mode).
+            ESC if matches!(pending[i + 1], b'[' | b'O') => {
+                i += 2;
+                match pending[i] {
+                    b'A' => Some(Key::Up),
+                    b'B' => Some(Key::Down),
+                    b'C' => Some(Key::Right),
+                    b'D' => Some(Key::Left),
+                    _ => None,
+                }
+            }
+            b'\t' => Some(Key::Down),
+            b'+' | b'=' => Some(Key::Right),
+            b'-' | b'_' => Some(Key::Left),
+            _ => None,
+        };
+
+        keys.extend(key);
+        i += 1;
+    }
+
+    pending.drain(..i);
+    keys
+}
+
+/// The terminal in cbreak mode: keys arrive as they're pressed and aren't
+/// echoed, but Ctrl+C still raises SIGINT and output is left alone.
+#[cfg(unix)]
+struct Cbreak {
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl Cbreak {
+    fn enable() -> Option<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in; the
+        // calls only touch stdin's terminal settings.
+        unsafe {
+            let mut saved: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return None;
+            }
+
+            let mut cbreak = saved;
+            cbreak.c_lflag &= !(libc::ICANON | libc::ECHO);
+            cbreak.c_cc[libc::VMIN] = 1;
+            cbreak.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &cbreak) != 0 {
+                return None;
+            }
+
+            Some(Self { saved })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Cbreak {
+    fn drop(&mut self) {
+        // SAFETY: restores settings tcgetattr returned.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use owo_colors::OwoColorize;
+use serde_json::{Map, Value};
+
+use lightwave_core::daemon::{self, Request, StreamName};
+
+#[derive(clap::Args)]
+pub struct DaemonArgs {
+    /// lightwaved's control socket [default: LIGHTWAVE_SOCKET, else
+    /// $XDG_RUNTIME_DIR/lightwave.sock]
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    cmd: DaemonCmd,
+}
+
+#[derive(clap::Subcommand)]
+enum DaemonCmd {
+    /// Show the daemon's stream, its tuning and latest stats
+    Status,
+    /// Start (or resume) music or ambilight
+    Start {
+        stream: StreamName,
+        /// Stream options as key=value, named like the `music`/`ambilight`
+        /// flags (e.g. bins=64 edge=left host=10.0.0.5)
+        options: Vec<String>,
+    },
+    /// Stop streaming; capture stays open for a quick restart
+    Stop {
+        /// Close the capture too
+        #[arg(long)]
+        release: bool,
+    },
+    /// Change a tuning parameter live (gain; vividness, gamma, min_saturation)
+    Set { param: String, value: f32 },
+    /// Stop streaming and exit the daemon
+    Shutdown,
+}
+
+pub fn run(args: &DaemonArgs, json_mode: bool) -> Result<()> {
+    let request = match &args.cmd {
+        DaemonCmd::Status => Request::Status,
+        DaemonCmd::Start { stream, options } => Request::Start {
+            stream: *stream,
+            options: parse_options(options)?,
+        },
+        DaemonCmd::Stop { release } => Request::Stop { release: *release },
+        DaemonCmd::Set { param, value } => Request::Set {
+            param: param.replace('-', "_"),
+            value: *value,
+        },
+        DaemonCmd::Shutdown => Request::Shutdown,
+    };
+
+    let socket = args.socket.clone().unwrap_or_else(daemon::socket_path);
+    let response = daemon::request(&socket, &request)?;
+
+    if json_mode {
+        return crate::commands::print_json(&response);
+    }
+
+    match request {
+        Request::Status => print_status(&response),
+        Request::Start { stream, .. } => {
+            let verb = if response["resumed"] == true {
+                "resumed"
+            } else {
+                "started"
+            };
+            println!("  {} {stream} {verb}", "▶".bright_green());
+        }
+        Request::Stop { .. } => println!("  {} stopped", "■".bright_red()),
+        Request::Set { param, value } => {
+            println!("  {} {param} = {}", "✓".green(), value.bold())
+        }
+        Request::Shutdown => println!("  {} lightwaved shut down", "■".bright_red()),
+    }
+
+    Ok(())
+}
+
+/// `key=value` pairs into a JSON object. Values are read as JSON when
+/// they parse (numbers, booleans), else taken as strings.
+fn parse_options(options: &[String]) -> Result<Map<String, Value>> {
+    options
+        .iter()
+        .map(|option| {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected key=value, got {option:?}"))?;
+            let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+
+            Ok((key.trim_start_matches("--").replace('-', "_"), value))
+        })
+        .collect()
+}
+
+fn print_status(status: &Value) {
+    let Some(stream) = status["stream"].as_str() else {
+        println!("  {} idle", "○".dimmed());
+        if let Some(error) = status["last_error"].as_str() {
+            println!("  {} {}", "›".dimmed(), error.dimmed());
+        }
+        return;
+    };
+
+    let state = status["state"].as_str().unwrap_or_default();
+    let marker = if state == "running" {
+        "●".green().to_string()
+    } else {
+        "◐".yellow().to_string()
+    };
+    println!(
+        "  {marker} {} {}  {}",
+        stream.bright_white().bold(),
+        state,
+        format!("→ {}", status["target"].as_str().unwrap_or_default()).dimmed()
+    );
+
+    if let Some(params) = status["tuning"].as_array() {
+        let params = params
+            .iter()
+            .map(|param| {
+                format!(
+                    "{} {}",
+                    param["name"].as_str().unwrap_or_default(),
+                    param["value"]
+                )
+            })
+            .collect::<Vec<_>>();
+        println!("  {} {}", "›".dimmed(), params.join(" · "));
+    }
+
+    if let Some(stats) = status["stats"].as_object() {
+        println!(
+            "  {} {} fps · {} sent · {} refused · {} errors",
+            "›".dimmed(),
+            stats["fps"],
+            stats["frames_sent"],
+            stats["refused"],
+            stats["send_errors"]
+        );
+    }
+}
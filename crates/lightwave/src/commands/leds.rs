@@ -1,28 +1,52 @@
 use anyhow::{Result, bail};
 use lightwave_core::{
     Client,
-    color::{normalize, parse_hex_rgb},
+    color::{normalize, parse_hex_rgb, to_hex},
+    config::Config,
 };
 use owo_colors::OwoColorize;
 
-pub fn set(c: &Client, input: &str, json_mode: bool) -> Result<()> {
+pub fn set(c: &Client, input: &str, profile: Option<&str>, json_mode: bool) -> Result<()> {
     let hex = normalize(input)?;
-    c.set_color(&hex)?;
+    let profile = Config::load()?.profile(profile)?;
+
+    // Named colors are resolved server-side, out of the profile's reach.
+    let sent = match parse_hex_rgb(&hex) {
+        Some(rgb) => to_hex(profile.apply_srgb(rgb.map(|c| c as f32 / 255.0))),
+        None => {
+            if !profile.is_identity() {
+                eprintln!(
+                    "warning: named colors are sent uncalibrated; use hex to apply the profile"
+                );
+            }
+            hex.clone()
+        }
+    };
+
+    c.set_color(&sent)?;
 
     if json_mode {
         crate::commands::print_ok_json(serde_json::json!({
             "action": "color_set",
             "color": hex,
+            "sent": sent,
         }))?;
         return Ok(());
     }
 
     if let Some([r, g, b]) = parse_hex_rgb(&hex) {
+        let note = if sent != hex {
+            format!("(calibrated {sent})")
+        } else {
+            String::new()
+        };
+
         println!(
-            "  {} color set to {} {}",
+            "  {} color set to {} {} {}",
             "●".truecolor(r, g, b),
             hex.bright_white().bold(),
-            "██".truecolor(r, g, b)
+            "██".truecolor(r, g, b),
+            note.dimmed()
         );
         return Ok(());
     }
@@ -5,12 +5,23 @@ use serde_json::{Value, json};
 
 #[cfg(feature = "ambilight")]
 pub mod ambilight;
+pub mod calibrate;
+pub mod completions;
+#[cfg(any(feature = "music", feature = "ambilight"))]
+pub mod controls;
+#[cfg(unix)]
+pub mod daemon;
 pub mod leds;
 #[cfg(feature = "music")]
 pub mod music;
 pub mod presets;
 pub mod start;
 pub mod stop;
+#[cfg(any(feature = "music", feature = "ambilight"))]
+pub mod stream;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod white;
 
 pub fn print_json(value: &Value) -> Result<()> {
     let stdout = io::stdout();
@@ -1,16 +1,70 @@
-use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
 use owo_colors::OwoColorize;
 use serde_json::json;
 
-use lightwave_core::Client;
-use lightwave_music::{Config, Streamer};
+use lightwave_core::{
+    Client,
+    config::Config as FileConfig,
+    net::{Encoding, PacketFormat, Protocol, SendOptions, UdpOptions},
+};
+use lightwave_music::{
+    AgcMode, Channels, Config, Fallback, FreqScale, Input, OnSilence, PcmFormat, Reduction, Scale,
+    Streamer, TestSignal, Weighting, Window,
+};
+
+use crate::commands::controls::Controls;
+use crate::commands::stream::{
+    OnPreempt, Reporter, Supervisor, restore_or_stop, stats_interval, stream_url,
+};
 
 #[derive(clap::Args)]
 pub struct MusicArgs {
     /// Capture device (case-insensitive substring match)
-    #[arg(long)]
+    #[arg(long, conflicts_with_all = ["file", "stdin", "signal"])]
     device: Option<String>,
 
+    /// Analyze a WAV or FLAC file instead of capturing, in real time;
+    /// the stream ends with the file
+    #[arg(long, conflicts_with_all = ["stdin", "signal"])]
+    file: Option<PathBuf>,
+
+    /// With --file, analyze as fast as possible, each packet covering
+    /// 1/--fps seconds of audio
+    #[arg(long, requires = "file")]
+    fast: bool,
+
+    /// Read raw interleaved PCM from stdin (see --format, --rate and
+    /// --input-channels); the stream ends when stdin closes
+    #[arg(long, conflicts_with = "signal")]
+    stdin: bool,
+
+    /// With --stdin, pace the PCM as it would play rather than analyzing
+    /// it as it arrives; for pipes that write faster than real time, such
+    /// as a decoder or a raw file
+    #[arg(long, requires = "stdin")]
+    realtime: bool,
+
+    /// Sample encoding on stdin: s16le, s24le, s32le or f32le
+    #[arg(long, default_value = "s16le")]
+    format: PcmFormat,
+
+    /// Sample rate of the PCM on stdin, in Hz
+    #[arg(long, default_value_t = 48_000)]
+    rate: u32,
+
+    /// Interleaved channels in the PCM on stdin (--channels picks which
+    /// are analyzed)
+    #[arg(long, default_value_t = 2)]
+    input_channels: u16,
+
+    /// Analyze a built-in test signal: sweep (20 Hz-20 kHz every 10 s),
+    /// pink (noise) or clicks[:<bpm>] (default 120)
+    #[arg(long)]
+    signal: Option<TestSignal>,
+
     /// List capture devices and exit
     #[arg(long)]
     list_devices: bool,
@@ -19,7 +73,27 @@ pub struct MusicArgs {
     #[arg(long, default_value_t = 2048)]
     fft_size: usize,
 
-    /// Number of frequency bins sent per packet
+    /// Channels to analyze: mono (downmixed), left, right, stereo (left
+    /// then right) or mid-side (mid then side); stereo and mid-side send
+    /// two spectra per packet, back to back
+    #[arg(long, default_value = "mono")]
+    channels: Channels,
+
+    /// FFT window: hann, hamming, blackman-harris (least leakage between
+    /// bands), flat-top (accurate levels) or kaiser
+    #[arg(long, default_value = "hann")]
+    window: Window,
+
+    /// Analyze a window every this many samples (--fft-size/16 up to
+    /// --fft-size) and
+    /// send each bin's loudest level since the last packet, so no
+    /// transient between packets is missed [default: one window per
+    /// packet]
+    #[arg(long)]
+    hop: Option<usize>,
+
+    /// Number of frequency bins sent per packet (with --scale-type
+    /// octave, the range and --octave-fraction set it instead)
     #[arg(long, default_value_t = 32)]
     bins: usize,
 
@@ -27,7 +101,59 @@ pub struct MusicArgs {
     #[arg(long, default_value_t = 4.0)]
     gain: f32,
 
-    /// Capture sample rate in Hz [default: device preference]
+    /// Perceptual weighting of each bin by its center frequency: none,
+    /// a (A-weighting, ear sensitivity at moderate levels) or c (flatter,
+    /// for loud music)
+    #[arg(long, default_value = "none")]
+    weighting: Weighting,
+
+    /// Spectral tilt in dB per octave around 1 kHz; positive brightens
+    /// the highs, which pink-ish music leaves dim
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    tilt: f32,
+
+    /// Ignore the EQ curve saved by --calibrate-pink
+    #[arg(long)]
+    no_eq: bool,
+
+    /// Measure the capture chain with pink noise (play it through the
+    /// system first), save the correcting EQ curve to the config file
+    /// and exit
+    #[arg(long)]
+    calibrate_pink: bool,
+
+    /// Seconds of pink noise to average with --calibrate-pink
+    #[arg(long, default_value_t = 10.0)]
+    calibrate_seconds: f64,
+
+    /// How amplitudes map to brightness: linear, db (--floor-db..
+    /// --ceiling-db mapped to 0-1, so quiet bands show) or sqrt
+    #[arg(long, default_value = "linear")]
+    scale: Scale,
+
+    /// Level shown as dark with --scale db
+    #[arg(long, default_value_t = -60.0, allow_negative_numbers = true)]
+    floor_db: f32,
+
+    /// Level shown at full brightness with --scale db
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    ceiling_db: f32,
+
+    /// Automatic gain control: off, global (one gain from the loudest
+    /// bin) or per-band (each bin normalized on its own)
+    #[arg(long, default_value = "off")]
+    agc: AgcMode,
+
+    /// Level (0-1] the AGC steers bins toward
+    #[arg(long, default_value_t = 0.8)]
+    agc_target: f32,
+
+    /// Most the AGC may amplify, or (as its inverse) attenuate
+    #[arg(long, default_value_t = 10.0)]
+    agc_max_gain: f32,
+
+    /// Sample rate of the capture device or test signal in Hz [default:
+    /// the device's preference, 48000 for test signals]
     #[arg(long)]
     sample_rate: Option<u32>,
 
@@ -43,46 +169,304 @@ pub struct MusicArgs {
     #[arg(long, default_value_t = 16000.0)]
     max_freq: f32,
 
-    /// UDP port the visualizer preset listens on
-    #[arg(long, default_value_t = 5555)]
-    port: u16,
+    /// How bins divide the range: log, mel, bark, linear, or octave
+    /// (fractional-octave bands, see --octave-fraction)
+    #[arg(long, default_value = "log")]
+    scale_type: FreqScale,
+
+    /// Bands per octave with --scale-type octave (3 = third-octave)
+    #[arg(long, default_value_t = 3)]
+    octave_fraction: u32,
+
+    /// How a bin's FFT bins combine: peak, power (mean) or rms
+    #[arg(long, default_value = "peak")]
+    reduction: Reduction,
+
+    /// Milliseconds for bins to rise toward a louder level (0 = instant)
+    #[arg(long, default_value_t = 0.0)]
+    attack: f64,
+
+    /// Milliseconds for bins to fall toward a quieter level (0 = instant)
+    #[arg(long, default_value_t = 0.0)]
+    release: f64,
+
+    /// Milliseconds to hold each bin's peak before it falls at the
+    /// release rate (0 = off)
+    #[arg(long, default_value_t = 0.0)]
+    peak_hold: f64,
+
+    /// Noise gate in dBFS (e.g. -50): bins stay dark while the input is
+    /// quieter, so hiss and fan noise don't light them [default: off]
+    #[arg(long, allow_negative_numbers = true)]
+    gate: Option<f32>,
+
+    /// dB the input must fall below --gate before the gate closes again
+    #[arg(long, default_value_t = 6.0)]
+    gate_hysteresis: f32,
+
+    /// Seconds the input must stay below --gate (or without one, at
+    /// -70 dBFS) to count as silent, emitting "silence" and "resumed"
+    /// events with --json (0 = never)
+    #[arg(long, default_value_t = 10.0)]
+    silence_timeout: f64,
+
+    /// What to do while silent: keep (streaming), stop (sending, letting
+    /// the preset idle), zeros (send dark frames), fade (out over 2 s,
+    /// then stop) or preset:<name> (run that preset until sound returns)
+    #[arg(long, default_value = "keep")]
+    on_silence: OnSilence,
+
+    /// Output protocol: lightwave (the server's visualizer preset), or ddp,
+    /// e131 or artnet to drive an LED controller directly
+    #[arg(long, default_value = "lightwave")]
+    protocol: Protocol,
+
+    /// Host to stream to [default: the server's host]
+    #[arg(long)]
+    host: Option<String>,
+
+    /// UDP port to stream to [default: 5555 for lightwave, else the
+    /// protocol's standard port]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// First e131/artnet universe; frames beyond 170 pixels continue in
+    /// the following universes [default: 1 for e131, 0 for artnet]
+    #[arg(long)]
+    universe: Option<u16>,
+
+    /// E1.31 source priority (0-200)
+    #[arg(long, default_value_t = 100)]
+    priority: u8,
+
+    /// Hops multicast packets may travel (1 = local subnet only)
+    #[arg(long, default_value_t = 1)]
+    multicast_ttl: u32,
+
+    /// Interface for multicast targets: a name, or an IPv4 address /
+    /// IPv6 interface index [default: by route]
+    #[arg(long)]
+    multicast_interface: Option<String>,
+
+    /// Don't loop multicast back to listeners on this host
+    #[arg(long)]
+    no_multicast_loop: bool,
+
+    /// Packet layout: legacy (bare f32 values) or framed (versioned
+    /// header with stream kind, count, sequence number and timestamp)
+    #[arg(long, default_value = "legacy")]
+    packet_format: PacketFormat,
+
+    /// Payload encoding: f32, u16, u8 (quantized with temporal dithering)
+    /// or delta (u8, only changed elements); all but f32 need
+    /// --packet-format framed
+    #[arg(long, default_value = "f32")]
+    encoding: Encoding,
+
+    /// Detect beats, emitting "beat" events (strength, bpm, confidence)
+    /// with --json
+    #[arg(long)]
+    beats: bool,
+
+    /// Also send each beat as a framed beat packet to this UDP port on
+    /// the stream's host; implies --beats
+    #[arg(long)]
+    beat_port: Option<u16>,
 
     /// Name of the visualizer preset on the server
     #[arg(long, default_value = "MusicVisualizer")]
     preset: String,
 
-    /// Stream UDP only; don't start/stop the preset (assume it's running)
+    /// Seconds between stream stats: a status line, or "stats" events
+    /// with --json [default: 1, or 5 with --json; 0 = off]
+    #[arg(long)]
+    stats_interval: Option<f64>,
+
+    /// Refused sends in a row before warning that nothing is listening
+    #[arg(long, default_value_t = 30)]
+    unreachable_after: u64,
+
+    /// Exit (status 3) instead of just warning when nothing is listening
+    #[arg(long)]
+    fail_on_unreachable: bool,
+
+    /// When the server switches to another preset: exit, wait (pause
+    /// until the server is idle, then restart the preset) or reclaim
+    /// (restart it right away). A preset stopped outright ends the
+    /// stream unless reclaiming
+    #[arg(long, default_value = "exit")]
+    on_preempt: OnPreempt,
+
+    /// Seconds between checks that the preset is still running (0 = off)
+    #[arg(long, default_value_t = 5.0)]
+    preempt_interval: f64,
+
+    /// On exit, restart whatever preset was running before instead of
+    /// stopping (with its default args; the server doesn't report them)
+    #[arg(long)]
+    restore: bool,
+
+    /// Stream UDP only; don't start/stop the preset (assume it's running).
+    /// Implied by protocols other than lightwave
     #[arg(long)]
     no_start: bool,
 }
 
+impl MusicArgs {
+    /// UDP port to stream to.
+    pub fn port(&self) -> u16 {
+        self.port.or(self.protocol.default_port()).unwrap_or(5555)
+    }
+
+    /// `host:port` to stream to, defaulting to the server's host.
+    pub fn target(&self, client: &Client) -> String {
+        format!("{}:{}", self.host(client), self.port())
+    }
+
+    fn host<'a>(&'a self, client: &'a Client) -> &'a str {
+        self.host.as_deref().unwrap_or(client.host())
+    }
+
+    /// Whether to start and stop the preset. Other protocols feed a
+    /// controller directly; there's no preset to run.
+    pub fn manages_preset(&self) -> bool {
+        !self.no_start && self.protocol == Protocol::LightWave
+    }
+
+    pub fn preset(&self) -> &str {
+        &self.preset
+    }
+
+    fn input(&self) -> Input {
+        if let Some(path) = &self.file {
+            Input::File {
+                path: path.clone(),
+                realtime: !self.fast,
+            }
+        } else if self.stdin {
+            Input::Stdin {
+                format: self.format,
+                sample_rate: self.rate,
+                channels: self.input_channels,
+                realtime: self.realtime,
+            }
+        } else if let Some(signal) = self.signal {
+            Input::Signal(signal)
+        } else {
+            Input::Device(self.device.clone())
+        }
+    }
+
+    pub fn config(&self, client: &Client, json_mode: bool) -> Result<Config> {
+        Ok(Config {
+            input: self.input(),
+            sample_rate: self.sample_rate,
+            fft_size: self.fft_size,
+            channels: self.channels,
+            window: self.window,
+            hop: self.hop,
+            bins: self.bins,
+            gain: self.gain,
+            weighting: self.weighting,
+            tilt: self.tilt,
+            equalize: !self.no_eq,
+            scale: self.scale,
+            floor_db: self.floor_db,
+            ceiling_db: self.ceiling_db,
+            agc: self.agc,
+            agc_target: self.agc_target,
+            agc_max_gain: self.agc_max_gain,
+            min_freq: self.min_freq,
+            max_freq: self.max_freq,
+            scale_type: self.scale_type,
+            octave_fraction: self.octave_fraction,
+            reduction: self.reduction,
+            attack: millis("attack", self.attack)?,
+            release: millis("release", self.release)?,
+            peak_hold: Some(millis("peak-hold", self.peak_hold)?).filter(|hold| !hold.is_zero()),
+            gate: self.gate,
+            gate_hysteresis: self.gate_hysteresis,
+            silence_timeout: Some(
+                Duration::try_from_secs_f64(self.silence_timeout)
+                    .map_err(|_| anyhow!("silence-timeout must be a finite number >= 0"))?,
+            )
+            .filter(|timeout| !timeout.is_zero()),
+            on_silence: self.on_silence.clone(),
+            fps: self.fps,
+            target: self.target(client),
+            output: SendOptions {
+                protocol: self.protocol,
+                format: self.packet_format,
+                encoding: self.encoding,
+                universe: self.universe,
+                priority: self.priority,
+                udp: UdpOptions {
+                    multicast_ttl: self.multicast_ttl,
+                    multicast_interface: self.multicast_interface.clone(),
+                    multicast_loop: !self.no_multicast_loop,
+                },
+            },
+            beats: self.beats,
+            beat_target: self
+                .beat_port
+                .map(|port| format!("{}:{port}", self.host(client))),
+            stats_interval: stats_interval(self.stats_interval, json_mode)?,
+            unreachable_after: self.unreachable_after,
+            fail_on_unreachable: self.fail_on_unreachable,
+        })
+    }
+}
+
 pub fn run(client: &Client, args: &MusicArgs, json_mode: bool) -> Result<()> {
     if args.list_devices {
         return list_devices(json_mode);
     }
 
-    let target = format!("{}:{}", client.host(), args.port);
+    let port = args.port();
+    let target = args.target(client);
+    let manage_preset = args.manages_preset();
+    let config = args.config(client, json_mode)?;
 
-    let config = Config {
-        device: args.device.clone(),
-        sample_rate: args.sample_rate,
-        fft_size: args.fft_size,
-        bins: args.bins,
-        gain: args.gain,
-        min_freq: args.min_freq,
-        max_freq: args.max_freq,
-        fps: args.fps,
-        target: target.clone(),
-    };
+    if args.calibrate_pink {
+        return calibrate_pink(&config, args.calibrate_seconds, json_mode);
+    }
+
+    if let OnSilence::Preset(_) = args.on_silence
+        && !manage_preset
+    {
+        bail!("--on-silence preset:<name> needs --protocol lightwave without --no-start");
+    }
+
+    let preempt_interval = Duration::try_from_secs_f64(args.preempt_interval)
+        .map_err(|_| anyhow!("preempt-interval must be a finite number >= 0"))?;
 
     let streamer = Streamer::new(&config)?;
 
-    if !args.no_start {
+    // Remember what to put back on exit (our own preset doesn't count).
+    let previous = if manage_preset && args.restore {
+        client
+            .running()
+            .context("checking the running preset")?
+            .map(|running| running.name)
+            .filter(|name| *name != args.preset)
+    } else {
+        None
+    };
+
+    let start_args = json!({ "port": port });
+    if manage_preset {
         client
-            .start(&args.preset, &json!({ "port": args.port }))
+            .start(&args.preset, &start_args)
             .with_context(|| format!("starting preset {}", args.preset))?;
     }
 
+    // Stdin carrying audio has no room for commands.
+    let controls = if args.stdin {
+        Controls::none()
+    } else {
+        Controls::spawn(streamer.tuning(), streamer.control(), json_mode)
+    };
+
     if json_mode {
         // First line on stdout confirms the capture and socket are up and the
         // preset was started; streaming begins right after. Consumers can block
@@ -93,48 +477,117 @@ pub fn run(client: &Client, args: &MusicArgs, json_mode: bool) -> Result<()> {
             "device": streamer.device_name(),
             "sample_rate": streamer.sample_rate(),
             "target": target,
+            "protocol": args.protocol.to_string(),
+            "destination": streamer.destination().to_string(),
             "fft_size": args.fft_size,
-            "bins": args.bins,
+            "window": args.window.to_string(),
+            "hop": args.hop,
+            "bins": streamer.bins(),
+            "channels": args.channels.to_string(),
+            "scale_type": args.scale_type.to_string(),
+            "scale": args.scale.to_string(),
+            "agc": args.agc.to_string(),
+            "weighting": args.weighting.to_string(),
+            "tilt": args.tilt,
             "fps": args.fps,
+            "packet_format": args.packet_format.to_string(),
+            "encoding": args.encoding.to_string(),
+            "beats": args.beats || args.beat_port.is_some(),
+            "gate": args.gate,
+            "on_silence": args.on_silence.to_string(),
+            "tuning": streamer.tuning().params(),
         }))?;
     } else {
         println!(
             "\n  {} {}  {}",
             "♪".bright_magenta(),
             streamer.device_name().bright_white().bold(),
-            format!("→ udp://{target}").dimmed()
+            format!("→ {}", stream_url(args.protocol, &target)).dimmed()
         );
         println!(
-            "  {} {} Hz · fft {} · {} bins · gain {} · {} fps",
+            "  {} {} Hz · {} · fft {} · {} bins · gain {} · {} fps",
             "›".dimmed(),
             streamer.sample_rate(),
+            args.channels,
             args.fft_size,
-            args.bins,
+            streamer.bins(),
             args.gain,
             args.fps
         );
         println!(
-            "  {} streaming, press {} to stop\n",
+            "  {} streaming, press {} to stop",
             "▶".bright_green(),
             "Ctrl+C".bright_yellow().bold()
         );
+        if controls.keyboard() {
+            println!(
+                "  {} {} pick a setting, {} adjust it",
+                "›".dimmed(),
+                "↑↓".bright_yellow().bold(),
+                "←→".bright_yellow().bold()
+            );
+        }
+        println!();
     }
 
-    let result = streamer.run();
+    let mut reporter = Reporter::new(json_mode);
+    if args.protocol == Protocol::LightWave {
+        reporter = reporter.with_preset(client, &args.preset);
+    }
+    if controls.keyboard() {
+        reporter = reporter.with_tuning(streamer.tuning());
+    }
+    let supervisor = (manage_preset && preempt_interval > Duration::ZERO).then(|| {
+        Supervisor::spawn(
+            client,
+            &args.preset,
+            start_args.clone(),
+            args.on_preempt,
+            preempt_interval,
+            streamer.control(),
+        )
+    });
+
+    let fallback = match &args.on_silence {
+        OnSilence::Preset(fallback) => {
+            let fallback = Fallback::new(client.clone(), fallback, &args.preset, start_args);
+            Some(match &supervisor {
+                Some(supervisor) => fallback.with_stand_by(|standing_by| {
+                    supervisor.stand_by(standing_by);
+                }),
+                None => fallback,
+            })
+        }
+        _ => None,
+    };
 
-    if !args.no_start
-        && let Err(err) = client.stop()
-    {
-        eprintln!("warning: failed to stop preset: {err:#}");
+    let result = streamer.run(|event| match &fallback {
+        Some(fallback) => {
+            reporter.event(event.clone())?;
+            if let Err(err) = fallback.event(&event) {
+                eprintln!("warning: {err:#}");
+            }
+            Ok(())
+        }
+        None => reporter.event(event),
+    });
+    reporter.finish();
+    drop(controls);
+    drop(fallback);
+
+    // Once preempted, the running preset is someone else's to stop.
+    let preempted = supervisor.is_some_and(Supervisor::finish);
+    if manage_preset && !preempted {
+        restore_or_stop(client, previous.as_deref());
     }
 
-    result?;
+    let reason = result?;
 
     if json_mode {
         // Errors skip this: they surface as the final {"ok":false,...} line.
         crate::commands::print_json(&json!({
             "event": "stop",
-            "reason": "interrupt",
+            "reason": reason.to_string(),
         }))?;
     } else {
         println!("  {} stopped", "■".bright_red());
@@ -143,6 +596,58 @@ pub fn run(client: &Client, args: &MusicArgs, json_mode: bool) -> Result<()> {
     Ok(())
 }
 
+fn millis(flag: &str, ms: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(ms / 1000.0)
+        .map_err(|_| anyhow!("{flag} must be a finite number of milliseconds >= 0, got {ms}"))
+}
+
+fn calibrate_pink(config: &Config, seconds: f64, json_mode: bool) -> Result<()> {
+    let duration = Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| anyhow!("calibrate-seconds must be a finite number > 0, got {seconds}"))?;
+
+    if !json_mode {
+        println!(
+            "\n  {} measuring {:.0} s of pink noise, keep it playing…",
+            "♪".bright_magenta(),
+            duration.as_secs_f64()
+        );
+    }
+
+    let eq = lightwave_music::calibrate_pink(config, duration)?;
+    let (cut, boost) = eq.iter().fold((0.0f32, 0.0f32), |(cut, boost), &[_, db]| {
+        (cut.min(db), boost.max(db))
+    });
+
+    let mut file = FileConfig::load()?;
+    file.music.eq = eq;
+    let path = file.save()?;
+
+    if json_mode {
+        crate::commands::print_ok_json(json!({
+            "action": "calibrate-pink",
+            "path": path.display().to_string(),
+            "eq": file.music.eq,
+        }))?;
+    } else {
+        println!(
+            "  {} saved a {}-point EQ curve to {}",
+            "✓".bright_green(),
+            file.music.eq.len(),
+            path.display().dimmed()
+        );
+        println!(
+            "  {} corrections from {:+.1} to {:+.1} dB; --no-eq ignores them\n",
+            "›".dimmed(),
+            cut,
+            boost
+        );
+    }
+
+    Ok(())
+}
+
 fn list_devices(json_mode: bool) -> Result<()> {
     let devices = lightwave_music::list_devices()?;
 
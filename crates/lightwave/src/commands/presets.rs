@@ -1,11 +1,18 @@
+use std::time::SystemTime;
+
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use owo_colors::OwoColorize;
-use serde_json::json;
+use serde_json::{Value, json};
 
+use lightwave_core::cache::{Cache, Cached};
 use lightwave_core::{ArgSchema, Client};
 
-pub fn list(c: &Client, json_mode: bool) -> Result<()> {
-    let resp = c.list_presets()?;
+pub fn list(cache: &Cache, json_mode: bool) -> Result<()> {
+    let Cached {
+        value: resp,
+        offline,
+    } = cache.presets()?;
 
     if json_mode {
         let presets = resp
@@ -19,14 +26,18 @@ pub fn list(c: &Client, json_mode: bool) -> Result<()> {
             })
             .collect::<Vec<_>>();
 
-        crate::commands::print_json(&json!({
+        let mut payload = json!({
             "ok": true,
             "presets": presets,
-        }))?;
+        });
+        mark_offline(&mut payload, offline);
+        crate::commands::print_json(&payload)?;
 
         return Ok(());
     }
 
+    print_offline(offline);
+
     if resp.presets.is_empty() {
         println!("{}  no presets registered", "✗".red());
         return Ok(());
@@ -54,8 +65,11 @@ pub fn list(c: &Client, json_mode: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn info(c: &Client, name: &str, json_mode: bool) -> Result<()> {
-    let info = c.preset_info(name)?;
+pub fn info(cache: &Cache, name: &str, json_mode: bool) -> Result<()> {
+    let Cached {
+        value: info,
+        offline,
+    } = cache.preset_info(name)?;
 
     if json_mode {
         let args = info
@@ -71,16 +85,20 @@ pub fn info(c: &Client, name: &str, json_mode: bool) -> Result<()> {
             })
             .collect::<Vec<_>>();
 
-        crate::commands::print_json(&json!({
+        let mut payload = json!({
             "ok": true,
             "name": name,
             "description": &info.description,
             "args": args,
-        }))?;
+        });
+        mark_offline(&mut payload, offline);
+        crate::commands::print_json(&payload)?;
 
         return Ok(());
     }
 
+    print_offline(offline);
+
     println!(
         "\n  {}  {}",
         "✦".bright_yellow(),
@@ -127,6 +145,53 @@ fn print_arg(a: &ArgSchema, name_w: usize) {
     );
 }
 
+/// Note in `--json` output that the server was down and this is the copy
+/// cached at `offline`.
+fn mark_offline(payload: &mut Value, offline: Option<SystemTime>) {
+    if let Some(fetched) = offline {
+        payload["offline"] = json!(true);
+        payload["cached_at"] = json!(DateTime::<Local>::from(fetched).to_rfc3339());
+    }
+}
+
+fn print_offline(offline: Option<SystemTime>) {
+    let Some(fetched) = offline else {
+        return;
+    };
+
+    let age = fetched.elapsed().unwrap_or_default().as_secs();
+    let age = match age {
+        0..60 => format!("{age}s"),
+        60..3600 => format!("{}m", age / 60),
+        3600..86400 => format!("{}h", age / 3600),
+        _ => format!("{}d", age / 86400),
+    };
+
+    println!(
+        "  {}  server unreachable; showing the copy cached {age} ago\n",
+        "⚠".yellow()
+    );
+}
+
+pub fn clear_cache(json_mode: bool) -> Result<()> {
+    let servers = Cache::clear()?;
+
+    if json_mode {
+        return crate::commands::print_ok_json(json!({
+            "action": "cache_clear",
+            "servers": servers,
+        }));
+    }
+
+    println!(
+        "  {} cleared cached presets for {servers} server{}",
+        "✓".bright_green(),
+        if servers == 1 { "" } else { "s" }
+    );
+
+    Ok(())
+}
+
 pub fn running(c: &Client, json_mode: bool) -> Result<()> {
     let running = c.running()?;
 
@@ -1,17 +1,26 @@
 use anyhow::{Context, Result, anyhow, bail};
+use clap::builder::PossibleValuesParser;
 use clap::{Arg, ArgAction, Command};
 use owo_colors::OwoColorize;
 use serde_json::{Value, json};
 
 use lightwave_core::{
     ArgSchema, Client,
+    cache::Cache,
     color::{normalize, parse_hex_rgb},
 };
 
-pub fn run(client: &Client, preset: &str, rest: &[String], json_mode: bool) -> Result<()> {
-    let info = client
+pub fn run(
+    client: &Client,
+    cache: &Cache,
+    preset: &str,
+    rest: &[String],
+    json_mode: bool,
+) -> Result<()> {
+    let info = cache
         .preset_info(preset)
-        .with_context(|| format!("fetching schema for {preset}"))?;
+        .with_context(|| format!("fetching schema for {preset}"))?
+        .value;
 
     // clap stores arg/command identifiers as &'static str; leak the dynamic strings
     let preset_name: &'static str = preset.to_string().leak();
@@ -114,15 +123,46 @@ fn build_arg(arg: &ArgSchema) -> Result<Arg> {
     let name: &'static str = arg.name.clone().leak();
     let help: &'static str = format!("{}  [default: {}]", arg.description, arg.default).leak();
 
-    Ok(Arg::new(name)
+    let mut built = Arg::new(name)
         .long(name)
         .help(help)
         .action(ArgAction::Set)
-        .required(false))
+        .required(false);
+
+    if !arg.choices.is_empty() {
+        let choices = arg
+            .choices
+            .iter()
+            .map(|choice| -> &'static str { choice_text(choice).leak() });
+        built = built.value_parser(PossibleValuesParser::new(choices));
+    }
+
+    Ok(built)
+}
+
+/// Values worth offering for an arg: the server's choices, or true/false
+/// for bools.
+pub(crate) fn suggestions(arg: &ArgSchema) -> Vec<String> {
+    if !arg.choices.is_empty() {
+        return arg.choices.iter().map(choice_text).collect();
+    }
+
+    match arg.arg_type.as_str() {
+        "bool" => vec!["true".to_string(), "false".to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// A choice as typed on the command line: strings without their quotes.
+fn choice_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
 }
 
 /// Convert a string from clap into the JSON type the server expects.
-fn coerce(ty: &str, raw: &str) -> Result<Value> {
+pub(crate) fn coerce(ty: &str, raw: &str) -> Result<Value> {
     match ty {
         "int" => Ok(json!(
             raw.parse::<i64>()
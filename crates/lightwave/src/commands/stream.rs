@@ -0,0 +1,577 @@
+//! Output shared by the streaming commands (music, ambilight).
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use owo_colors::OwoColorize;
+use serde_json::{Value, json};
+
+use lightwave_core::Client;
+use lightwave_core::net::Protocol;
+use lightwave_core::stream::{Control, Event, Stats, StopReason};
+use lightwave_core::tuning::Tuning;
+
+/// Where a streaming command sends its packets, for pretty output.
+pub fn stream_url(protocol: Protocol, target: &str) -> String {
+    match protocol {
+        Protocol::LightWave => format!("udp://{target}"),
+        protocol => format!("{protocol}://{target}"),
+    }
+}
+
+/// The stats interval for `--stats-interval` (None = the mode's default),
+/// with 0 turning stats off.
+pub fn stats_interval(secs: Option<f64>, json_mode: bool) -> Result<Option<Duration>> {
+    let secs = secs.unwrap_or(if json_mode { 5.0 } else { 1.0 });
+
+    if secs == 0.0 {
+        return Ok(None);
+    }
+
+    match Duration::try_from_secs_f64(secs) {
+        Ok(interval) => Ok(Some(interval)),
+        Err(_) => bail!("stats-interval must be a finite number >= 0, got {secs}"),
+    }
+}
+
+/// Hand the strip back after streaming: restart `previous` if there was
+/// one, else stop. Failures only warn; the stream itself went fine.
+pub fn restore_or_stop(client: &Client, previous: Option<&str>) {
+    let result = match previous {
+        Some(previous) => client
+            .start(previous, &json!({}))
+            .with_context(|| format!("failed to restore preset {previous}")),
+        None => client.stop().context("failed to stop preset"),
+    };
+
+    if let Err(err) = result {
+        eprintln!("warning: {err:#}");
+    }
+}
+
+/// What to do when the server switches away from the stream's preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnPreempt {
+    /// End the stream.
+    Exit,
+    /// Pause until the server is idle, then restart the preset.
+    Wait,
+    /// Restart the preset right away.
+    Reclaim,
+}
+
+impl FromStr for OnPreempt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exit" => Ok(Self::Exit),
+            "wait" => Ok(Self::Wait),
+            "reclaim" => Ok(Self::Reclaim),
+            _ => Err(format!(
+                "unknown preempt action {s:?}; expected exit, wait or reclaim"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OnPreempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Exit => "exit",
+            Self::Wait => "wait",
+            Self::Reclaim => "reclaim",
+        })
+    }
+}
+
+/// Polls the server on a background thread while a stream runs, and
+/// steers the stream when its preset stops being the running one.
+pub struct Supervisor {
+    /// Dropping it ends the polling thread.
+    done: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    preempted: Arc<AtomicBool>,
+    standing_by: Arc<AtomicBool>,
+}
+
+impl Supervisor {
+    pub fn spawn(
+        client: &Client,
+        preset: &str,
+        start_args: Value,
+        on_preempt: OnPreempt,
+        interval: Duration,
+        control: Control,
+    ) -> Self {
+        let (done, done_rx) = mpsc::channel();
+        let preempted = Arc::new(AtomicBool::new(false));
+        let standing_by = Arc::new(AtomicBool::new(false));
+
+        let watch = Watch {
+            client: client.clone(),
+            preset: preset.to_string(),
+            start_args,
+            on_preempt,
+            control,
+            preempted: Arc::clone(&preempted),
+            standing_by: Arc::clone(&standing_by),
+            waiting: false,
+        };
+
+        let thread = thread::spawn(move || {
+            let mut watch = watch;
+            while let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(interval) {
+                if !watch.poll() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            done: Some(done),
+            thread: Some(thread),
+            preempted,
+            standing_by,
+        }
+    }
+
+    /// Stop (or resume) checking on the preset, while the stream itself
+    /// has the server run another one.
+    pub fn stand_by(&self, standing_by: bool) {
+        self.standing_by.store(standing_by, Ordering::Relaxed);
+    }
+
+    /// Stop polling. Returns whether the server had moved on from the
+    /// preset, in which case stopping "our" preset would stop someone
+    /// else's.
+    pub fn finish(mut self) -> bool {
+        self.done.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        self.preempted.load(Ordering::Relaxed)
+    }
+}
+
+struct Watch {
+    client: Client,
+    preset: String,
+    start_args: Value,
+    on_preempt: OnPreempt,
+    control: Control,
+    preempted: Arc<AtomicBool>,
+    /// Another preset runs on the stream's behalf; see
+    /// [`Supervisor::stand_by`].
+    standing_by: Arc<AtomicBool>,
+    /// Paused, waiting for the server to go idle.
+    waiting: bool,
+}
+
+impl Watch {
+    /// Check the server once; false once the stream was told to stop.
+    fn poll(&mut self) -> bool {
+        // A server that doesn't answer says nothing about the preset;
+        // the stream's own reachability checks cover that.
+        let Ok(running) = self.client.running() else {
+            return true;
+        };
+
+        // Checked after asking, in case the stream switched presets while
+        // the answer was on its way.
+        if self.standing_by.load(Ordering::Relaxed) {
+            return true;
+        }
+        let running = running.map(|running| running.name);
+        let ours = running.as_deref() == Some(self.preset.as_str());
+
+        if self.waiting {
+            if running.is_none() {
+                self.reclaim();
+            } else if ours {
+                self.reclaimed();
+            }
+
+            return true;
+        }
+
+        if ours {
+            return true;
+        }
+
+        self.preempted.store(true, Ordering::Relaxed);
+        self.control.report(Event::Preempted {
+            preset: self.preset.clone(),
+            by: running.clone(),
+        });
+
+        match (self.on_preempt, running) {
+            (OnPreempt::Reclaim, _) => self.reclaim(),
+            (OnPreempt::Wait, Some(_)) => {
+                self.waiting = true;
+                self.control.pause();
+            }
+            // Stopped outright (or told to give way): someone wants the
+            // strip off, not waiting on us.
+            _ => {
+                self.control.stop(StopReason::Preempted);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Restart the preset; on failure, try again next poll.
+    fn reclaim(&mut self) {
+        if self.client.start(&self.preset, &self.start_args).is_ok() {
+            self.reclaimed();
+        }
+    }
+
+    fn reclaimed(&mut self) {
+        self.preempted.store(false, Ordering::Relaxed);
+        self.control.report(Event::Reclaimed {
+            preset: self.preset.clone(),
+        });
+
+        if self.waiting {
+            self.waiting = false;
+            self.control.resume();
+        }
+    }
+}
+
+/// Renders a streamer's events: newline-delimited JSON with `--json`,
+/// otherwise a status line redrawn in place.
+pub struct Reporter {
+    json_mode: bool,
+    status_shown: bool,
+    /// Preset expected to be receiving the stream, checked on the
+    /// server when the target turns out unreachable.
+    preset: Option<(Client, String)>,
+    /// Parameters shown on the status line, and the one being adjusted.
+    tuning: Option<(Tuning, String)>,
+    /// Stats last drawn, for redrawing when only the tuning changed.
+    stats: Option<String>,
+}
+
+impl Reporter {
+    pub fn new(json_mode: bool) -> Self {
+        Self {
+            json_mode,
+            status_shown: false,
+            preset: None,
+            tuning: None,
+            stats: None,
+        }
+    }
+
+    pub fn with_preset(mut self, client: &Client, preset: &str) -> Self {
+        self.preset = Some((client.clone(), preset.to_string()));
+        self
+    }
+
+    /// Show `tuning` on the status line (keyboard controls are active).
+    pub fn with_tuning(mut self, tuning: Tuning) -> Self {
+        let selected = tuning.params().first().map(|param| param.name.to_string());
+        if let Some(selected) = selected {
+            self.tuning = Some((tuning, selected));
+        }
+        self
+    }
+
+    pub fn event(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::Stats(stats) => self.stats(&stats),
+            Event::Unreachable { target, refused } => self.unreachable(&target, refused),
+            Event::Reachable { target } => self.reachable(&target),
+            Event::Preempted { preset, by } => self.preempted(&preset, by.as_deref()),
+            Event::Reclaimed { preset } => self.reclaimed(&preset),
+            Event::Reloaded => self.reloaded(None),
+            Event::ReloadFailed { error } => self.reloaded(Some(&error)),
+            Event::Tuned { param, value } => self.tuned(param, value),
+            Event::Beat {
+                strength,
+                bpm,
+                confidence,
+            } => self.beat(strength, bpm, confidence),
+            Event::Silence => self.silence(true),
+            Event::Resumed => self.silence(false),
+        }
+    }
+
+    /// End the status line so later output starts on a fresh one.
+    pub fn finish(&mut self) {
+        if self.status_shown {
+            println!();
+            self.status_shown = false;
+        }
+    }
+
+    fn unreachable(&mut self, target: &str, refused: u64) -> Result<()> {
+        let preset = self.preset.as_ref().map(|(_, name)| name.clone());
+        let running = self.preset_running();
+
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": "warning",
+                "kind": "unreachable",
+                "target": target,
+                "refused": refused,
+                "preset": preset,
+                "preset_running": running,
+            }));
+        }
+
+        self.finish();
+        println!(
+            "  {} nothing is listening on {target} ({refused} sends refused)",
+            "⚠".yellow()
+        );
+
+        match (preset.as_deref(), running) {
+            (Some(preset), Some(false)) => println!(
+                "  {} preset {} is not running on the server",
+                "›".dimmed(),
+                preset.bold()
+            ),
+            (Some(preset), Some(true)) => println!(
+                "  {} preset {} is running; check --port",
+                "›".dimmed(),
+                preset.bold()
+            ),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Beats are for scripts; on a terminal they'd just scroll by.
+    fn beat(&mut self, strength: f32, bpm: Option<f32>, confidence: f32) -> Result<()> {
+        if !self.json_mode {
+            return Ok(());
+        }
+
+        super::print_json(&json!({
+            "event": "beat",
+            "strength": strength,
+            "bpm": bpm,
+            "confidence": confidence,
+        }))
+    }
+
+    fn silence(&mut self, silent: bool) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": if silent { "silence" } else { "resumed" },
+            }));
+        }
+
+        self.finish();
+        if silent {
+            println!("  {} silence, idling", "◌".dimmed());
+        } else {
+            println!("  {} sound again", "♪".bright_magenta());
+        }
+
+        Ok(())
+    }
+
+    fn reachable(&mut self, target: &str) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": "reachable",
+                "target": target,
+            }));
+        }
+
+        self.finish();
+        println!("  {} {target} is listening again", "✓".bright_green());
+
+        Ok(())
+    }
+
+    fn preempted(&mut self, preset: &str, by: Option<&str>) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": "preempted",
+                "preset": preset,
+                "by": by,
+            }));
+        }
+
+        self.finish();
+        match by {
+            Some(by) => println!(
+                "  {} {} took over from {}",
+                "⏸".yellow(),
+                by.bold(),
+                preset.bold()
+            ),
+            None => println!(
+                "  {} {} was stopped on the server",
+                "⏸".yellow(),
+                preset.bold()
+            ),
+        }
+
+        Ok(())
+    }
+
+    fn reclaimed(&mut self, preset: &str) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": "reclaimed",
+                "preset": preset,
+            }));
+        }
+
+        self.finish();
+        println!(
+            "  {} {} is running again",
+            "▶".bright_green(),
+            preset.bold()
+        );
+
+        Ok(())
+    }
+
+    fn reloaded(&mut self, error: Option<&str>) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&match error {
+                None => json!({ "event": "reloaded" }),
+                Some(error) => json!({
+                    "event": "warning",
+                    "kind": "reload",
+                    "detail": error,
+                }),
+            });
+        }
+
+        self.finish();
+        match error {
+            None => println!("  {} reloaded config", "↻".bright_cyan()),
+            Some(error) => println!("  {} reload failed: {error}", "⚠".yellow()),
+        }
+
+        Ok(())
+    }
+
+    fn tuned(&mut self, param: String, value: f32) -> Result<()> {
+        if self.json_mode {
+            return super::print_json(&json!({
+                "event": "tuned",
+                "param": param,
+                "value": value,
+            }));
+        }
+
+        if let Some((_, selected)) = &mut self.tuning {
+            *selected = param;
+        }
+
+        self.draw()
+    }
+
+    /// Whether the expected preset is the one running (None if there's
+    /// no preset to check or the server didn't answer).
+    fn preset_running(&self) -> Option<bool> {
+        let (client, preset) = self.preset.as_ref()?;
+
+        // Events arrive inside the streamer's async runtime, where the
+        // blocking HTTP client must not run; ask from a plain thread.
+        let running = std::thread::scope(|scope| scope.spawn(|| client.running()).join());
+
+        match running {
+            Ok(Ok(running)) => Some(running.is_some_and(|running| running.name == *preset)),
+            _ => None,
+        }
+    }
+
+    fn stats(&mut self, stats: &Stats) -> Result<()> {
+        if self.json_mode {
+            let mut line = json!({ "event": "stats" });
+            if let (Value::Object(line), Value::Object(fields)) =
+                (&mut line, serde_json::to_value(stats)?)
+            {
+                line.extend(fields);
+            }
+
+            return super::print_json(&line);
+        }
+
+        let latency = match stats.latency_ms {
+            Some(ms) => format!("{ms:.1} ms"),
+            None => "—".to_string(),
+        };
+
+        let mut status = format!(
+            "  {} {:.1} fps · latency {} · {} captured · {} sent · {} skipped",
+            "◷".dimmed(),
+            stats.fps,
+            latency,
+            stats.frames_captured,
+            stats.packets_sent,
+            stats.ticks_skipped,
+        );
+
+        if let Some(gain) = stats.agc_gain {
+            status += &format!(" · agc ×{gain:.2}");
+        }
+
+        if stats.refused > 0 {
+            status += &format!(" · {}", format!("{} refused", stats.refused).yellow());
+        }
+
+        if stats.send_errors > 0 {
+            status += &format!(" · {}", format!("{} errors", stats.send_errors).red());
+        }
+
+        self.stats = Some(status);
+        self.draw()
+    }
+
+    /// Redraw the status line: the latest stats, then the tuning with
+    /// the parameter being adjusted highlighted.
+    fn draw(&mut self) -> Result<()> {
+        let mut status = self.stats.clone().unwrap_or_default();
+
+        if let Some((tuning, selected)) = &self.tuning {
+            let params = tuning
+                .params()
+                .iter()
+                .map(|param| {
+                    let text = format!("{} {:.2}", param.name, param.value);
+                    if param.name == selected {
+                        format!("‹{text}›").bright_cyan().bold().to_string()
+                    } else {
+                        text.dimmed().to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if status.is_empty() {
+                status += "  ";
+            } else {
+                status += &format!(" {} ", "│".dimmed());
+            }
+            status += &params.join(" ");
+        }
+
+        // Clear the line first: it may have been longer last time.
+        print!("\r\x1b[2K{status}");
+        io::stdout().flush()?;
+        self.status_shown = true;
+
+        Ok(())
+    }
+}
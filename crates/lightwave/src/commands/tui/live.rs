@@ -0,0 +1,228 @@
+//! Music and ambilight streams run inside the dashboard, with their
+//! `lightwave music` / `lightwave ambilight` defaults, so their output
+//! can be drawn live.
+
+use std::fmt;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+#[cfg(any(feature = "music", feature = "ambilight"))]
+use serde_json::json;
+
+use lightwave_core::Client;
+#[cfg(any(feature = "music", feature = "ambilight"))]
+use lightwave_core::stream::Event;
+use lightwave_core::stream::{Control, Monitor, Stats, StopReason};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Music,
+    Ambilight,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Music => "music",
+            Self::Ambilight => "ambilight",
+        })
+    }
+}
+
+/// What the stream reported last.
+#[derive(Default)]
+struct Report {
+    stats: Option<Stats>,
+    warning: Option<String>,
+}
+
+type Handles = (Control, Monitor);
+
+pub struct Live {
+    pub kind: Kind,
+    /// Preset to run for the stream, with its start args.
+    pub preset: Option<(String, Value)>,
+    ready: Option<Receiver<Result<Handles>>>,
+    handles: Option<Handles>,
+    report: Arc<Mutex<Report>>,
+    thread: JoinHandle<Result<StopReason>>,
+}
+
+impl Live {
+    /// Start capturing on a thread of its own; [`Live::poll`] says when
+    /// it's up.
+    #[cfg(not(any(feature = "music", feature = "ambilight")))]
+    pub fn start(kind: Kind, client: &Client, profile: Option<&str>) -> Result<Self> {
+        let _ = (client, profile);
+        Err(anyhow!("built without {kind} support"))
+    }
+
+    /// Start capturing on a thread of its own; [`Live::poll`] says when
+    /// it's up.
+    #[cfg(any(feature = "music", feature = "ambilight"))]
+    pub fn start(kind: Kind, client: &Client, profile: Option<&str>) -> Result<Self> {
+        let report = Arc::new(Mutex::new(Report::default()));
+        let on_event = {
+            let report = Arc::clone(&report);
+            move |event| -> Result<()> {
+                let mut report = report.lock().unwrap();
+                match event {
+                    Event::Stats(stats) => report.stats = Some(stats),
+                    Event::Unreachable { target, .. } => {
+                        report.warning = Some(format!("nothing is listening on {target}"))
+                    }
+                    Event::Reachable { .. } => report.warning = None,
+                    _ => {}
+                }
+                Ok(())
+            }
+        };
+
+        let (preset, (ready, thread)) = match kind {
+            #[cfg(feature = "music")]
+            Kind::Music => {
+                let args: crate::commands::music::MusicArgs = defaults("music")?;
+                let config = args.config(client, false)?;
+                let preset = args
+                    .manages_preset()
+                    .then(|| (args.preset().to_string(), json!({ "port": args.port() })));
+
+                let streams = spawn(
+                    move || lightwave_music::Streamer::new(&config),
+                    |streamer| (streamer.control(), streamer.monitor()),
+                    |streamer| streamer.run(on_event),
+                );
+                (preset, streams)
+            }
+            #[cfg(feature = "ambilight")]
+            Kind::Ambilight => {
+                let args: crate::commands::ambilight::AmbilightArgs = defaults("ambilight")?;
+                let config = args.config(client, profile, false)?;
+                let preset = args
+                    .manages_preset()
+                    .then(|| (args.preset().to_string(), json!({ "port": args.port() })));
+
+                let streams = spawn(
+                    move || lightwave_ambilight::Streamer::new(&config),
+                    |streamer| (streamer.control(), streamer.monitor()),
+                    |streamer| streamer.run(on_event),
+                );
+                (preset, streams)
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                let _ = (client, profile, on_event);
+                return Err(anyhow!("built without {kind} support"));
+            }
+        };
+
+        Ok(Self {
+            kind,
+            preset,
+            ready: Some(ready),
+            handles: None,
+            report,
+            thread,
+        })
+    }
+
+    /// Check on a starting stream: Ok(true) once, when capture comes up.
+    pub fn poll(&mut self) -> Result<bool> {
+        let Some(ready) = &self.ready else {
+            return Ok(false);
+        };
+
+        match ready.try_recv() {
+            Ok(handles) => {
+                self.ready = None;
+                self.handles = Some(handles?);
+                Ok(true)
+            }
+            Err(TryRecvError::Empty) => Ok(false),
+            Err(TryRecvError::Disconnected) => {
+                self.ready = None;
+                Err(anyhow!("{} stream died while starting", self.kind))
+            }
+        }
+    }
+
+    pub fn starting(&self) -> bool {
+        self.ready.is_some()
+    }
+
+    /// Whether the stream ended on its own.
+    pub fn ended(&self) -> bool {
+        self.handles.is_some() && self.thread.is_finished()
+    }
+
+    /// The values sent last: bins for music, RGB triplets for ambilight.
+    pub fn values(&self) -> Vec<f32> {
+        self.handles
+            .as_ref()
+            .map(|(_, monitor)| monitor.latest())
+            .unwrap_or_default()
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        self.report.lock().unwrap().stats.clone()
+    }
+
+    pub fn warning(&self) -> Option<String> {
+        self.report.lock().unwrap().warning.clone()
+    }
+
+    /// Stop the stream and wait for it, returning how it ended.
+    pub fn stop(self) -> Result<StopReason> {
+        if let Some((control, _)) = &self.handles {
+            control.stop(StopReason::Interrupt);
+        } else if self.ready.is_some() {
+            // Still opening capture (e.g. the portal's picker is up);
+            // it stops itself once it finds nobody waiting.
+            return Ok(StopReason::Interrupt);
+        }
+
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("{} stream panicked", self.kind))?
+    }
+}
+
+/// A stream command's flags at their defaults.
+#[cfg(any(feature = "music", feature = "ambilight"))]
+fn defaults<T: clap::Args + clap::FromArgMatches>(name: &'static str) -> Result<T> {
+    let matches = T::augment_args(clap::Command::new(name)).try_get_matches_from([name])?;
+    Ok(T::from_arg_matches(&matches)?)
+}
+
+/// Build a streamer on its own thread (capture handles aren't `Send`)
+/// and run it there, handing back its handles once built.
+#[cfg(any(feature = "music", feature = "ambilight"))]
+fn spawn<S>(
+    build: impl FnOnce() -> Result<S> + Send + 'static,
+    handles: impl FnOnce(&S) -> Handles + Send + 'static,
+    run: impl FnOnce(S) -> Result<StopReason> + Send + 'static,
+) -> (Receiver<Result<Handles>>, JoinHandle<Result<StopReason>>) {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let thread = std::thread::spawn(move || {
+        let streamer = match build() {
+            Ok(streamer) => streamer,
+            Err(err) => {
+                let _ = tx.send(Err(err));
+                return Ok(StopReason::Interrupt);
+            }
+        };
+
+        if tx.send(Ok(handles(&streamer))).is_err() {
+            // Stopped while starting: nobody wants the stream any more.
+            return Ok(StopReason::Interrupt);
+        }
+
+        run(streamer)
+    });
+
+    (rx, thread)
+}
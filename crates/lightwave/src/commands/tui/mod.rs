@@ -0,0 +1,508 @@
+//! `lightwave tui`: a full-screen dashboard for the server — presets with
+//! a start form built from their arg schemas, color and brightness
+//! controls, and the music / ambilight streams run in-process with their
+//! output drawn live.
+
+mod live;
+mod ui;
+mod worker;
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::widgets::ListState;
+use serde_json::Value;
+
+use lightwave_core::{
+    ArgSchema, Client, PresetInfo, PresetSummary, RunningPreset, calibration::Profile,
+    color::to_hex, config::Config,
+};
+
+use crate::commands::start::coerce;
+use live::{Kind, Live};
+use worker::{Done, Job, Worker};
+
+/// How often the running preset is re-checked.
+const RUNNING_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn run(client: &Client, profile: Option<&str>, json_mode: bool) -> Result<()> {
+    if json_mode {
+        bail!("tui is interactive; --json isn't supported");
+    }
+
+    if !io::stdout().is_terminal() {
+        bail!("tui needs a terminal");
+    }
+
+    let calibration = Config::load()?.profile(profile)?;
+    let mut app = App::new(client, profile, calibration);
+
+    let mut terminal = ratatui::try_init()?;
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+
+    app.shutdown(client);
+    result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Presets,
+    Form,
+    Color,
+    Brightness,
+}
+
+impl Focus {
+    const ALL: [Self; 4] = [Self::Presets, Self::Form, Self::Color, Self::Brightness];
+
+    fn next(self, by: isize) -> Self {
+        let i = Self::ALL
+            .iter()
+            .position(|focus| *focus == self)
+            .unwrap_or(0);
+        let len = Self::ALL.len() as isize;
+        Self::ALL[(i as isize + by).rem_euclid(len) as usize]
+    }
+}
+
+/// A start form for one preset, a text field per arg.
+struct Form {
+    preset: String,
+    description: String,
+    fields: Vec<Field>,
+    selected: usize,
+}
+
+struct Field {
+    arg: ArgSchema,
+    /// The default as text; fields left at it aren't sent, so the server
+    /// applies its own default.
+    initial: String,
+    text: String,
+}
+
+impl Form {
+    fn new(preset: &str, info: &PresetInfo) -> Self {
+        let fields = info
+            .args
+            .iter()
+            .map(|arg| {
+                let initial = default_text(arg);
+                Field {
+                    arg: arg.clone(),
+                    text: initial.clone(),
+                    initial,
+                }
+            })
+            .collect();
+
+        Self {
+            preset: preset.to_string(),
+            description: info.description.clone(),
+            fields,
+            selected: 0,
+        }
+    }
+
+    /// Start args from the fields that were changed.
+    fn args(&self) -> Result<Value> {
+        let mut args = serde_json::Map::new();
+
+        for field in &self.fields {
+            if field.text != field.initial {
+                args.insert(
+                    field.arg.name.clone(),
+                    coerce(&field.arg.arg_type, field.text.trim())?,
+                );
+            }
+        }
+
+        Ok(Value::Object(args))
+    }
+}
+
+/// A schema default as the text a user would type for it.
+fn default_text(arg: &ArgSchema) -> String {
+    match &arg.default {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(rgb) if arg.arg_type == "color" && rgb.len() == 3 => {
+            let channel = |i: usize| rgb[i].as_u64().unwrap_or(0).min(255) as u8;
+            format!("#{:02X}{:02X}{:02X}", channel(0), channel(1), channel(2))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Hue (degrees), saturation and value being picked.
+struct Picker {
+    hsv: [f32; 3],
+    channel: usize,
+}
+
+impl Picker {
+    fn rgb(&self) -> [f32; 3] {
+        hsv_to_rgb(self.hsv)
+    }
+
+    fn nudge(&mut self, steps: f32) {
+        let value = &mut self.hsv[self.channel];
+        if self.channel == 0 {
+            *value = (*value + steps * 5.0).rem_euclid(360.0);
+        } else {
+            *value = (*value + steps * 0.05).clamp(0.0, 1.0);
+        }
+    }
+}
+
+fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let sector = (h / 60.0).rem_euclid(6.0);
+    let c = v * s;
+    let x = c * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as u32 {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    let m = v - c;
+
+    [r + m, g + m, b + m]
+}
+
+struct App {
+    client: Client,
+    profile: Option<String>,
+    calibration: Profile,
+    worker: Worker,
+    focus: Focus,
+    presets: Vec<PresetSummary>,
+    list: ListState,
+    infos: HashMap<String, PresetInfo>,
+    form: Option<Form>,
+    /// The running preset and when it was fetched, for a ticking clock.
+    running: Option<(RunningPreset, Instant)>,
+    polled: Option<Instant>,
+    picker: Picker,
+    /// None until set: the server doesn't report it.
+    brightness: Option<f32>,
+    music: Option<Live>,
+    ambilight: Option<Live>,
+    /// Last thing that happened, and whether it went wrong.
+    message: Option<(String, bool)>,
+    quit: bool,
+}
+
+impl App {
+    fn new(client: &Client, profile: Option<&str>, calibration: Profile) -> Self {
+        let worker = Worker::spawn(client.clone());
+        worker.send(Job::Presets);
+
+        Self {
+            client: client.clone(),
+            profile: profile.map(str::to_string),
+            calibration,
+            worker,
+            focus: Focus::Presets,
+            presets: Vec::new(),
+            list: ListState::default(),
+            infos: HashMap::new(),
+            form: None,
+            running: None,
+            polled: None,
+            picker: Picker {
+                hsv: [30.0, 0.6, 1.0],
+                channel: 0,
+            },
+            brightness: None,
+            music: None,
+            ambilight: None,
+            message: None,
+            quit: false,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut ratatui::DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            self.update();
+            terminal.draw(|frame| ui::draw(frame, self))?;
+
+            // ~30 redraws a second keep the live views and the clock moving.
+            if event::poll(Duration::from_millis(33))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.key(key);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take in worker results and stream state changes.
+    fn update(&mut self) {
+        for done in self.worker.finished() {
+            match done {
+                Done::Presets(Ok(presets)) => {
+                    self.presets = presets;
+                    if self.list.selected().is_none() && !self.presets.is_empty() {
+                        self.select(0);
+                    }
+                }
+                Done::Info(preset, Ok(info)) => {
+                    if self.selected_preset() == Some(preset.as_str()) {
+                        self.form = Some(Form::new(&preset, &info));
+                    }
+                    self.infos.insert(preset, info);
+                }
+                Done::Running(Ok(running)) => {
+                    self.running = running.map(|running| (running, Instant::now()));
+                }
+                Done::Action(Ok(message)) => {
+                    self.message = Some((message, false));
+                    self.worker.send(Job::Running);
+                }
+                Done::Presets(Err(err))
+                | Done::Info(_, Err(err))
+                | Done::Running(Err(err))
+                | Done::Action(Err(err)) => self.message = Some((format!("{err:#}"), true)),
+            }
+        }
+
+        if self
+            .polled
+            .is_none_or(|polled| polled.elapsed() >= RUNNING_INTERVAL)
+        {
+            self.worker.send(Job::Running);
+            self.polled = Some(Instant::now());
+        }
+
+        for slot in [&mut self.music, &mut self.ambilight] {
+            let Some(live) = slot else {
+                continue;
+            };
+
+            match live.poll() {
+                Ok(true) => {
+                    if let Some((preset, args)) = &live.preset {
+                        self.worker.send(Job::Start {
+                            preset: preset.clone(),
+                            args: args.clone(),
+                        });
+                    }
+                    self.message = Some((format!("{} streaming", live.kind), false));
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    self.message = Some((format!("{err:#}"), true));
+                    *slot = None;
+                    continue;
+                }
+            }
+
+            if live.ended() {
+                let live = slot.take().unwrap();
+                let message = match live.stop() {
+                    Ok(reason) => (format!("stream ended ({reason})"), false),
+                    Err(err) => (format!("{err:#}"), true),
+                };
+                self.message = Some(message);
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        let cycle = match key.code {
+            KeyCode::Tab => 1,
+            KeyCode::BackTab => -1,
+            _ => 0,
+        };
+        if cycle != 0 {
+            self.focus = self.focus.next(cycle);
+            return;
+        }
+
+        // The form takes typed text; shortcuts live everywhere else.
+        if self.focus == Focus::Form {
+            self.form_key(key.code);
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('s') => self.worker.send(Job::Stop),
+            KeyCode::Char('r') => self.worker.send(Job::Presets),
+            KeyCode::Char('m') => self.toggle(Kind::Music),
+            KeyCode::Char('a') => self.toggle(Kind::Ambilight),
+            code => match self.focus {
+                Focus::Presets => self.presets_key(code),
+                Focus::Color => self.color_key(code),
+                Focus::Brightness => self.brightness_key(code),
+                Focus::Form => {}
+            },
+        }
+    }
+
+    fn presets_key(&mut self, code: KeyCode) {
+        let len = self.presets.len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0);
+
+        match code {
+            KeyCode::Up => self.select((current + len - 1) % len),
+            KeyCode::Down => self.select((current + 1) % len),
+            KeyCode::Enter | KeyCode::Right => self.focus = Focus::Form,
+            _ => {}
+        }
+    }
+
+    fn form_key(&mut self, code: KeyCode) {
+        if code == KeyCode::Esc {
+            self.focus = Focus::Presets;
+            return;
+        }
+
+        let Some(form) = &mut self.form else {
+            return;
+        };
+
+        if code == KeyCode::Enter {
+            match form.args() {
+                Ok(args) => self.worker.send(Job::Start {
+                    preset: form.preset.clone(),
+                    args,
+                }),
+                Err(err) => self.message = Some((format!("{err:#}"), true)),
+            }
+            return;
+        }
+
+        let len = form.fields.len();
+        if len == 0 {
+            return;
+        }
+
+        match code {
+            KeyCode::Up => form.selected = (form.selected + len - 1) % len,
+            KeyCode::Down => form.selected = (form.selected + 1) % len,
+            code => {
+                let field = &mut form.fields[form.selected];
+                match code {
+                    KeyCode::Char(' ') if field.arg.arg_type == "bool" => {
+                        field.text = (field.text.trim() != "true").to_string();
+                    }
+                    KeyCode::Char(c) => field.text.push(c),
+                    KeyCode::Backspace => {
+                        field.text.pop();
+                    }
+                    KeyCode::Delete => field.text = field.initial.clone(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn color_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Up => self.picker.channel = (self.picker.channel + 2) % 3,
+            KeyCode::Down => self.picker.channel = (self.picker.channel + 1) % 3,
+            KeyCode::Left => self.picker.nudge(-1.0),
+            KeyCode::Right => self.picker.nudge(1.0),
+            KeyCode::Enter => {
+                let hex = to_hex(self.calibration.apply_srgb(self.picker.rgb()));
+                self.worker.send(Job::Color(hex));
+            }
+            KeyCode::Char('x') => self.worker.send(Job::Clear),
+            _ => {}
+        }
+    }
+
+    fn brightness_key(&mut self, code: KeyCode) {
+        let step = match code {
+            KeyCode::Left => -0.05,
+            KeyCode::Right => 0.05,
+            _ => return,
+        };
+
+        let level = (self.brightness.unwrap_or(1.0) + step).clamp(0.0, 1.0);
+        // Snap to whole percents so repeated steps don't drift.
+        let level = (level * 100.0).round() / 100.0;
+        self.brightness = Some(level);
+        self.worker.send(Job::Brightness(level));
+    }
+
+    fn select(&mut self, index: usize) {
+        self.list.select(Some(index));
+        let Some(preset) = self.selected_preset().map(str::to_string) else {
+            return;
+        };
+
+        match self.infos.get(&preset) {
+            Some(info) => self.form = Some(Form::new(&preset, info)),
+            None => {
+                self.form = None;
+                self.worker.send(Job::Info(preset));
+            }
+        }
+    }
+
+    fn selected_preset(&self) -> Option<&str> {
+        let index = self.list.selected()?;
+        self.presets.get(index).map(|preset| preset.name.as_str())
+    }
+
+    /// Start or stop an in-process stream.
+    fn toggle(&mut self, kind: Kind) {
+        let slot = match kind {
+            Kind::Music => &mut self.music,
+            Kind::Ambilight => &mut self.ambilight,
+        };
+
+        if let Some(live) = slot.take() {
+            let managed = live.preset.is_some() && !live.starting();
+            self.message = Some(match live.stop() {
+                Ok(_) => (format!("{kind} stopped"), false),
+                Err(err) => (format!("{err:#}"), true),
+            });
+            if managed {
+                self.worker.send(Job::Stop);
+            }
+            return;
+        }
+
+        match Live::start(kind, &self.client, self.profile.as_deref()) {
+            Ok(live) => {
+                *slot = Some(live);
+                self.message = Some((format!("{kind} starting…"), false));
+            }
+            Err(err) => self.message = Some((format!("{err:#}"), true)),
+        }
+    }
+
+    /// Stop in-process streams and the presets they were feeding.
+    fn shutdown(&mut self, client: &Client) {
+        for live in [self.music.take(), self.ambilight.take()]
+            .into_iter()
+            .flatten()
+        {
+            let managed = live.preset.is_some() && !live.starting();
+            let _ = live.stop();
+            if managed && let Err(err) = client.stop() {
+                eprintln!("warning: failed to stop preset: {err:#}");
+            }
+        }
+    }
+}
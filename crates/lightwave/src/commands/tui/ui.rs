@@ -0,0 +1,363 @@
+//! Drawing the dashboard.
+
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph, Wrap};
+
+use super::live::Live;
+use super::{App, Focus};
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [header, main, controls, streams, footer] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(8),
+        Constraint::Length(5),
+        Constraint::Length(9),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let [presets, form] =
+        Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+    let [color, brightness] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(controls);
+    let [music, ambilight] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(streams);
+
+    draw_header(frame, app, header);
+    draw_presets(frame, app, presets);
+    draw_form(frame, app, form);
+    draw_color(frame, app, color);
+    draw_brightness(frame, app, brightness);
+    draw_live(frame, app.music.as_ref(), "music", 'm', music, spectrum);
+    draw_live(
+        frame,
+        app.ambilight.as_ref(),
+        "ambilight",
+        'a',
+        ambilight,
+        boxes,
+    );
+    draw_footer(frame, app, footer);
+}
+
+fn pane(title: &str, focused: bool) -> Block<'_> {
+    let block = Block::bordered().title(format!(" {title} "));
+    if focused {
+        block.border_style(Style::new().cyan())
+    } else {
+        block.border_style(Style::new().dark_gray())
+    }
+}
+
+fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
+    let running = match &app.running {
+        Some((running, fetched)) => {
+            let elapsed = running.duration_seconds + fetched.elapsed().as_secs_f64();
+            Line::from(vec![
+                "▶ ".green(),
+                running.name.as_str().bold(),
+                format!("  {}", elapsed_text(elapsed)).dark_gray(),
+            ])
+        }
+        None => Line::from("■ idle".dark_gray()),
+    };
+
+    let [title, status] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(40)]).areas(area);
+    frame.render_widget(
+        Line::from(vec![
+            " LightWave ".bold().magenta(),
+            app.client.host().dark_gray(),
+        ]),
+        title,
+    );
+    frame.render_widget(running.right_aligned(), status);
+}
+
+/// 3h 02m, 4m 05s, 12s.
+fn elapsed_text(secs: f64) -> String {
+    let secs = secs.max(0.0) as u64;
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, _) => format!("{h}h {m:02}m"),
+    }
+}
+
+fn draw_presets(frame: &mut Frame, app: &mut App, area: Rect) {
+    let running = app
+        .running
+        .as_ref()
+        .map(|(running, _)| running.name.as_str());
+    let items = app
+        .presets
+        .iter()
+        .map(|preset| {
+            let marker = if Some(preset.name.as_str()) == running {
+                "▶ ".green()
+            } else {
+                "  ".into()
+            };
+            ListItem::new(Line::from(vec![marker, preset.name.as_str().into()]))
+        })
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(pane("Presets", app.focus == Focus::Presets))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut app.list);
+}
+
+fn draw_form(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Form;
+    let Some(form) = &app.form else {
+        let hint = if app.presets.is_empty() {
+            "loading presets…"
+        } else {
+            "loading…"
+        };
+        frame.render_widget(
+            Paragraph::new(hint.dark_gray()).block(pane("Start", focused)),
+            area,
+        );
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from(form.description.as_str().italic()),
+        Line::default(),
+    ];
+    let width = form
+        .fields
+        .iter()
+        .map(|field| field.arg.name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (i, field) in form.fields.iter().enumerate() {
+        let selected = focused && i == form.selected;
+        let cursor = if selected { "▏" } else { " " };
+        let value = format!(" {}{cursor}", field.text);
+        let value = if selected {
+            value.black().on_cyan()
+        } else if field.text != field.initial {
+            value.yellow()
+        } else {
+            value.into()
+        };
+
+        lines.push(Line::from(vec![
+            format!("{:>width$} ", field.arg.name).bold(),
+            value,
+            format!("  {} · {}", field.arg.arg_type, field.arg.description).dark_gray(),
+        ]));
+    }
+
+    if form.fields.is_empty() {
+        lines.push(Line::from("no args".dark_gray()));
+    }
+
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(pane(&format!("Start {}", form.preset), focused)),
+        area,
+    );
+}
+
+fn draw_color(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Color;
+    let block = pane("Color", focused);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [sliders, swatch] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(8)]).areas(inner);
+
+    let picker = &app.picker;
+    let names = ["hue", "sat", "val"];
+    let fractions = [picker.hsv[0] / 360.0, picker.hsv[1], picker.hsv[2]];
+    let labels = [
+        format!("{:.0}°", picker.hsv[0]),
+        format!("{:.0}%", picker.hsv[1] * 100.0),
+        format!("{:.0}%", picker.hsv[2] * 100.0),
+    ];
+    let rows = Layout::vertical([Constraint::Length(1); 3]).split(sliders);
+
+    for i in 0..3 {
+        let selected = focused && picker.channel == i;
+        let style = if selected {
+            Style::new().cyan()
+        } else {
+            Style::new().dark_gray()
+        };
+        let gauge = Gauge::default()
+            .gauge_style(style)
+            .ratio(f64::from(fractions[i].clamp(0.0, 1.0)))
+            .label(format!("{} {}", names[i], labels[i]));
+        frame.render_widget(gauge, rows[i]);
+    }
+
+    let [r, g, b] = picker.rgb().map(|channel| (channel * 255.0).round() as u8);
+    let fill =
+        Paragraph::new(vec![Line::from("      "); 3]).style(Style::new().bg(Color::Rgb(r, g, b)));
+    frame.render_widget(fill, swatch.inner(ratatui::layout::Margin::new(1, 0)));
+}
+
+fn draw_brightness(frame: &mut Frame, app: &App, area: Rect) {
+    let focused = app.focus == Focus::Brightness;
+    let block = pane("Brightness", focused);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [gauge] = Layout::vertical([Constraint::Length(1)])
+        .flex(ratatui::layout::Flex::Center)
+        .areas(inner);
+
+    let (ratio, label) = match app.brightness {
+        Some(level) => (f64::from(level), format!("{:.0}%", level * 100.0)),
+        None => (0.0, "—  (←→ to set)".to_string()),
+    };
+    let style = if focused {
+        Style::new().yellow()
+    } else {
+        Style::new().dark_gray()
+    };
+    frame.render_widget(
+        Gauge::default()
+            .gauge_style(style)
+            .ratio(ratio)
+            .label(label),
+        gauge,
+    );
+}
+
+fn draw_live(
+    frame: &mut Frame,
+    live: Option<&Live>,
+    name: &str,
+    key: char,
+    area: Rect,
+    render: fn(&[f32], Rect) -> Vec<Line<'static>>,
+) {
+    let Some(live) = live else {
+        frame.render_widget(
+            Paragraph::new(format!("off — press {key} to stream").dark_gray())
+                .block(pane(name, false)),
+            area,
+        );
+        return;
+    };
+
+    let mut title = name.to_string();
+    if let Some(stats) = live.stats() {
+        title += &format!(" · {:.0} fps", stats.fps);
+        if let Some(gain) = stats.agc_gain {
+            title += &format!(" · agc ×{gain:.2}");
+        }
+    }
+    let block = pane(&title, false);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let lines = if live.starting() {
+        vec![Line::from("starting…".dark_gray())]
+    } else if let Some(warning) = live.warning() {
+        vec![Line::from(format!("⚠ {warning}").yellow())]
+    } else {
+        render(&live.values(), inner)
+    };
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+/// Spectrum bins as vertical bars, in eighths of a cell.
+fn spectrum(values: &[f32], area: Rect) -> Vec<Line<'static>> {
+    const LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    if values.is_empty() || area.width == 0 {
+        return Vec::new();
+    }
+
+    let rows = area.height as usize;
+    let width = (area.width as usize / values.len()).max(1);
+
+    (0..rows)
+        .map(|row| {
+            // Rows count down from the top; each covers eight levels.
+            let floor = (rows - 1 - row) * 8;
+            let spans = values
+                .iter()
+                .take(area.width as usize / width)
+                .enumerate()
+                .map(|(i, value)| {
+                    let level = (value.clamp(0.0, 1.0) * (rows * 8) as f32).round() as usize;
+                    let cell = LEVELS[level.saturating_sub(floor).min(8)];
+                    let hue = i as f32 / values.len() as f32 * 300.0;
+                    let [r, g, b] =
+                        super::hsv_to_rgb([hue, 0.7, 1.0]).map(|channel| (channel * 255.0) as u8);
+                    let bar = cell.to_string().repeat(width.saturating_sub(1).max(1));
+                    Span::styled(
+                        if width > 1 { bar + " " } else { bar },
+                        Style::new().fg(Color::Rgb(r, g, b)),
+                    )
+                })
+                .collect::<Vec<_>>();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Ambilight boxes as colored cells, as wide as the pane allows.
+fn boxes(values: &[f32], area: Rect) -> Vec<Line<'static>> {
+    let colors = values.chunks_exact(3).collect::<Vec<_>>();
+    if colors.is_empty() || area.width == 0 {
+        return Vec::new();
+    }
+
+    let width = (area.width as usize / colors.len()).max(1);
+    let row = Line::from(
+        colors
+            .iter()
+            .take(area.width as usize / width)
+            .map(|rgb| {
+                let [r, g, b] = [rgb[0], rgb[1], rgb[2]]
+                    .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+                Span::styled(" ".repeat(width), Style::new().bg(Color::Rgb(r, g, b)))
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    vec![row; area.height as usize]
+}
+
+fn draw_footer(frame: &mut Frame, app: &App, area: Rect) {
+    let keys = match app.focus {
+        Focus::Presets => "↑↓ preset · enter edit args",
+        Focus::Form => {
+            "↑↓ field · type to edit · space toggle bool · del reset · enter start · esc back"
+        }
+        Focus::Color => "↑↓ hue/sat/val · ←→ adjust · enter apply · x clear",
+        Focus::Brightness => "←→ adjust",
+    };
+    let global = if app.focus == Focus::Form {
+        " · tab next pane"
+    } else {
+        " · tab next pane · s stop · m music · a ambilight · r refresh · q quit"
+    };
+
+    let mut line = vec![Span::from(format!(" {keys}{global}")).dark_gray()];
+    if let Some((message, error)) = &app.message {
+        line.push("   ".into());
+        line.push(if *error {
+            message.clone().red()
+        } else {
+            message.clone().green()
+        });
+    }
+
+    frame.render_widget(Line::from(line), area);
+}
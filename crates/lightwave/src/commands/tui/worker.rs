@@ -0,0 +1,102 @@
+//! Server requests off the UI thread, so a slow or dead server never
+//! freezes the screen.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use lightwave_core::{Client, PresetInfo, PresetSummary, RunningPreset};
+
+pub enum Job {
+    Presets,
+    Info(String),
+    Running,
+    Start { preset: String, args: Value },
+    Stop,
+    Color(String),
+    Clear,
+    Brightness(f32),
+}
+
+pub enum Done {
+    Presets(Result<Vec<PresetSummary>>),
+    Info(String, Result<PresetInfo>),
+    Running(Result<Option<RunningPreset>>),
+    /// An action finished; the message says what happened.
+    Action(Result<String>),
+}
+
+pub struct Worker {
+    jobs: Sender<Job>,
+    done: Receiver<Done>,
+}
+
+impl Worker {
+    pub fn spawn(client: Client) -> Self {
+        let (jobs, jobs_rx) = mpsc::channel::<Job>();
+        let (done_tx, done) = mpsc::channel();
+
+        thread::spawn(move || {
+            while let Ok(job) = jobs_rx.recv() {
+                let mut batch = vec![job];
+                batch.extend(jobs_rx.try_iter());
+
+                // Dragging the slider queues a value per key press; only
+                // the last one matters.
+                let last_brightness = batch
+                    .iter()
+                    .rposition(|job| matches!(job, Job::Brightness(_)));
+
+                for (i, job) in batch.into_iter().enumerate() {
+                    if matches!(job, Job::Brightness(_)) && Some(i) != last_brightness {
+                        continue;
+                    }
+
+                    if done_tx.send(execute(&client, job)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self { jobs, done }
+    }
+
+    pub fn send(&self, job: Job) {
+        let _ = self.jobs.send(job);
+    }
+
+    /// Results that came in since the last call.
+    pub fn finished(&self) -> Vec<Done> {
+        self.done.try_iter().collect()
+    }
+}
+
+fn execute(client: &Client, job: Job) -> Done {
+    match job {
+        Job::Presets => Done::Presets(client.list_presets().map(|list| list.presets)),
+        Job::Info(preset) => {
+            let info = client
+                .preset_info(&preset)
+                .with_context(|| format!("fetching schema for {preset}"));
+            Done::Info(preset, info)
+        }
+        Job::Running => Done::Running(client.running()),
+        Job::Start { preset, args } => Done::Action(
+            client
+                .start(&preset, &args)
+                .map(|()| format!("started {preset}"))
+                .with_context(|| format!("starting preset {preset}")),
+        ),
+        Job::Stop => Done::Action(client.stop().map(|()| "stopped".to_string())),
+        Job::Color(hex) => Done::Action(client.set_color(&hex).map(|()| format!("color {hex}"))),
+        Job::Clear => Done::Action(client.clear().map(|()| "cleared".to_string())),
+        Job::Brightness(level) => Done::Action(
+            client
+                .set_brightness(level)
+                .map(|()| format!("brightness {:.0}%", level * 100.0)),
+        ),
+    }
+}
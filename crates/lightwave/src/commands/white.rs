@@ -0,0 +1,156 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{Local, Timelike};
+use owo_colors::OwoColorize;
+use serde_json::json;
+
+use lightwave_core::{
+    Client,
+    calibration::Profile,
+    circadian::Schedule,
+    color::{kelvin_to_rgb, linear_to_srgb, parse_hex_rgb, parse_kelvin, to_hex},
+    config::Config,
+};
+
+#[derive(clap::Args)]
+pub struct WhiteArgs {
+    /// Color temperature, e.g. 3200K
+    #[arg(value_parser = parse_kelvin, required_unless_present = "circadian")]
+    temperature: Option<f32>,
+
+    /// Also set the global brightness (0.0 to 1.0)
+    #[arg(long)]
+    brightness: Option<f32>,
+
+    /// Follow a daily temperature schedule until interrupted
+    #[arg(long, conflicts_with = "temperature")]
+    circadian: bool,
+
+    /// Circadian schedule, e.g. "07:00=5000,21:00=2700" [default: the
+    /// config's `circadian.schedule`, or a built-in warm/neutral day]
+    #[arg(long, requires = "circadian")]
+    schedule: Option<Schedule>,
+
+    /// Seconds between circadian updates
+    #[arg(long, default_value_t = 60, requires = "circadian")]
+    interval: u64,
+}
+
+pub fn run(
+    client: &Client,
+    args: &WhiteArgs,
+    profile: Option<&str>,
+    json_mode: bool,
+) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config.profile(profile)?;
+
+    if let Some(level) = args.brightness {
+        client.set_brightness(level)?;
+    }
+
+    if args.circadian {
+        let schedule = args
+            .schedule
+            .clone()
+            .or(config.circadian.schedule)
+            .unwrap_or_default();
+
+        return circadian(client, &profile, &schedule, args, json_mode);
+    }
+
+    let kelvin = args
+        .temperature
+        .context("a color temperature is required without --circadian")?;
+    let hex = to_hex(profile.apply(kelvin_to_rgb(kelvin)).map(linear_to_srgb));
+
+    client.set_color(&hex)?;
+
+    if json_mode {
+        crate::commands::print_ok_json(json!({
+            "action": "white",
+            "kelvin": kelvin,
+            "color": hex,
+            "brightness": args.brightness,
+        }))?;
+    } else {
+        print_white(kelvin, &hex);
+    }
+
+    Ok(())
+}
+
+/// Re-evaluate the schedule every `--interval` seconds, sending a color
+/// only when it changes. Network errors are reported and retried on the
+/// next tick; a day-long loop shouldn't die on one dropped request.
+fn circadian(
+    client: &Client,
+    profile: &Profile,
+    schedule: &Schedule,
+    args: &WhiteArgs,
+    json_mode: bool,
+) -> Result<()> {
+    if json_mode {
+        crate::commands::print_json(&json!({
+            "event": "start",
+            "schedule": schedule.to_string(),
+            "interval": args.interval,
+        }))?;
+    } else {
+        println!(
+            "\n  {} circadian white  {}",
+            "☀".bright_yellow(),
+            schedule.to_string().dimmed()
+        );
+        println!(
+            "  {} updating every {}s, press {} to stop\n",
+            "▶".bright_green(),
+            args.interval,
+            "Ctrl+C".bright_yellow().bold()
+        );
+    }
+
+    let mut last_hex = None;
+
+    loop {
+        let now = Local::now();
+        let minute = now.hour() as f32 * 60.0 + now.minute() as f32 + now.second() as f32 / 60.0;
+        let kelvin = schedule.kelvin_at(minute).round();
+        let hex = to_hex(profile.apply(kelvin_to_rgb(kelvin)).map(linear_to_srgb));
+
+        if last_hex.as_ref() != Some(&hex) {
+            match client.set_color(&hex) {
+                Ok(()) => {
+                    if json_mode {
+                        crate::commands::print_json(&json!({
+                            "event": "white",
+                            "kelvin": kelvin,
+                            "color": hex,
+                        }))?;
+                    } else {
+                        print_white(kelvin, &hex);
+                    }
+
+                    last_hex = Some(hex);
+                }
+                Err(err) => eprintln!("warning: failed to set color: {err:#}"),
+            }
+        }
+
+        thread::sleep(Duration::from_secs(args.interval.max(1)));
+    }
+}
+
+fn print_white(kelvin: f32, hex: &str) {
+    let [r, g, b] = parse_hex_rgb(hex).unwrap_or([255; 3]);
+
+    println!(
+        "  {} white {} {} {}",
+        "●".truecolor(r, g, b),
+        format!("{kelvin:.0}K").bright_white().bold(),
+        hex.dimmed(),
+        "██".truecolor(r, g, b)
+    );
+}
@@ -1,9 +1,13 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use lightwave_core::api;
+use clap::{CommandFactory, Parser, Subcommand};
+use lightwave_core::cache::{self, Cache};
+use lightwave_core::{api, stream::Unreachable};
 
 mod commands;
 
+/// Exit status for a stream given up with --fail-on-unreachable.
+const EXIT_UNREACHABLE: i32 = 3;
+
 #[derive(Parser)]
 #[command(name = "lightwave", version, about = "CLI for LightWave-Server")]
 struct Cli {
@@ -15,6 +19,14 @@ struct Cli {
     #[arg(long, global = true)]
     json: bool,
 
+    /// Calibration profile from the config file [default: the config's `profile`]
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Ask the server for presets and schemas instead of using the cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+
     #[command(subcommand)]
     cmd: Cmd,
 }
@@ -39,7 +51,7 @@ enum Cmd {
     Stop,
     /// Capture audio and stream its spectrum to the music visualizer
     #[cfg(feature = "music")]
-    Music(commands::music::MusicArgs),
+    Music(Box<commands::music::MusicArgs>),
     /// Capture the screen and stream edge colors to the ambilight preset
     #[cfg(feature = "ambilight")]
     Ambilight(commands::ambilight::AmbilightArgs),
@@ -48,6 +60,27 @@ enum Cmd {
     /// Color controls
     #[command(subcommand)]
     Color(ColorCmd),
+    /// Set the strip to white at a color temperature (e.g. 3200K)
+    White(commands::white::WhiteArgs),
+    /// Interactively build a calibration profile for the strip
+    Calibrate(commands::calibrate::CalibrateArgs),
+    /// Full-screen dashboard: presets, color, brightness and live streams
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Control a running lightwaved
+    #[cfg(unix)]
+    Daemon(commands::daemon::DaemonArgs),
+    /// Manage the cached preset lists and schemas
+    #[command(subcommand)]
+    Cache(CacheCmd),
+    /// Print a completion script (bash, zsh, fish or elvish)
+    Completions { shell: commands::completions::Shell },
+    /// Candidates for the word being completed; called by the scripts
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -58,8 +91,21 @@ enum ColorCmd {
     Clear,
 }
 
+#[derive(Subcommand)]
+enum CacheCmd {
+    /// Delete cached responses for every server
+    Clear,
+}
+
 fn main() -> Result<()> {
-    let Cli { server, json, cmd } = Cli::parse();
+    let Cli {
+        server,
+        json,
+        profile,
+        no_cache,
+        cmd,
+    } = Cli::parse();
+    let profile = profile.as_deref();
 
     let base = server
         .or_else(|| std::env::var("LIGHTWAVE_URL").ok())
@@ -68,29 +114,65 @@ fn main() -> Result<()> {
     let result = (|| -> Result<()> {
         let client = api::Client::new(&base)
             .with_context(|| format!("initializing LightWave client for {base}"))?;
+        let cache = if no_cache {
+            Cache::disabled(client.clone())
+        } else {
+            Cache::new(client.clone(), cache::DEFAULT_MAX_AGE)
+        };
 
         match cmd {
-            Cmd::Presets => commands::presets::list(&client, json),
-            Cmd::Info { preset } => commands::presets::info(&client, &preset, json),
+            Cmd::Presets => commands::presets::list(&cache, json),
+            Cmd::Info { preset } => commands::presets::info(&cache, &preset, json),
             Cmd::Running => commands::presets::running(&client, json),
-            Cmd::Start { preset, rest } => commands::start::run(&client, &preset, &rest, json),
+            Cmd::Start { preset, rest } => {
+                commands::start::run(&client, &cache, &preset, &rest, json)
+            }
             Cmd::Stop => commands::stop::run(&client, json),
             #[cfg(feature = "music")]
             Cmd::Music(args) => commands::music::run(&client, &args, json),
             #[cfg(feature = "ambilight")]
-            Cmd::Ambilight(args) => commands::ambilight::run(&client, &args, json),
+            Cmd::Ambilight(args) => commands::ambilight::run(&client, &args, profile, json),
             Cmd::Brightness { level } => commands::leds::brightness(&client, level, json),
-            Cmd::Color(ColorCmd::Set { color }) => commands::leds::set(&client, &color, json),
+            Cmd::Color(ColorCmd::Set { color }) => {
+                commands::leds::set(&client, &color, profile, json)
+            }
             Cmd::Color(ColorCmd::Clear) => commands::leds::clear(&client, json),
+            Cmd::White(args) => commands::white::run(&client, &args, profile, json),
+            Cmd::Calibrate(args) => commands::calibrate::run(&client, &args, profile, json),
+            #[cfg(feature = "tui")]
+            Cmd::Tui => commands::tui::run(&client, profile, json),
+            #[cfg(unix)]
+            Cmd::Daemon(args) => commands::daemon::run(&args, json),
+            Cmd::Cache(CacheCmd::Clear) => commands::presets::clear_cache(json),
+            Cmd::Completions { shell } => commands::completions::print(shell, &mut Cli::command()),
+            Cmd::Complete { words } => commands::completions::complete(&base, &words),
         }
     })();
 
     if let Err(err) = result {
+        // A distinct status lets supervisors tell a dead stream target
+        // apart from other failures.
+        let unreachable = err.downcast_ref::<Unreachable>().is_some();
+
         if json {
+            if unreachable {
+                commands::print_json(&serde_json::json!({
+                    "ok": false,
+                    "error": "unreachable",
+                    "detail": format!("{err:#}"),
+                }))?;
+                std::process::exit(EXIT_UNREACHABLE);
+            }
+
             commands::print_error_json(format!("{err:#}"))?;
             std::process::exit(1);
         }
 
+        if unreachable {
+            eprintln!("Error: {err:?}");
+            std::process::exit(EXIT_UNREACHABLE);
+        }
+
         return Err(err);
     }
 
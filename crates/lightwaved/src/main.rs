@@ -0,0 +1,120 @@
+//! `lightwaved`: a long-lived process owning the HTTP client and one
+//! streamer at a time, steered over a Unix domain socket (see
+//! `lightwave_core::daemon`) by `lightwave daemon` or anything else that
+//! speaks the protocol. Capture stays open while a stream is stopped, so
+//! restarting it doesn't re-open the audio device or the screencast.
+
+#[cfg(not(unix))]
+compile_error!("lightwaved needs Unix domain sockets");
+
+#[cfg(any(feature = "music", feature = "ambilight"))]
+mod options;
+mod server;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use lightwave_core::{
+    api,
+    daemon::socket_path,
+    stream::{Signal, Signals, StopReason},
+};
+
+use server::Daemon;
+
+#[derive(Parser)]
+#[command(
+    name = "lightwaved",
+    version,
+    about = "LightWave streaming daemon, controlled over a Unix socket"
+)]
+struct Cli {
+    /// Server base URL (overrides LIGHTWAVE_URL)
+    #[arg(long)]
+    server: Option<String>,
+
+    /// Control socket [default: LIGHTWAVE_SOCKET, else
+    /// $XDG_RUNTIME_DIR/lightwave.sock]
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Calibration profile for ambilight [default: the config's `profile`]
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let Cli {
+        server,
+        socket,
+        profile,
+    } = Cli::parse();
+
+    let base = server
+        .or_else(|| std::env::var("LIGHTWAVE_URL").ok())
+        .unwrap_or_else(|| "http://localhost:8080".to_string());
+    let client = api::Client::new(&base)
+        .with_context(|| format!("initializing LightWave client for {base}"))?;
+
+    let socket = socket.unwrap_or_else(socket_path);
+    let listener = bind(&socket)?;
+    eprintln!("lightwaved: listening on {}", socket.display());
+
+    let daemon = Arc::new(Daemon::new(client, profile, socket));
+    std::thread::spawn({
+        let daemon = Arc::clone(&daemon);
+        move || daemon.serve(listener)
+    });
+
+    let reason = wait_for_stop()?;
+    eprintln!("lightwaved: shutting down ({reason})");
+    daemon.shutdown();
+
+    Ok(())
+}
+
+/// Bind the control socket, replacing a stale one left by a crash but
+/// refusing to steal it from a running daemon.
+fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            bail!("lightwaved is already running on {}", path.display());
+        }
+
+        fs::remove_file(path)
+            .with_context(|| format!("removing stale socket {}", path.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("binding control socket {}", path.display()))?;
+
+    // Whoever can connect can drive the lights; keep it to this user.
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("restricting access to {}", path.display()))?;
+
+    Ok(listener)
+}
+
+/// Block until SIGINT or SIGTERM. SIGHUP is left to the running stream,
+/// which re-reads the config file.
+fn wait_for_stop() -> Result<StopReason> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("building tokio runtime")?;
+
+    runtime.block_on(async {
+        let mut signals = Signals::new()?;
+
+        loop {
+            if let Signal::Stop(reason) = signals.recv().await {
+                return Ok(reason);
+            }
+        }
+    })
+}
@@ -0,0 +1,273 @@
+//! Stream settings as sent in a `start` request: the `lightwave music` /
+//! `lightwave ambilight` flags by name (underscores for dashes), with the
+//! same defaults.
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use serde_json::{Map, Value};
+
+use lightwave_core::net::{Encoding, PacketFormat, Protocol, SendOptions, UdpOptions};
+
+/// Options shared by both streams; everything else belongs to the stream.
+const OUTPUT_KEYS: &[&str] = &[
+    "protocol",
+    "host",
+    "port",
+    "universe",
+    "priority",
+    "multicast_ttl",
+    "multicast_interface",
+    "multicast_loop",
+    "packet_format",
+    "encoding",
+    "preset",
+    "no_start",
+];
+
+/// How often a running stream refreshes the stats `status` reports.
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Refused sends in a row before the daemon logs that nothing is listening.
+pub const UNREACHABLE_AFTER: u64 = 30;
+
+/// Split `options` into output and stream settings, rejecting unknown keys.
+pub fn split<T: DeserializeOwned>(options: &Map<String, Value>) -> Result<(Output, T)> {
+    let (output, stream): (Map<_, _>, Map<_, _>) = options
+        .clone()
+        .into_iter()
+        .partition(|(key, _)| OUTPUT_KEYS.contains(&key.as_str()));
+
+    Ok((
+        serde_json::from_value(Value::Object(output)).context("invalid output option")?,
+        serde_json::from_value(Value::Object(stream)).context("invalid stream option")?,
+    ))
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    #[serde(deserialize_with = "parsed")]
+    pub protocol: Protocol,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub universe: Option<u16>,
+    pub priority: u8,
+    pub multicast_ttl: u32,
+    pub multicast_interface: Option<String>,
+    pub multicast_loop: bool,
+    #[serde(deserialize_with = "parsed")]
+    pub packet_format: PacketFormat,
+    #[serde(deserialize_with = "parsed")]
+    pub encoding: Encoding,
+    /// Preset on the server [default: the stream's visualizer preset].
+    pub preset: Option<String>,
+    pub no_start: bool,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        let udp = UdpOptions::default();
+
+        Self {
+            protocol: Protocol::default(),
+            host: None,
+            port: None,
+            universe: None,
+            priority: SendOptions::default().priority,
+            multicast_ttl: udp.multicast_ttl,
+            multicast_interface: udp.multicast_interface,
+            multicast_loop: udp.multicast_loop,
+            packet_format: PacketFormat::default(),
+            encoding: Encoding::default(),
+            preset: None,
+            no_start: false,
+        }
+    }
+}
+
+impl Output {
+    /// The UDP port to stream to, given the stream's own default.
+    pub fn port(&self, default: u16) -> u16 {
+        self.port
+            .or(self.protocol.default_port())
+            .unwrap_or(default)
+    }
+
+    /// Whether the daemon starts and stops a preset for this stream; other
+    /// protocols feed a controller directly.
+    pub fn manages_preset(&self) -> bool {
+        !self.no_start && self.protocol == Protocol::LightWave
+    }
+
+    pub fn send_options(&self) -> SendOptions {
+        SendOptions {
+            protocol: self.protocol,
+            format: self.packet_format,
+            encoding: self.encoding,
+            universe: self.universe,
+            priority: self.priority,
+            udp: UdpOptions {
+                multicast_ttl: self.multicast_ttl,
+                multicast_interface: self.multicast_interface.clone(),
+                multicast_loop: self.multicast_loop,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "music")]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Music {
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub fft_size: usize,
+    #[serde(deserialize_with = "parsed")]
+    pub channels: lightwave_music::Channels,
+    #[serde(deserialize_with = "parsed")]
+    pub window: lightwave_music::Window,
+    pub hop: Option<usize>,
+    pub bins: usize,
+    pub gain: f32,
+    #[serde(deserialize_with = "parsed")]
+    pub weighting: lightwave_music::Weighting,
+    pub tilt: f32,
+    /// Ignore the EQ curve in the daemon's config file.
+    pub no_eq: bool,
+    #[serde(deserialize_with = "parsed")]
+    pub scale: lightwave_music::Scale,
+    pub floor_db: f32,
+    pub ceiling_db: f32,
+    #[serde(deserialize_with = "parsed")]
+    pub agc: lightwave_music::AgcMode,
+    pub agc_target: f32,
+    pub agc_max_gain: f32,
+    pub fps: u32,
+    pub min_freq: f32,
+    pub max_freq: f32,
+    #[serde(deserialize_with = "parsed")]
+    pub scale_type: lightwave_music::FreqScale,
+    pub octave_fraction: u32,
+    #[serde(deserialize_with = "parsed")]
+    pub reduction: lightwave_music::Reduction,
+    /// Milliseconds, as on the command line.
+    pub attack: f64,
+    pub release: f64,
+    pub peak_hold: f64,
+    pub gate: Option<f32>,
+    pub gate_hysteresis: f32,
+    /// Seconds, as on the command line; 0 = never.
+    pub silence_timeout: f64,
+    #[serde(deserialize_with = "parsed")]
+    pub on_silence: lightwave_music::OnSilence,
+    /// Beats are only sent as packets; the daemon has nowhere to report
+    /// beat events.
+    pub beats: bool,
+    pub beat_port: Option<u16>,
+}
+
+#[cfg(feature = "music")]
+impl Default for Music {
+    fn default() -> Self {
+        Self {
+            device: None,
+            sample_rate: None,
+            fft_size: 2048,
+            channels: lightwave_music::Channels::Mono,
+            window: lightwave_music::Window::Hann,
+            hop: None,
+            bins: 32,
+            gain: 4.0,
+            weighting: lightwave_music::Weighting::None,
+            tilt: 0.0,
+            no_eq: false,
+            scale: lightwave_music::Scale::Linear,
+            floor_db: -60.0,
+            ceiling_db: 0.0,
+            agc: lightwave_music::AgcMode::Off,
+            agc_target: 0.8,
+            agc_max_gain: 10.0,
+            fps: 60,
+            min_freq: 40.0,
+            max_freq: 16000.0,
+            scale_type: lightwave_music::FreqScale::Log,
+            octave_fraction: 3,
+            reduction: lightwave_music::Reduction::Peak,
+            attack: 0.0,
+            release: 0.0,
+            peak_hold: 0.0,
+            gate: None,
+            gate_hysteresis: 6.0,
+            silence_timeout: 10.0,
+            on_silence: lightwave_music::OnSilence::Keep,
+            beats: false,
+            beat_port: None,
+        }
+    }
+}
+
+#[cfg(feature = "ambilight")]
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ambilight {
+    pub boxes: usize,
+    #[serde(deserialize_with = "parsed")]
+    pub edge: lightwave_ambilight::Edge,
+    pub depth: f32,
+    pub vividness: f32,
+    pub gamma: f32,
+    pub min_saturation: f32,
+    pub reverse: bool,
+    pub fps: u32,
+    pub reselect: bool,
+    /// Calibration profile [default: the daemon's `--profile`].
+    pub profile: Option<String>,
+}
+
+#[cfg(feature = "ambilight")]
+impl Default for Ambilight {
+    fn default() -> Self {
+        Self {
+            boxes: 16,
+            edge: lightwave_ambilight::Edge::Bottom,
+            depth: 0.2,
+            vividness: 1.0,
+            gamma: 2.2,
+            min_saturation: 0.0,
+            reverse: false,
+            fps: 30,
+            reselect: false,
+            profile: None,
+        }
+    }
+}
+
+/// A millisecond option as a [`Duration`].
+#[cfg(feature = "music")]
+pub fn millis(name: &str, ms: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(ms / 1000.0).map_err(|_| {
+        anyhow::anyhow!("{name} must be a finite number of milliseconds >= 0, got {ms}")
+    })
+}
+
+/// A seconds option as a [`Duration`].
+#[cfg(feature = "music")]
+pub fn seconds(name: &str, seconds: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        anyhow::anyhow!("{name} must be a finite number of seconds >= 0, got {seconds}")
+    })
+}
+
+/// Deserialize a string through the type's `FromStr`, as clap does.
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err: Display>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,516 @@
+//! Request handling: one streamer at a time, owned by the daemon and run
+//! on its own thread.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+#[cfg(any(feature = "music", feature = "ambilight"))]
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Map, Value, json};
+
+#[cfg(any(feature = "music", feature = "ambilight"))]
+use lightwave_core::stream::Event;
+use lightwave_core::{
+    Client,
+    daemon::{Request, StreamName},
+    stream::{Control, Stats, StopReason},
+    tuning::Tuning,
+};
+
+#[cfg(any(feature = "music", feature = "ambilight"))]
+use crate::options::{self, Output};
+
+pub struct Daemon {
+    client: Client,
+    /// Default calibration profile for ambilight.
+    #[cfg_attr(not(feature = "ambilight"), allow(dead_code))]
+    profile: Option<String>,
+    socket: PathBuf,
+    /// Held across a whole request, so requests are handled one at a time.
+    stream: Mutex<Option<Stream>>,
+    /// Why the last stream ended on its own, until the next start.
+    last_error: Mutex<Option<String>>,
+}
+
+struct Stream {
+    name: StreamName,
+    /// As requested, to tell a restart with the same settings from a new one.
+    options: Map<String, Value>,
+    target: String,
+    /// Preset started for the stream, with its args.
+    preset: Option<(String, Value)>,
+    paused: bool,
+    control: Control,
+    tuning: Tuning,
+    stats: Arc<Mutex<Option<Stats>>>,
+    thread: JoinHandle<Result<StopReason>>,
+}
+
+impl Daemon {
+    pub fn new(client: Client, profile: Option<String>, socket: PathBuf) -> Self {
+        Self {
+            client,
+            profile,
+            socket,
+            stream: Mutex::new(None),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    /// Accept connections until the process exits, one thread each.
+    pub fn serve(self: Arc<Self>, listener: UnixListener) {
+        for connection in listener.incoming() {
+            let Ok(connection) = connection else {
+                continue;
+            };
+
+            let daemon = Arc::clone(&self);
+            thread::spawn(move || {
+                if let Err(err) = daemon.converse(connection) {
+                    eprintln!("lightwaved: {err:#}");
+                }
+            });
+        }
+    }
+
+    /// Answer newline-delimited requests until the client hangs up.
+    fn converse(&self, connection: UnixStream) -> Result<()> {
+        let mut writer = connection.try_clone().context("cloning connection")?;
+
+        for line in BufReader::new(connection).lines() {
+            let line = line.context("reading request")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request = serde_json::from_str::<Request>(&line);
+            let shutdown = matches!(request, Ok(Request::Shutdown));
+
+            let response = match request {
+                Ok(request) => self.handle(request),
+                Err(err) => Err(anyhow!("invalid request: {err}")),
+            };
+            let response = match response {
+                Ok(Value::Object(mut fields)) => {
+                    fields.insert("ok".to_string(), Value::Bool(true));
+                    Value::Object(fields)
+                }
+                Ok(_) => json!({ "ok": true }),
+                Err(err) => json!({ "ok": false, "error": format!("{err:#}") }),
+            };
+
+            writeln!(writer, "{response}").context("writing response")?;
+
+            if shutdown {
+                std::process::exit(0);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, request: Request) -> Result<Value> {
+        match request {
+            Request::Status => self.status(),
+            Request::Start { stream, options } => self.start(stream, options),
+            Request::Stop { release } => self.stop(release),
+            Request::Set { param, value } => self.set(&param, value),
+            Request::Shutdown => {
+                self.shutdown();
+                Ok(json!({}))
+            }
+        }
+    }
+
+    fn status(&self) -> Result<Value> {
+        let mut current = self.stream.lock().unwrap();
+        self.reap(&mut current);
+
+        let last_error = self.last_error.lock().unwrap().clone();
+        let Some(stream) = current.as_ref() else {
+            return Ok(json!({ "state": "idle", "last_error": last_error }));
+        };
+
+        Ok(json!({
+            "stream": stream.name,
+            "state": if stream.paused { "paused" } else { "running" },
+            "preset": stream.preset.as_ref().map(|(name, _)| name),
+            "target": stream.target,
+            "tuning": stream.tuning.params(),
+            "stats": &*stream.stats.lock().unwrap(),
+            "last_error": last_error,
+        }))
+    }
+
+    fn start(&self, name: StreamName, options: Map<String, Value>) -> Result<Value> {
+        let mut current = self.stream.lock().unwrap();
+        self.reap(&mut current);
+
+        if let Some(stream) = current.as_mut()
+            && stream.name == name
+            && stream.options == options
+        {
+            // Same settings: keep the open capture, just carry on sending.
+            if stream.paused {
+                if let Some((preset, args)) = &stream.preset {
+                    self.client
+                        .start(preset, args)
+                        .with_context(|| format!("starting preset {preset}"))?;
+                }
+                stream.control.resume();
+                stream.paused = false;
+            }
+
+            return Ok(json!({ "stream": name, "resumed": true }));
+        }
+
+        if let Some(stream) = current.take() {
+            self.release(stream);
+        }
+        *self.last_error.lock().unwrap() = None;
+
+        let stream = self.launch(name, options)?;
+        if let Some((preset, args)) = &stream.preset
+            && let Err(err) = self.client.start(preset, args)
+        {
+            let preset = preset.clone();
+            self.release(stream);
+            return Err(err).with_context(|| format!("starting preset {preset}"));
+        }
+
+        let response = json!({
+            "stream": name,
+            "target": stream.target,
+            "resumed": false,
+        });
+        *current = Some(stream);
+
+        Ok(response)
+    }
+
+    fn stop(&self, release: bool) -> Result<Value> {
+        let mut current = self.stream.lock().unwrap();
+        self.reap(&mut current);
+
+        let Some(stream) = current.as_mut() else {
+            bail!("nothing is streaming");
+        };
+
+        if release {
+            self.release(current.take().unwrap());
+            return Ok(json!({ "state": "idle" }));
+        }
+
+        if !stream.paused {
+            stream.control.pause();
+            stream.paused = true;
+            if let Some((preset, _)) = &stream.preset {
+                self.stop_preset(preset);
+            }
+        }
+
+        Ok(json!({ "state": "paused" }))
+    }
+
+    fn set(&self, param: &str, value: f32) -> Result<Value> {
+        let mut current = self.stream.lock().unwrap();
+        self.reap(&mut current);
+
+        let Some(stream) = current.as_ref() else {
+            bail!("nothing is streaming");
+        };
+        stream.tuning.set(param, value)?;
+
+        Ok(json!({ "param": param, "value": value }))
+    }
+
+    /// End any stream and remove the socket.
+    pub fn shutdown(&self) {
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            self.release(stream);
+        }
+
+        let _ = std::fs::remove_file(&self.socket);
+    }
+
+    /// Drop a stream that ended on its own, remembering why.
+    fn reap(&self, current: &mut Option<Stream>) {
+        if !current
+            .as_ref()
+            .is_some_and(|stream| stream.thread.is_finished())
+        {
+            return;
+        }
+
+        let stream = current.take().unwrap();
+        let error = match stream.thread.join() {
+            Ok(Ok(reason)) => format!("{} stream ended ({reason})", stream.name),
+            Ok(Err(err)) => format!("{} stream failed: {err:#}", stream.name),
+            Err(_) => format!("{} stream panicked", stream.name),
+        };
+
+        if let Some((preset, _)) = &stream.preset
+            && !stream.paused
+        {
+            self.stop_preset(preset);
+        }
+
+        eprintln!("lightwaved: {error}");
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    /// Stop a stream's thread, closing its capture, and its preset.
+    fn release(&self, stream: Stream) {
+        stream.control.stop(StopReason::Interrupt);
+        let _ = stream.thread.join();
+
+        if let Some((preset, _)) = &stream.preset
+            && !stream.paused
+        {
+            self.stop_preset(preset);
+        }
+    }
+
+    /// Stop `preset` unless someone has since switched to another one.
+    fn stop_preset(&self, preset: &str) {
+        let ours = match self.client.running() {
+            Ok(running) => running.is_some_and(|running| running.name == preset),
+            Err(err) => {
+                eprintln!("lightwaved: checking the running preset: {err:#}");
+                return;
+            }
+        };
+
+        if ours && let Err(err) = self.client.stop() {
+            eprintln!("lightwaved: stopping preset {preset}: {err:#}");
+        }
+    }
+
+    #[cfg(not(any(feature = "music", feature = "ambilight")))]
+    fn launch(&self, name: StreamName, options: Map<String, Value>) -> Result<Stream> {
+        let _ = options;
+        bail!("this lightwaved was built without {name} support")
+    }
+
+    #[cfg(any(feature = "music", feature = "ambilight"))]
+    fn launch(&self, name: StreamName, options: Map<String, Value>) -> Result<Stream> {
+        let stats = Arc::new(Mutex::new(None));
+        let on_event = {
+            let stats = Arc::clone(&stats);
+            move |event| {
+                log(name, event, &stats);
+                Ok(())
+            }
+        };
+
+        let (output, port, ready, thread) = match name {
+            #[cfg(feature = "music")]
+            StreamName::Music => {
+                let (output, music) = options::split::<options::Music>(&options)?;
+                let port = output.port(5555);
+
+                let fallback = match &music.on_silence {
+                    lightwave_music::OnSilence::Preset(fallback) => {
+                        let (preset, args) = preset(name, &output, port).ok_or_else(|| {
+                            anyhow!("on_silence preset:<name> needs a preset the daemon starts")
+                        })?;
+                        Some(lightwave_music::Fallback::new(
+                            self.client.clone(),
+                            fallback,
+                            &preset,
+                            args,
+                        ))
+                    }
+                    _ => None,
+                };
+                let on_event = move |event: Event| {
+                    if let Some(fallback) = &fallback
+                        && let Err(err) = fallback.event(&event)
+                    {
+                        eprintln!("lightwaved: music: {err:#}");
+                    }
+                    on_event(event)
+                };
+
+                let config = lightwave_music::Config {
+                    input: lightwave_music::Input::Device(music.device),
+                    sample_rate: music.sample_rate,
+                    fft_size: music.fft_size,
+                    channels: music.channels,
+                    window: music.window,
+                    hop: music.hop,
+                    bins: music.bins,
+                    gain: music.gain,
+                    weighting: music.weighting,
+                    tilt: music.tilt,
+                    equalize: !music.no_eq,
+                    scale: music.scale,
+                    floor_db: music.floor_db,
+                    ceiling_db: music.ceiling_db,
+                    agc: music.agc,
+                    agc_target: music.agc_target,
+                    agc_max_gain: music.agc_max_gain,
+                    min_freq: music.min_freq,
+                    max_freq: music.max_freq,
+                    scale_type: music.scale_type,
+                    octave_fraction: music.octave_fraction,
+                    reduction: music.reduction,
+                    attack: options::millis("attack", music.attack)?,
+                    release: options::millis("release", music.release)?,
+                    peak_hold: Some(options::millis("peak_hold", music.peak_hold)?)
+                        .filter(|hold| !hold.is_zero()),
+                    gate: music.gate,
+                    gate_hysteresis: music.gate_hysteresis,
+                    silence_timeout: Some(options::seconds(
+                        "silence_timeout",
+                        music.silence_timeout,
+                    )?)
+                    .filter(|timeout| !timeout.is_zero()),
+                    on_silence: music.on_silence,
+                    fps: music.fps,
+                    target: self.target(&output, port),
+                    output: output.send_options(),
+                    beats: music.beats,
+                    beat_target: music.beat_port.map(|port| self.target(&output, port)),
+                    stats_interval: Some(options::STATS_INTERVAL),
+                    unreachable_after: options::UNREACHABLE_AFTER,
+                    fail_on_unreachable: false,
+                };
+
+                let (ready, thread) = spawn(
+                    move || lightwave_music::Streamer::new(&config),
+                    |streamer| (streamer.control(), streamer.tuning()),
+                    |streamer| streamer.run(on_event),
+                );
+                (output, port, ready, thread)
+            }
+            #[cfg(feature = "ambilight")]
+            StreamName::Ambilight => {
+                let (output, ambilight) = options::split::<options::Ambilight>(&options)?;
+                let port = output.port(5556);
+                let config = lightwave_ambilight::Config {
+                    boxes: ambilight.boxes,
+                    edge: ambilight.edge,
+                    depth: ambilight.depth,
+                    vividness: ambilight.vividness,
+                    gamma: ambilight.gamma,
+                    min_saturation: ambilight.min_saturation,
+                    reverse: ambilight.reverse,
+                    profile: ambilight.profile.or_else(|| self.profile.clone()),
+                    fps: ambilight.fps,
+                    reselect: ambilight.reselect,
+                    target: self.target(&output, port),
+                    output: output.send_options(),
+                    stats_interval: Some(options::STATS_INTERVAL),
+                    unreachable_after: options::UNREACHABLE_AFTER,
+                    fail_on_unreachable: false,
+                };
+
+                let (ready, thread) = spawn(
+                    move || lightwave_ambilight::Streamer::new(&config),
+                    |streamer| (streamer.control(), streamer.tuning()),
+                    |streamer| streamer.run(on_event),
+                );
+                (output, port, ready, thread)
+            }
+            #[allow(unreachable_patterns)]
+            _ => bail!("this lightwaved was built without {name} support"),
+        };
+
+        // Blocks until capture is up, which for ambilight may mean waiting
+        // on the portal's screen picker.
+        let (control, tuning) = ready
+            .recv()
+            .map_err(|_| anyhow!("{name} stream thread died while starting"))??;
+
+        Ok(Stream {
+            name,
+            options,
+            target: self.target(&output, port),
+            preset: preset(name, &output, port),
+            paused: false,
+            control,
+            tuning,
+            stats,
+            thread,
+        })
+    }
+
+    #[cfg(any(feature = "music", feature = "ambilight"))]
+    fn target(&self, output: &Output, port: u16) -> String {
+        let host = output.host.as_deref().unwrap_or(self.client.host());
+        format!("{host}:{port}")
+    }
+}
+
+/// The preset started for a stream, with its args; None if the stream
+/// doesn't manage one.
+#[cfg(any(feature = "music", feature = "ambilight"))]
+fn preset(name: StreamName, output: &Output, port: u16) -> Option<(String, Value)> {
+    output.manages_preset().then(|| {
+        let default = match name {
+            StreamName::Music => "MusicVisualizer",
+            StreamName::Ambilight => "Ambilight",
+        };
+        let preset = output.preset.clone().unwrap_or_else(|| default.to_string());
+        (preset, json!({ "port": port }))
+    })
+}
+
+#[cfg(any(feature = "music", feature = "ambilight"))]
+type Ready = mpsc::Receiver<Result<(Control, Tuning)>>;
+
+/// Build a streamer on a thread of its own (capture handles aren't
+/// `Send`) and run it there, handing its control handles back once built.
+#[cfg(any(feature = "music", feature = "ambilight"))]
+fn spawn<S>(
+    build: impl FnOnce() -> Result<S> + Send + 'static,
+    handles: impl FnOnce(&S) -> (Control, Tuning) + Send + 'static,
+    run: impl FnOnce(S) -> Result<StopReason> + Send + 'static,
+) -> (Ready, JoinHandle<Result<StopReason>>) {
+    let (tx, rx) = mpsc::channel();
+
+    let thread = thread::spawn(move || {
+        let streamer = match build() {
+            Ok(streamer) => streamer,
+            Err(err) => {
+                let _ = tx.send(Err(err));
+                // Nobody joins a stream that failed to start.
+                return Ok(StopReason::Interrupt);
+            }
+        };
+
+        let _ = tx.send(Ok(handles(&streamer)));
+        run(streamer)
+    });
+
+    (rx, thread)
+}
+
+/// Keep the latest stats for `status` and log everything else.
+#[cfg(any(feature = "music", feature = "ambilight"))]
+fn log(name: StreamName, event: Event, stats: &Mutex<Option<Stats>>) {
+    match event {
+        Event::Stats(latest) => *stats.lock().unwrap() = Some(latest),
+        Event::Unreachable { target, refused } => {
+            eprintln!(
+                "lightwaved: {name}: nothing is listening on {target} ({refused} sends refused)"
+            )
+        }
+        Event::Reachable { target } => eprintln!("lightwaved: {name}: {target} is reachable again"),
+        Event::Reloaded => eprintln!("lightwaved: {name}: reloaded config"),
+        Event::ReloadFailed { error } => {
+            eprintln!("lightwaved: {name}: reloading config failed: {error}")
+        }
+        Event::Silence => eprintln!("lightwaved: {name}: silence, idling"),
+        Event::Resumed => eprintln!("lightwaved: {name}: sound again"),
+        Event::Preempted { .. }
+        | Event::Reclaimed { .. }
+        | Event::Tuned { .. }
+        | Event::Beat { .. } => {}
+    }
+}